@@ -0,0 +1,421 @@
+//! Evaluation of arithmetic expressions as in `$(( ))`, with the operators
+//! and precedence of C on 64-bit integers.
+
+use crate::variables::Variables;
+
+/// How deep the values of variables, which are expressions themselves, are
+/// evaluated before giving up, e.g. when `a` is `b` and `b` is `a`.
+const MAX_DEPTH: usize = 32;
+
+/// The operators, longest first so that e.g. `<<=` is not read as `<`.
+const OPERATORS: &[&str] = &[
+    "<<=", ">>=", "**", "++", "--", "<<", ">>", "<=", ">=", "==", "!=", "&&", "||", "+=", "-=",
+    "*=", "/=", "%=", "&=", "^=", "|=", "+", "-", "*", "/", "%", "<", ">", "=", "!", "~", "&", "^",
+    "|", "?", ":", ",", "(", ")",
+];
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Token {
+    Number(i64),
+    Name(String),
+    Operator(&'static str),
+}
+
+/// Evaluates `expr`. Variables which are unset or empty are 0.
+pub fn evaluate(expr: &str, variables: &mut Variables) -> Result<i64, String> {
+    evaluate_at_depth(expr, variables, 0)
+}
+
+fn evaluate_at_depth(expr: &str, variables: &mut Variables, depth: usize) -> Result<i64, String> {
+    if depth > MAX_DEPTH {
+        return Err(format!(
+            "{}: expression recursion level exceeded",
+            expr.trim()
+        ));
+    }
+    let tokens = tokenize(expr)?;
+    if tokens.is_empty() {
+        return Ok(0);
+    }
+    let mut evaluator = Evaluator {
+        expr,
+        tokens,
+        pos: 0,
+        variables,
+        depth,
+    };
+    let value = evaluator.comma(false)?;
+    match evaluator.tokens.get(evaluator.pos) {
+        None => Ok(value),
+        Some(_) => Err(evaluator.syntax_error()),
+    }
+}
+
+fn tokenize(expr: &str) -> Result<Vec<Token>, String> {
+    let mut tokens = Vec::new();
+    let mut rest = expr.trim_start();
+    while let Some(ch) = rest.chars().next() {
+        let len = if ch.is_ascii_digit() {
+            let len = rest
+                .find(|ch: char| !ch.is_ascii_alphanumeric())
+                .unwrap_or(rest.len());
+            tokens.push(Token::Number(parse_number(&rest[..len])?));
+            len
+        } else if ch == '_' || ch.is_ascii_alphabetic() || ch == '$' {
+            // `$name` is the same as `name`.
+            let start = if ch == '$' { 1 } else { 0 };
+            let len = rest[start..]
+                .find(|ch: char| ch != '_' && !ch.is_ascii_alphanumeric())
+                .map_or(rest.len(), |len| start + len);
+            if len == start {
+                return Err(format!("{}: syntax error: operand expected", expr.trim()));
+            }
+            tokens.push(Token::Name(rest[start..len].to_owned()));
+            len
+        } else {
+            match OPERATORS.iter().find(|op| rest.starts_with(**op)) {
+                Some(op) => {
+                    tokens.push(Token::Operator(op));
+                    op.len()
+                }
+                None => {
+                    return Err(format!(
+                        "{}: syntax error: invalid arithmetic operator (error token is \"{}\")",
+                        expr.trim(),
+                        rest.trim_end()
+                    ))
+                }
+            }
+        };
+        rest = rest[len..].trim_start();
+    }
+    Ok(tokens)
+}
+
+/// Parses a decimal number, a hexadecimal one such as `0x1f`, or an octal
+/// one such as `017`.
+fn parse_number(s: &str) -> Result<i64, String> {
+    let (digits, radix) = if let Some(hex) = s.strip_prefix("0x").or(s.strip_prefix("0X")) {
+        (hex, 16)
+    } else if s.len() > 1 && s.starts_with('0') {
+        (&s[1..], 8)
+    } else {
+        (s, 10)
+    };
+    u64::from_str_radix(digits, radix)
+        .map(|n| n as i64)
+        .map_err(|_| format!("{}: value too great for base", s))
+}
+
+/// Evaluates the tokens while parsing them. When `skip` is set, e.g. on
+/// the right of `&&` after a false condition, the tokens are only parsed
+/// and nothing is assigned.
+struct Evaluator<'a> {
+    expr: &'a str,
+    tokens: Vec<Token>,
+    pos: usize,
+    variables: &'a mut Variables,
+    depth: usize,
+}
+
+impl Evaluator<'_> {
+    fn syntax_error(&self) -> String {
+        let rest = match self.tokens.get(self.pos) {
+            Some(_) => self.tokens[self.pos..]
+                .iter()
+                .map(|token| match token {
+                    Token::Number(n) => n.to_string(),
+                    Token::Name(name) => name.clone(),
+                    Token::Operator(op) => op.to_string(),
+                })
+                .collect::<Vec<_>>()
+                .join(" "),
+            None => return format!("{}: syntax error: operand expected", self.expr.trim()),
+        };
+        format!(
+            "{}: syntax error in expression (error token is \"{}\")",
+            self.expr.trim(),
+            rest
+        )
+    }
+
+    fn peek_operator(&self) -> Option<&'static str> {
+        match self.tokens.get(self.pos) {
+            Some(Token::Operator(op)) => Some(op),
+            _ => None,
+        }
+    }
+
+    fn expect(&mut self, op: &str) -> Result<(), String> {
+        if self.peek_operator() == Some(op) {
+            self.pos += 1;
+            Ok(())
+        } else {
+            Err(self.syntax_error())
+        }
+    }
+
+    fn comma(&mut self, skip: bool) -> Result<i64, String> {
+        let mut value = self.assignment(skip)?;
+        while self.peek_operator() == Some(",") {
+            self.pos += 1;
+            value = self.assignment(skip)?;
+        }
+        Ok(value)
+    }
+
+    fn assignment(&mut self, skip: bool) -> Result<i64, String> {
+        if let (Some(Token::Name(name)), Some(Token::Operator(op))) =
+            (self.tokens.get(self.pos), self.tokens.get(self.pos + 1))
+        {
+            let op = *op;
+            if op.ends_with('=') && !matches!(op, "==" | "!=" | "<=" | ">=") {
+                let name = name.clone();
+                self.pos += 2;
+                let right = self.assignment(skip)?;
+                let value = match op {
+                    "=" => right,
+                    op => {
+                        let left = self.variable(&name)?;
+                        apply(&op[..op.len() - 1], left, right, skip)?
+                    }
+                };
+                if !skip {
+                    self.variables.set(&name, value.to_string());
+                }
+                return Ok(value);
+            }
+        }
+        self.conditional(skip)
+    }
+
+    fn conditional(&mut self, skip: bool) -> Result<i64, String> {
+        let condition = self.binary(1, skip)?;
+        if self.peek_operator() != Some("?") {
+            return Ok(condition);
+        }
+        self.pos += 1;
+        let then = self.comma(skip || condition == 0)?;
+        self.expect(":")?;
+        let otherwise = self.conditional(skip || condition != 0)?;
+        Ok(if condition != 0 { then } else { otherwise })
+    }
+
+    /// Parses binary operators whose precedence is at least `min`.
+    fn binary(&mut self, min: u8, skip: bool) -> Result<i64, String> {
+        let mut left = self.unary(skip)?;
+        while let Some(op) = self.peek_operator() {
+            let precedence = match precedence(op) {
+                Some(precedence) if precedence >= min => precedence,
+                _ => break,
+            };
+            self.pos += 1;
+            left = match op {
+                "&&" => {
+                    let right = self.binary(precedence + 1, skip || left == 0)?;
+                    (left != 0 && right != 0) as i64
+                }
+                "||" => {
+                    let right = self.binary(precedence + 1, skip || left != 0)?;
+                    (left != 0 || right != 0) as i64
+                }
+                // `**` is right-associative.
+                "**" => {
+                    let right = self.binary(precedence, skip)?;
+                    apply(op, left, right, skip)?
+                }
+                op => {
+                    let right = self.binary(precedence + 1, skip)?;
+                    apply(op, left, right, skip)?
+                }
+            };
+        }
+        Ok(left)
+    }
+
+    fn unary(&mut self, skip: bool) -> Result<i64, String> {
+        match self.peek_operator() {
+            Some(op @ ("++" | "--")) => {
+                self.pos += 1;
+                let name = match self.tokens.get(self.pos) {
+                    Some(Token::Name(name)) => name.clone(),
+                    _ => return Err(self.syntax_error()),
+                };
+                self.pos += 1;
+                let value = self.variable(&name)?.wrapping_add(step(op));
+                if !skip {
+                    self.variables.set(&name, value.to_string());
+                }
+                Ok(value)
+            }
+            Some(op @ ("+" | "-" | "!" | "~")) => {
+                self.pos += 1;
+                let value = self.unary(skip)?;
+                Ok(match op {
+                    "+" => value,
+                    "-" => value.wrapping_neg(),
+                    "!" => (value == 0) as i64,
+                    _ => !value,
+                })
+            }
+            _ => self.postfix(skip),
+        }
+    }
+
+    fn postfix(&mut self, skip: bool) -> Result<i64, String> {
+        let token = match self.tokens.get(self.pos) {
+            Some(token) => token.clone(),
+            None => return Err(self.syntax_error()),
+        };
+        self.pos += 1;
+        match token {
+            Token::Number(n) => Ok(n),
+            Token::Name(name) => {
+                let value = self.variable(&name)?;
+                if let Some(op @ ("++" | "--")) = self.peek_operator() {
+                    self.pos += 1;
+                    if !skip {
+                        self.variables
+                            .set(&name, value.wrapping_add(step(op)).to_string());
+                    }
+                }
+                Ok(value)
+            }
+            Token::Operator("(") => {
+                let value = self.comma(skip)?;
+                self.expect(")")?;
+                Ok(value)
+            }
+            Token::Operator(_) => {
+                self.pos -= 1;
+                Err(self.syntax_error())
+            }
+        }
+    }
+
+    /// Returns the value of the variable `name`, which is evaluated as an
+    /// expression itself.
+    fn variable(&mut self, name: &str) -> Result<i64, String> {
+        match self.variables.get(name) {
+            Some(value) if !value.trim().is_empty() => {
+                evaluate_at_depth(&value, self.variables, self.depth + 1)
+            }
+            _ => Ok(0),
+        }
+    }
+}
+
+fn precedence(op: &str) -> Option<u8> {
+    let precedence = match op {
+        "||" => 1,
+        "&&" => 2,
+        "|" => 3,
+        "^" => 4,
+        "&" => 5,
+        "==" | "!=" => 6,
+        "<" | "<=" | ">" | ">=" => 7,
+        "<<" | ">>" => 8,
+        "+" | "-" => 9,
+        "*" | "/" | "%" => 10,
+        "**" => 11,
+        _ => return None,
+    };
+    Some(precedence)
+}
+
+fn step(op: &str) -> i64 {
+    if op == "++" {
+        1
+    } else {
+        -1
+    }
+}
+
+/// Applies the binary operator `op`. Division by zero is an error unless
+/// the result is going to be thrown away anyway.
+fn apply(op: &str, left: i64, right: i64, skip: bool) -> Result<i64, String> {
+    let value = match op {
+        "+" => left.wrapping_add(right),
+        "-" => left.wrapping_sub(right),
+        "*" => left.wrapping_mul(right),
+        "/" | "%" if right == 0 => {
+            if skip {
+                return Ok(0);
+            }
+            return Err("division by 0".to_owned());
+        }
+        "/" => left.wrapping_div(right),
+        "%" => left.wrapping_rem(right),
+        "**" if right < 0 => {
+            if skip {
+                return Ok(0);
+            }
+            return Err("exponent less than 0".to_owned());
+        }
+        "**" => left.wrapping_pow(right.min(u32::MAX as i64) as u32),
+        "<<" => left.wrapping_shl(right as u32),
+        ">>" => left.wrapping_shr(right as u32),
+        "&" => left & right,
+        "^" => left ^ right,
+        "|" => left | right,
+        "==" => (left == right) as i64,
+        "!=" => (left != right) as i64,
+        "<" => (left < right) as i64,
+        "<=" => (left <= right) as i64,
+        ">" => (left > right) as i64,
+        ">=" => (left >= right) as i64,
+        _ => unreachable!("{} is not a binary operator", op),
+    };
+    Ok(value)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn eval(expr: &str) -> Result<i64, String> {
+        evaluate(expr, &mut Variables::new())
+    }
+
+    #[test]
+    fn precedence() {
+        assert_eq!(eval("1 + 2 * 3"), Ok(7));
+        assert_eq!(eval("(1 + 2) * 3"), Ok(9));
+        assert_eq!(eval("10 - 4 - 3"), Ok(3));
+        assert_eq!(eval("1 << 2 + 1"), Ok(8));
+        assert_eq!(eval("1 | 2 ^ 3 & 6"), Ok(1));
+        assert_eq!(eval("1 < 2 == 1"), Ok(1));
+        assert_eq!(eval("0 || 1 && 0"), Ok(0));
+        assert_eq!(eval("-2 * 3"), Ok(-6));
+        assert_eq!(eval("1 ? 2 : 3"), Ok(2));
+    }
+
+    #[test]
+    fn power() {
+        assert_eq!(eval("2 ** 10"), Ok(1024));
+        assert_eq!(eval("2 ** 3 ** 2"), Ok(512));
+        assert_eq!(eval("3 * 2 ** 2"), Ok(12));
+        assert_eq!(eval("2 ** -1"), Err("exponent less than 0".to_owned()));
+    }
+
+    #[test]
+    fn division_by_zero() {
+        assert_eq!(eval("7 / 2"), Ok(3));
+        assert_eq!(eval("7 % 3"), Ok(1));
+        assert_eq!(eval("1 / 0"), Err("division by 0".to_owned()));
+        assert_eq!(eval("1 % 0"), Err("division by 0".to_owned()));
+        assert_eq!(eval("0 && 1 / 0"), Ok(0));
+        assert_eq!(eval("1 ? 2 : 1 / 0"), Ok(2));
+    }
+
+    #[test]
+    fn variables() {
+        let mut variables = Variables::new();
+        variables.set("a", "3".to_owned());
+        variables.set("b", "a + 1".to_owned());
+        assert_eq!(evaluate("a * b", &mut variables), Ok(12));
+        assert_eq!(evaluate("unset + 1", &mut variables), Ok(1));
+        assert_eq!(evaluate("a += 2, a++", &mut variables), Ok(5));
+        assert_eq!(variables.get("a"), Some("6".to_owned()));
+    }
+}
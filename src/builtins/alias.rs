@@ -0,0 +1,66 @@
+use super::quote;
+use crate::{process::ExitStatus, shell::Shell};
+
+/// `alias NAME=VALUE ...` defines aliases, and `alias NAME ...` prints them.
+/// `alias` alone prints all of them in a form which can be read back.
+pub fn alias(shell: &mut Shell, argv: &[String]) -> ExitStatus {
+    if argv.len() == 1 {
+        for (name, value) in shell.aliases() {
+            print_alias(name, value);
+        }
+        return ExitStatus::ExitedWith(0);
+    }
+
+    let mut status = ExitStatus::ExitedWith(0);
+    for arg in &argv[1..] {
+        match arg.split_once('=') {
+            Some((name, value)) if is_alias_name(name) => {
+                shell
+                    .aliases_mut()
+                    .insert(name.to_owned(), value.to_owned());
+            }
+            Some((name, _)) => {
+                eprintln!("toysh: alias: `{}`: invalid alias name", name);
+                status = ExitStatus::ExitedWith(1);
+            }
+            None => match shell.aliases().get(arg) {
+                Some(value) => print_alias(arg, value),
+                None => {
+                    eprintln!("toysh: alias: {}: not found", arg);
+                    status = ExitStatus::ExitedWith(1);
+                }
+            },
+        }
+    }
+    status
+}
+
+/// `unalias NAME ...` removes aliases, and `unalias -a` all of them.
+pub fn unalias(shell: &mut Shell, argv: &[String]) -> ExitStatus {
+    if argv.len() == 1 {
+        eprintln!("toysh: unalias: usage: unalias [-a] name [name ...]");
+        return ExitStatus::ExitedWith(2);
+    }
+
+    let mut status = ExitStatus::ExitedWith(0);
+    for arg in &argv[1..] {
+        if arg == "-a" {
+            shell.aliases_mut().clear();
+        } else if shell.aliases_mut().remove(arg).is_none() {
+            eprintln!("toysh: unalias: {}: not found", arg);
+            status = ExitStatus::ExitedWith(1);
+        }
+    }
+    status
+}
+
+fn print_alias(name: &str, value: &str) {
+    println!("alias {}={}", name, quote(value));
+}
+
+/// Whether `name` can be an alias, which it can't if it would be split or
+/// expanded like other words.
+fn is_alias_name(name: &str) -> bool {
+    !name.is_empty()
+        && !name.contains(|c: char| c.is_whitespace() || "/$`\\'\"|&;<>()=".contains(c))
+}
@@ -0,0 +1,143 @@
+use crate::{
+    keymap::{format_key_sequence, parse_key_sequence, EditCommand, Key, Keymap},
+    process::ExitStatus,
+    shell::Shell,
+};
+
+/// `bind [-m KEYMAP] [-l] [-p] [-r KEYSEQ] ["KEYSEQ": COMMAND ...]`
+///
+/// - `-m KEYMAP` selects the keymap (`emacs`, `vi-insert`, or `vi-command`)
+///   the options after it operate on.
+/// - `-l` lists the names of all editing commands.
+/// - `-p` prints the bindings in a form which can be read back by `bind`.
+/// - `-r KEYSEQ` removes the binding for `KEYSEQ`.
+pub fn bind(shell: &mut Shell, argv: &[String]) -> ExitStatus {
+    match run(shell, &argv[1..]) {
+        Ok(()) => ExitStatus::ExitedWith(0),
+        Err(err) => {
+            eprintln!("toysh: bind: {}", err);
+            ExitStatus::ExitedWith(1)
+        }
+    }
+}
+
+fn run(shell: &mut Shell, args: &[String]) -> Result<(), String> {
+    let mut keymap_name = None;
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "-m" => {
+                let name = args.next().ok_or("-m: option requires an argument")?;
+                keymap_name = Some(name.as_str());
+            }
+            "-l" => {
+                for command in EditCommand::all() {
+                    println!("{}", command.name());
+                }
+            }
+            "-p" => print_bindings(keymap(shell, keymap_name)?),
+            "-r" => {
+                let seq = args.next().ok_or("-r: option requires an argument")?;
+                let keys = parse_key_sequence(seq)?;
+                keymap(shell, keymap_name)?.unbind(&keys);
+            }
+            binding => {
+                let (keys, command) = parse_binding(binding)?;
+                keymap(shell, keymap_name)?.bind(&keys, command);
+            }
+        }
+    }
+
+    Ok(())
+}
+
+fn keymap<'a>(shell: &'a mut Shell, name: Option<&str>) -> Result<&'a mut Keymap, String> {
+    let keymaps = shell.keymaps_mut();
+    match name {
+        Some(name) => keymaps
+            .by_name_mut(name)
+            .ok_or_else(|| format!("{}: invalid keymap name", name)),
+        None => Ok(keymaps.current_mut()),
+    }
+}
+
+/// Parses `"KEYSEQ": COMMAND`.
+fn parse_binding(binding: &str) -> Result<(Vec<Key>, EditCommand), String> {
+    let invalid = || format!("{}: invalid binding, expected \"KEYSEQ\": COMMAND", binding);
+    let rest = binding.trim().strip_prefix('"').ok_or_else(invalid)?;
+
+    // Find the closing quote, skipping escaped characters.
+    let mut end = None;
+    let mut escaped = false;
+    for (i, ch) in rest.char_indices() {
+        match ch {
+            _ if escaped => escaped = false,
+            '\\' => escaped = true,
+            '"' => {
+                end = Some(i);
+                break;
+            }
+            _ => (),
+        }
+    }
+    let end = end.ok_or_else(invalid)?;
+
+    let keys = parse_key_sequence(&rest[..end])?;
+    let name = rest[end + 1..]
+        .trim_start()
+        .strip_prefix(':')
+        .ok_or_else(invalid)?
+        .trim();
+    let command =
+        EditCommand::from_name(name).ok_or_else(|| format!("{}: unknown command name", name))?;
+    Ok((keys, command))
+}
+
+fn print_bindings(keymap: &Keymap) {
+    for (keys, command) in keymap.bindings() {
+        println!("\"{}\": {}", format_key_sequence(keys), command.name());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_bindings() {
+        assert_eq!(
+            parse_binding("\"\\C-x\\C-u\": undo"),
+            Ok((vec![Key::ctrl('x'), Key::ctrl('u')], EditCommand::Undo))
+        );
+        assert_eq!(
+            parse_binding(" \"\\\"\":beginning-of-line "),
+            Ok((vec![Key::plain('"')], EditCommand::BeginningOfLine))
+        );
+    }
+
+    #[test]
+    fn invalid_bindings() {
+        let invalid = |binding: &str| {
+            Err(format!(
+                "{}: invalid binding, expected \"KEYSEQ\": COMMAND",
+                binding
+            ))
+        };
+        for binding in [
+            "\\C-a: undo",
+            "\"\\C-a: undo",
+            "\"\\C-a\" undo",
+            "\"\\\": undo",
+        ] {
+            assert_eq!(parse_binding(binding), invalid(binding), "{}", binding);
+        }
+        assert_eq!(
+            parse_binding("\"\\C-a\": nope"),
+            Err("nope: unknown command name".to_owned())
+        );
+        assert_eq!(
+            parse_binding("\"<Nope>\": undo"),
+            Err("Nope: unknown key name".to_owned())
+        );
+    }
+}
@@ -0,0 +1,109 @@
+use super::quote;
+use crate::{completion::CompletionSpec, process::ExitStatus, shell::Shell};
+
+/// `complete [-pr] [-W WORDS] [-F FUNCTION] [-A hostname] [NAME ...]`
+///
+/// - `-W WORDS` completes the arguments of the commands `NAME` with the
+///   words in `WORDS`, separated by whitespace.
+/// - `-F FUNCTION` completes them with the lines printed by the shell
+///   function `FUNCTION`.
+/// - `-A hostname` completes them with the hosts known to ssh. `ping`, `scp`,
+///   `sftp` and `ssh` do this unless set otherwise.
+/// - `-r` removes the specs of `NAME`s, or all of them if none is given.
+/// - `-p`, or no options, prints the specs of `NAME`s, or all of them, in a
+///   form which can be read back.
+pub fn complete(shell: &mut Shell, argv: &[String]) -> ExitStatus {
+    match run(shell, &argv[1..]) {
+        Ok(()) => ExitStatus::ExitedWith(0),
+        Err(err) => {
+            eprintln!("toysh: complete: {}", err);
+            ExitStatus::ExitedWith(1)
+        }
+    }
+}
+
+fn run(shell: &mut Shell, args: &[String]) -> Result<(), String> {
+    let mut spec = None;
+    let mut remove = false;
+    let mut args = args.iter();
+    let names: Vec<&String> = loop {
+        let arg = match args.next() {
+            Some(arg) => arg,
+            None => break Vec::new(),
+        };
+        match arg.as_str() {
+            "-p" => (),
+            "-r" => remove = true,
+            "-W" => {
+                let words = args.next().ok_or("-W: option requires an argument")?;
+                let words = words.split_whitespace().map(str::to_owned).collect();
+                spec = Some(CompletionSpec::Words(words));
+            }
+            "-A" => {
+                let action = args.next().ok_or("-A: option requires an argument")?;
+                if action != "hostname" {
+                    return Err(format!("{}: invalid action name", action));
+                }
+                spec = Some(CompletionSpec::Hostnames);
+            }
+            "-F" => {
+                let function = args.next().ok_or("-F: option requires an argument")?;
+                spec = Some(CompletionSpec::Function(function.clone()));
+            }
+            "--" => break args.collect(),
+            option if option.starts_with('-') && option.len() > 1 => {
+                return Err(format!("{}: invalid option", option));
+            }
+            _ => break std::iter::once(arg).chain(args).collect(),
+        }
+    };
+
+    let completer = shell.completer_mut();
+    if remove {
+        if names.is_empty() {
+            completer.clear_specs();
+        }
+        for name in names {
+            if !completer.remove_spec(name) {
+                return Err(format!("{}: no completion specification", name));
+            }
+        }
+        return Ok(());
+    }
+
+    if let Some(spec) = spec {
+        if names.is_empty() {
+            return Err(
+                "usage: complete [-pr] [-W WORDS] [-F FUNCTION] [-A hostname] [NAME ...]"
+                    .to_owned(),
+            );
+        }
+        for name in names {
+            completer.set_spec(name, spec.clone());
+        }
+        return Ok(());
+    }
+
+    if names.is_empty() {
+        for (name, spec) in completer.specs() {
+            print_spec(name, spec);
+        }
+    }
+    for name in names {
+        let spec = completer
+            .spec(name)
+            .ok_or_else(|| format!("{}: no completion specification", name))?;
+        print_spec(name, spec);
+    }
+    Ok(())
+}
+
+fn print_spec(name: &str, spec: &CompletionSpec) {
+    match spec {
+        CompletionSpec::Words(words) => {
+            println!("complete -W {} {}", quote(&words.join(" ")), name)
+        }
+        CompletionSpec::Function(function) => println!("complete -F {} {}", function, name),
+        CompletionSpec::Hostnames => println!("complete -A hostname {}", name),
+    }
+}
@@ -0,0 +1,33 @@
+use crate::{
+    process::{self, ExitStatus},
+    shell::Shell,
+    signal,
+};
+
+/// `exec [command [arg ...]]` replaces the shell with the command found on
+/// `$PATH`, and only returns if it can't be run. Without a command, the
+/// redirections of `exec` stay in effect, e.g. `exec 3< file` keeps the file
+/// open as the descriptor 3 for the commands run afterwards.
+pub fn exec(shell: &mut Shell, argv: &[String]) -> ExitStatus {
+    let argv = match argv.get(1).map(String::as_str) {
+        Some("--") => &argv[2..],
+        _ => &argv[1..],
+    };
+    let name = match argv.first() {
+        Some(name) => name,
+        None => {
+            shell.keep_redirects();
+            return ExitStatus::ExitedWith(0);
+        }
+    };
+
+    match shell.command_paths_mut().find(name) {
+        Some(path) => {
+            signal::restore_defaults();
+            let status = process::exec(&path, argv);
+            signal::install();
+            status
+        }
+        None => shell.command_not_found(name),
+    }
+}
@@ -0,0 +1,174 @@
+use crate::{
+    history::History,
+    process::{self, ExitStatus},
+    shell::Shell,
+};
+
+/// The number of entries `fc -l` lists by default.
+const DEFAULT_LIST_COUNT: isize = 16;
+
+/// `fc [-e EDITOR] [-lnr] [FIRST [LAST]]` or `fc -s [OLD=NEW] [COMMAND]`
+///
+/// - Without `-l` or `-s`, opens the entries from `FIRST` to `LAST` (the
+///   previous command by default) in `EDITOR`, `$FCEDIT`, or `$EDITOR`, and
+///   runs the edited commands.
+/// - `-l` lists the entries instead, the last 16 by default. `-n` omits the
+///   numbers and `-r` reverses the order.
+/// - `-s` runs `COMMAND` again (the previous command by default), replacing
+///   each `OLD` in it with `NEW`.
+///
+/// `FIRST`, `LAST`, and `COMMAND` are an entry number, a negative offset
+/// from the current command, or a prefix of the command.
+pub fn fc(shell: &mut Shell, argv: &[String]) -> ExitStatus {
+    match run(shell, &argv[1..]) {
+        Ok(status) => status,
+        Err(err) => {
+            eprintln!("toysh: fc: {}", err);
+            ExitStatus::ExitedWith(1)
+        }
+    }
+}
+
+fn run(shell: &mut Shell, args: &[String]) -> Result<ExitStatus, String> {
+    let mut list = false;
+    let mut numbered = true;
+    let mut reverse = false;
+    let mut substitute = false;
+    let mut editor = None;
+    let mut args = args.iter().peekable();
+    while let Some(arg) = args.peek() {
+        // Negative numbers are entry offsets rather than options.
+        let is_option = arg.len() > 1
+            && arg.starts_with('-')
+            && !arg[1..].starts_with(|ch: char| ch.is_ascii_digit());
+        if !is_option {
+            break;
+        }
+        let arg = args.next().unwrap();
+        if arg == "--" {
+            break;
+        }
+        if arg == "-e" {
+            let name = args.next().ok_or("-e: option requires an argument")?;
+            if name == "-" {
+                substitute = true;
+            } else {
+                editor = Some(name.clone());
+            }
+            continue;
+        }
+        for option in arg[1..].chars() {
+            match option {
+                'l' => list = true,
+                'n' => numbered = false,
+                'r' => reverse = true,
+                's' => substitute = true,
+                _ => return Err(format!("-{}: invalid option", option)),
+            }
+        }
+    }
+    let operands: Vec<&String> = args.collect();
+
+    if substitute {
+        return substitute_and_run(shell, &operands);
+    }
+
+    let history = shell.history();
+    let end = current_end(history);
+    let (default_first, default_last) = if list {
+        (-DEFAULT_LIST_COUNT, -1)
+    } else {
+        (-1, -1)
+    };
+    let find = |spec: Option<&&String>, default: isize| match spec {
+        Some(spec) => history
+            .find(spec, end)
+            .ok_or_else(|| format!("{}: history specification out of range", spec)),
+        None => Ok(end
+            .saturating_sub(default.unsigned_abs())
+            .min(end.saturating_sub(1))),
+    };
+    if end == 0 {
+        return Err("no command found".to_owned());
+    }
+    let first = find(operands.first(), default_first)?;
+    let last = match operands.get(1) {
+        Some(_) => find(operands.get(1), default_last)?,
+        None if list => find(None, default_last)?,
+        None => first,
+    };
+
+    let mut indices: Vec<usize> = if first <= last {
+        (first..=last).collect()
+    } else {
+        (last..=first).rev().collect()
+    };
+    if reverse {
+        indices.reverse();
+    }
+
+    if list {
+        for index in indices {
+            let line = history.get(index).unwrap();
+            if numbered {
+                println!("{}\t {}", history.number(index), line);
+            } else {
+                println!("\t {}", line);
+            }
+        }
+        return Ok(ExitStatus::ExitedWith(0));
+    }
+
+    let mut text = String::new();
+    for index in indices {
+        text.push_str(history.get(index).unwrap());
+        text.push('\n');
+    }
+    let editor = editor.unwrap_or_else(|| process::editor_from_env(&["FCEDIT", "EDITOR"]));
+    let script = process::edit_text(&editor, &text)?;
+    Ok(run_again(shell, &script))
+}
+
+/// Runs `fc -s [OLD=NEW] [COMMAND]`.
+fn substitute_and_run(shell: &mut Shell, operands: &[&String]) -> Result<ExitStatus, String> {
+    let (replacement, command) = match operands {
+        [] => (None, None),
+        [operand] if operand.contains('=') => (operand.split_once('='), None),
+        [command] => (None, Some(command)),
+        [operand, command, ..] => (operand.split_once('='), Some(command)),
+    };
+
+    let history = shell.history();
+    let end = current_end(history);
+    let spec = command.map_or("-1", |command| command.as_str());
+    let line = history
+        .find(spec, end)
+        .and_then(|index| history.get(index))
+        .ok_or_else(|| format!("{}: no command found", spec))?;
+    let line = match replacement {
+        Some((old, new)) if !old.is_empty() => line.replace(old, new),
+        _ => line.to_owned(),
+    };
+    Ok(run_again(shell, &line))
+}
+
+/// Shows and runs `script`, recording it in the history.
+fn run_again(shell: &mut Shell, script: &str) -> ExitStatus {
+    let script = script.trim_end_matches('\n');
+    if script.trim().is_empty() {
+        return ExitStatus::ExitedWith(0);
+    }
+
+    println!("{}", script);
+    if let Err(err) = shell.history_mut().push(script) {
+        eprintln!("toysh: fc: {}", err);
+    }
+    shell.run_script(script)
+}
+
+/// The end of the entries `fc` refers to. The command line running `fc` has
+/// been recorded already unless the history ignores it, and is excluded
+/// along with what has been recorded since, e.g. by `fc -s`.
+fn current_end(history: &History) -> usize {
+    history.running_index().unwrap_or(history.len())
+}
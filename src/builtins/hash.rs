@@ -0,0 +1,55 @@
+use crate::{process::ExitStatus, shell::Shell};
+
+use super::builtin_command;
+
+/// `hash` prints the remembered paths of the commands which have been run
+/// and how many times they have been, `hash name ...` finds the commands on
+/// `$PATH` and remembers them, and `hash -r` forgets all of them so that new
+/// and removed ones are noticed right away.
+pub fn hash(shell: &mut Shell, argv: &[String]) -> ExitStatus {
+    let mut names = &argv[1..];
+    while let Some(arg) = names.first() {
+        match arg.as_str() {
+            "-r" => {
+                shell.command_paths_mut().clear();
+                shell.completer_mut().forget_commands();
+            }
+            "--" => {
+                names = &names[1..];
+                break;
+            }
+            arg if arg.starts_with('-') => {
+                eprintln!("toysh: hash: {}: invalid option", arg);
+                return ExitStatus::ExitedWith(2);
+            }
+            _ => break,
+        }
+        names = &names[1..];
+    }
+
+    if names.is_empty() && argv.len() == 1 {
+        let mut entries = shell.command_paths_mut().entries().peekable();
+        if entries.peek().is_none() {
+            println!("hash: hash table empty");
+        } else {
+            println!("hits\tcommand");
+            for (_, entry) in entries {
+                println!("{:4}\t{}", entry.hits, entry.path.display());
+            }
+        }
+        return ExitStatus::ExitedWith(0);
+    }
+
+    let mut status = ExitStatus::ExitedWith(0);
+    for name in names {
+        // Builtins, functions and paths are not looked up on `$PATH`.
+        if name.contains('/') || builtin_command(name).is_some() || shell.has_function(name) {
+            continue;
+        }
+        if !shell.command_paths_mut().add(name) {
+            eprintln!("toysh: hash: {}: not found", name);
+            status = ExitStatus::ExitedWith(1);
+        }
+    }
+    status
+}
@@ -0,0 +1,119 @@
+use crate::{process::ExitStatus, shell::Shell};
+
+/// `history [--here] [--failed] [-c] [-d N] [-r] [-w] [COUNT]`
+///
+/// - With no options, lists the entries with their numbers, or only the last
+///   `COUNT` ones. If `$HISTTIMEFORMAT` is set, it is used as a `strftime(3)`
+///   format to show when each entry was run.
+/// - `--here` lists only the commands run in the current directory, and
+///   `--failed` only the ones which exited with a non-zero status.
+/// - `-c` clears the history.
+/// - `-d N` deletes the entry numbered `N`.
+/// - `-r` reads the entries other shells have added to the history file.
+/// - `-w` writes the history to the history file, replacing its contents.
+pub fn history(shell: &mut Shell, argv: &[String]) -> ExitStatus {
+    match run(shell, &argv[1..]) {
+        Ok(()) => ExitStatus::ExitedWith(0),
+        Err(err) => {
+            eprintln!("toysh: history: {}", err);
+            ExitStatus::ExitedWith(1)
+        }
+    }
+}
+
+fn run(shell: &mut Shell, args: &[String]) -> Result<(), String> {
+    let history = shell.history_mut();
+    let mut list = true;
+    let mut count = None;
+    let mut here = None;
+    let mut failed = false;
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--here" => {
+                let cwd = std::env::current_dir().map_err(|err| err.to_string())?;
+                here = Some(cwd);
+            }
+            "--failed" => failed = true,
+            "-c" => {
+                history.clear();
+                list = false;
+            }
+            "-d" => {
+                let number = args.next().ok_or("-d: option requires an argument")?;
+                let removed = number
+                    .parse()
+                    .map(|number| history.remove(number))
+                    .unwrap_or(false);
+                if !removed {
+                    return Err(format!("{}: history position out of range", number));
+                }
+                list = false;
+            }
+            "-r" | "-w" => {
+                let result = if arg == "-r" {
+                    history.sync()
+                } else {
+                    history.write()
+                };
+                result.map_err(|err| match history.path() {
+                    Some(path) => format!("{}: {}", path.display(), err),
+                    None => err.to_string(),
+                })?;
+                list = false;
+            }
+            arg => {
+                let n = arg
+                    .parse::<usize>()
+                    .map_err(|_| format!("{}: numeric argument required", arg))?;
+                count = Some(n);
+            }
+        }
+    }
+
+    if list {
+        let time_format = std::env::var("HISTTIMEFORMAT").ok();
+        let indices: Vec<usize> = (0..history.len())
+            .filter(|&index| {
+                let entry = history.entry(index).unwrap();
+                here.as_ref()
+                    .is_none_or(|cwd| entry.cwd.as_ref() == Some(cwd))
+                    && (!failed || entry.status.is_some_and(|status| status != 0))
+            })
+            .collect();
+        let start = count.map_or(0, |count| indices.len().saturating_sub(count));
+        for &index in &indices[start..] {
+            let entry = history.entry(index).unwrap();
+            let time = match (&time_format, entry.timestamp) {
+                (Some(format), Some(timestamp)) => format_time(format, timestamp),
+                _ => String::new(),
+            };
+            println!("{:5}  {}{}", history.number(index), time, entry.line);
+        }
+    }
+    Ok(())
+}
+
+/// Formats `timestamp` in the local time zone with `strftime(3)`.
+fn format_time(format: &str, timestamp: i64) -> String {
+    let format = match std::ffi::CString::new(format) {
+        Ok(format) => format,
+        Err(_) => return String::new(),
+    };
+    let mut buf = [0u8; 256];
+    // SAFETY: `tm` is fully initialized by `localtime_r` before use, and
+    // `strftime` writes at most `buf.len()` bytes.
+    let len = unsafe {
+        let mut tm = std::mem::zeroed();
+        if libc::localtime_r(&(timestamp as libc::time_t), &mut tm).is_null() {
+            return String::new();
+        }
+        libc::strftime(
+            buf.as_mut_ptr() as *mut libc::c_char,
+            buf.len(),
+            format.as_ptr(),
+            &tm,
+        )
+    };
+    String::from_utf8_lossy(&buf[..len]).into_owned()
+}
@@ -0,0 +1,251 @@
+use crate::{
+    job::JobState,
+    process::{self, ExitStatus},
+    shell::Shell,
+};
+
+/// `jobs [-l | -p] [jobspec ...]` lists the jobs, or the ones given, with
+/// their states. `-l` adds their process group ids, and `-p` prints only
+/// those. The jobs which are done are forgotten once they are listed.
+pub fn jobs(shell: &mut Shell, argv: &[String]) -> ExitStatus {
+    let mut format = Format::Normal;
+    let mut specs = &argv[1..];
+    while let Some(arg) = specs.first() {
+        match arg.as_str() {
+            "-l" => format = Format::Long,
+            "-p" => format = Format::Pids,
+            "--" => {
+                specs = &specs[1..];
+                break;
+            }
+            arg if arg.starts_with('-') => {
+                eprintln!("toysh: jobs: {}: invalid option", arg);
+                return ExitStatus::ExitedWith(2);
+            }
+            _ => break,
+        }
+        specs = &specs[1..];
+    }
+
+    shell.update_jobs();
+    let mut status = ExitStatus::ExitedWith(0);
+    let ids: Vec<usize> = if specs.is_empty() {
+        shell.jobs().iter().map(|job| job.id).collect()
+    } else {
+        specs
+            .iter()
+            .filter_map(|spec| match shell.jobs().find(spec) {
+                Ok(job) => Some(job.id),
+                Err(err) => {
+                    eprintln!("toysh: jobs: {}", err);
+                    status = ExitStatus::ExitedWith(1);
+                    None
+                }
+            })
+            .collect()
+    };
+
+    for id in ids {
+        let jobs = shell.jobs();
+        let job = match jobs.get(id) {
+            Some(job) => job,
+            None => continue,
+        };
+        match format {
+            Format::Normal => println!("{}", jobs.describe(id).unwrap_or_default()),
+            Format::Long => println!(
+                "[{}]{} {} {:<24}{}",
+                id,
+                jobs.mark(id),
+                job.pgid,
+                job.state(),
+                job.command
+            ),
+            Format::Pids => println!("{}", job.pgid),
+        }
+        if job.state().status().is_some() {
+            shell.jobs_mut().retire(id);
+        }
+    }
+    status
+}
+
+enum Format {
+    Normal,
+    /// With the process group ids.
+    Long,
+    /// Only the process group ids.
+    Pids,
+}
+
+/// `fg [jobspec]` lets the job, or the current one, continue in the
+/// foreground and waits for it.
+pub fn fg(shell: &mut Shell, argv: &[String]) -> ExitStatus {
+    if argv.len() > 2 {
+        eprintln!("toysh: fg: usage: fg [jobspec]");
+        return ExitStatus::ExitedWith(2);
+    }
+    let id = match find(shell, "fg", argv.get(1)) {
+        Some(id) => id,
+        None => return ExitStatus::ExitedWith(1),
+    };
+    if let Some(job) = shell.jobs().get(id) {
+        println!("{}", job.command);
+    }
+    shell.resume_job(id, true)
+}
+
+/// `bg [jobspec ...]` lets the jobs, or the current one, continue in the
+/// background.
+pub fn bg(shell: &mut Shell, argv: &[String]) -> ExitStatus {
+    let specs: Vec<Option<&String>> = if argv.len() == 1 {
+        vec![None]
+    } else {
+        argv[1..].iter().map(Some).collect()
+    };
+    let mut status = ExitStatus::ExitedWith(0);
+    for spec in specs {
+        let id = match find(shell, "bg", spec) {
+            Some(id) => id,
+            None => {
+                status = ExitStatus::ExitedWith(1);
+                continue;
+            }
+        };
+        if let Some(job) = shell.jobs().get(id) {
+            println!("[{}] {} &", id, job.command);
+        }
+        if shell.resume_job(id, false) != ExitStatus::ExitedWith(0) {
+            status = ExitStatus::ExitedWith(1);
+        }
+    }
+    status
+}
+
+/// `wait [jobspec | pid ...]` waits for the jobs or the processes given, or
+/// for all the jobs if none are, and returns the status of the last one
+/// given. A job which is stopped is not waited for. The jobs which have
+/// finished are forgotten, as their statuses have been returned. The status
+/// of a process of a job which has been reported as done is still returned
+/// once for its pid, e.g. for `wait $!`.
+pub fn wait(shell: &mut Shell, argv: &[String]) -> ExitStatus {
+    shell.update_jobs();
+    if argv.len() == 1 {
+        let ids: Vec<usize> = shell.jobs().iter().map(|job| job.id).collect();
+        for id in ids {
+            wait_job(shell, id);
+        }
+        return ExitStatus::ExitedWith(0);
+    }
+
+    let mut status = ExitStatus::ExitedWith(0);
+    for arg in &argv[1..] {
+        status = if arg.starts_with('%') {
+            match shell.jobs().find(arg) {
+                Ok(job) => {
+                    let id = job.id;
+                    wait_job(shell, id)
+                }
+                Err(err) => {
+                    eprintln!("toysh: wait: {}", err);
+                    ExitStatus::ExitedWith(127)
+                }
+            }
+        } else {
+            match arg.parse() {
+                Ok(pid) => wait_pid(shell, pid),
+                Err(_) => {
+                    eprintln!("toysh: wait: `{}': not a pid or valid job spec", arg);
+                    ExitStatus::ExitedWith(2)
+                }
+            }
+        };
+    }
+    status
+}
+
+/// Waits for the job `id` to finish, and returns its status.
+fn wait_job(shell: &mut Shell, id: usize) -> ExitStatus {
+    if let Some(job) = shell.jobs().get(id) {
+        if job.state() != JobState::Stopped {
+            for pid in job.unfinished_pids() {
+                if wait_process(shell, pid) == Some(JobState::Stopped) {
+                    break;
+                }
+            }
+        }
+    }
+    match shell.jobs().get(id).and_then(|job| job.state().status()) {
+        Some(status) => {
+            shell.jobs_mut().remove(id);
+            status
+        }
+        None => ExitStatus::ExitedWith(128 + libc::SIGTSTP),
+    }
+}
+
+/// Waits for the process `pid` of a job to finish, and returns its status.
+fn wait_pid(shell: &mut Shell, pid: libc::pid_t) -> ExitStatus {
+    let found = shell
+        .jobs()
+        .iter()
+        .find_map(|job| Some((job.id, job.process_state(pid)?)));
+    let (id, mut state) = match found {
+        Some(found) => found,
+        None => {
+            // The job may have been reported as done already.
+            if let Some(status) = shell.jobs_mut().take_status(pid) {
+                return status;
+            }
+            eprintln!("toysh: wait: pid {} is not a child of this shell", pid);
+            return ExitStatus::ExitedWith(127);
+        }
+    };
+    if state == JobState::Running {
+        state = wait_process(shell, pid).unwrap_or(state);
+    }
+    if shell
+        .jobs()
+        .get(id)
+        .is_some_and(|job| job.state().status().is_some())
+    {
+        shell.jobs_mut().remove(id);
+    }
+    state
+        .status()
+        .unwrap_or(ExitStatus::ExitedWith(128 + libc::SIGTSTP))
+}
+
+/// Waits for the process `pid` of a job to finish or to be stopped, and
+/// records what has become of it.
+fn wait_process(shell: &mut Shell, pid: libc::pid_t) -> Option<JobState> {
+    let state = process::wait_for_job(pid)?;
+    shell.jobs_mut().update(pid, state);
+    Some(state)
+}
+
+/// Returns the id of the job `spec` refers to, or of the current job if it
+/// is not given.
+fn find(shell: &mut Shell, name: &str, spec: Option<&String>) -> Option<usize> {
+    shell.update_jobs();
+    let found = match spec {
+        Some(spec) => shell.jobs().find(spec),
+        None => shell
+            .jobs()
+            .current()
+            .ok_or_else(|| "current: no such job".to_owned()),
+    };
+    match found {
+        Ok(job) if job.state().status().is_some() => {
+            eprintln!("toysh: {}: job has terminated", name);
+            let id = job.id;
+            shell.jobs_mut().retire(id);
+            None
+        }
+        Ok(job) => Some(job.id),
+        Err(err) => {
+            eprintln!("toysh: {}: {}", name, err);
+            None
+        }
+    }
+}
@@ -0,0 +1,126 @@
+use crate::{
+    job::JobState,
+    process::{self, ExitStatus},
+    shell::Shell,
+    signal,
+};
+
+const USAGE: &str =
+    "toysh: kill: usage: kill [-s sigspec | -n signum | -sigspec] pid | jobspec ... or kill -l [sigspec]";
+
+/// `kill [-s sigspec | -n signum | -sigspec] pid | jobspec ...` sends the
+/// signal, or SIGTERM, to the processes or the jobs given. A job which is
+/// stopped is let continue as well, so that it can be terminated. `kill -l`
+/// lists the signals, and `kill -l sigspec ...` translates between their
+/// names and numbers, where a number over 128 is taken as an exit status.
+pub fn kill(shell: &mut Shell, argv: &[String]) -> ExitStatus {
+    let mut signal = libc::SIGTERM;
+    let mut targets = &argv[1..];
+    match targets.first().map(String::as_str) {
+        Some("-l" | "-L") => return list(&targets[1..]),
+        Some("-s" | "-n") => {
+            let spec = match targets.get(1) {
+                Some(spec) => spec,
+                None => {
+                    eprintln!("toysh: kill: {}: option requires an argument", targets[0]);
+                    return ExitStatus::ExitedWith(2);
+                }
+            };
+            signal = match signal::number(spec) {
+                Some(signal) => signal,
+                None => return invalid(spec),
+            };
+            targets = &targets[2..];
+        }
+        Some("--") => targets = &targets[1..],
+        Some(arg) if arg.len() > 1 && arg.starts_with('-') => {
+            signal = match signal::number(&arg[1..]) {
+                Some(signal) => signal,
+                None => return invalid(&arg[1..]),
+            };
+            targets = &targets[1..];
+        }
+        _ => (),
+    }
+    if targets.first().map(String::as_str) == Some("--") {
+        targets = &targets[1..];
+    }
+    if targets.is_empty() {
+        eprintln!("{}", USAGE);
+        return ExitStatus::ExitedWith(2);
+    }
+
+    shell.update_jobs();
+    let mut status = ExitStatus::ExitedWith(0);
+    for target in targets {
+        if let Err(err) = send(shell, target, signal) {
+            eprintln!("toysh: kill: {}", err);
+            status = ExitStatus::ExitedWith(1);
+        }
+    }
+    status
+}
+
+/// Sends `signal` to the job or the process `target`.
+fn send(shell: &Shell, target: &str, signal: libc::c_int) -> Result<(), String> {
+    if target.starts_with('%') {
+        let job = shell.jobs().find(target)?;
+        let pids = job.unfinished_pids();
+        process::signal_job(job.pgid, &pids, signal)
+            .map_err(|err| format!("{}: {}", target, process::error_message(&err)))?;
+        let continuing = [
+            0,
+            libc::SIGCONT,
+            libc::SIGSTOP,
+            libc::SIGTSTP,
+            libc::SIGTTIN,
+            libc::SIGTTOU,
+        ];
+        if job.state() == JobState::Stopped && !continuing.contains(&signal) {
+            process::signal_job(job.pgid, &pids, libc::SIGCONT)
+                .map_err(|err| format!("{}: {}", target, process::error_message(&err)))?;
+        }
+        return Ok(());
+    }
+
+    let pid: libc::pid_t = target
+        .parse()
+        .map_err(|_| format!("{}: arguments must be process or job IDs", target))?;
+    // SAFETY: sending a signal has no preconditions.
+    if unsafe { libc::kill(pid, signal) } < 0 {
+        let err = std::io::Error::last_os_error();
+        return Err(format!("({}) - {}", pid, process::error_message(&err)));
+    }
+    Ok(())
+}
+
+/// Lists the signals, or prints the name of each number in `specs` and the
+/// number of each name.
+fn list(specs: &[String]) -> ExitStatus {
+    if specs.is_empty() {
+        for (name, number) in signal::signals() {
+            println!("{:2}) SIG{}", number, name);
+        }
+        return ExitStatus::ExitedWith(0);
+    }
+
+    let mut status = ExitStatus::ExitedWith(0);
+    for spec in specs {
+        let translated = match spec.parse::<libc::c_int>() {
+            // The status of a command killed by the signal.
+            Ok(number) if number > 128 => signal::name(number - 128).map(str::to_owned),
+            Ok(number) => signal::name(number).map(str::to_owned),
+            Err(_) => signal::number(spec).map(|number| number.to_string()),
+        };
+        match translated {
+            Some(translated) => println!("{}", translated),
+            None => status = invalid(spec),
+        }
+    }
+    status
+}
+
+fn invalid(spec: &str) -> ExitStatus {
+    eprintln!("toysh: kill: {}: invalid signal specification", spec);
+    ExitStatus::ExitedWith(1)
+}
@@ -0,0 +1,53 @@
+use crate::{process::ExitStatus, shell::Shell};
+
+mod alias;
+mod bind;
+mod complete;
+mod exec;
+mod fc;
+mod hash;
+mod history;
+mod jobs;
+mod kill;
+mod parse;
+mod set;
+
+pub use parse::dump_ast;
+
+/// A builtin command receives the shell and the whole argv including the
+/// command name itself.
+pub type BuiltinCommand = fn(&mut Shell, &[String]) -> ExitStatus;
+
+const BUILTINS: &[(&str, BuiltinCommand)] = &[
+    ("alias", alias::alias),
+    ("bg", jobs::bg),
+    ("bind", bind::bind),
+    ("complete", complete::complete),
+    ("exec", exec::exec),
+    ("fc", fc::fc),
+    ("fg", jobs::fg),
+    ("hash", hash::hash),
+    ("history", history::history),
+    ("jobs", jobs::jobs),
+    ("kill", kill::kill),
+    ("parse", parse::parse),
+    ("set", set::set),
+    ("unalias", alias::unalias),
+    ("wait", jobs::wait),
+];
+
+pub fn builtin_command(name: &str) -> Option<BuiltinCommand> {
+    BUILTINS
+        .iter()
+        .find(|(builtin, _)| *builtin == name)
+        .map(|(_, command)| *command)
+}
+
+pub fn builtin_names() -> impl Iterator<Item = &'static str> {
+    BUILTINS.iter().map(|(name, _)| *name)
+}
+
+/// Quotes `s` with single quotes so that the shell reads it back as is.
+fn quote(s: &str) -> String {
+    format!("'{}'", s.replace('\'', r"'\''"))
+}
@@ -0,0 +1,58 @@
+use std::io::{Read, Write};
+
+use crate::{parser, process::ExitStatus, shell::Shell};
+
+/// `parse [-j] [script ...]` prints the syntax tree of the script, or of
+/// the standard input if none is given, without running it. `-j` prints it
+/// as JSON.
+pub fn parse(_shell: &mut Shell, argv: &[String]) -> ExitStatus {
+    let (json, args) = match argv.get(1).map(String::as_str) {
+        Some("-j") => (true, &argv[2..]),
+        Some(arg) if arg.starts_with('-') && arg != "-" => {
+            eprintln!("toysh: parse: {}: invalid option", arg);
+            return ExitStatus::ExitedWith(2);
+        }
+        _ => (false, &argv[1..]),
+    };
+    let script = if args.is_empty() {
+        let mut script = String::new();
+        if let Err(err) = std::io::stdin().read_to_string(&mut script) {
+            eprintln!("toysh: parse: {}", err);
+            return ExitStatus::ExitedWith(1);
+        }
+        script
+    } else {
+        args.join(" ")
+    };
+    dump_ast(&script, json)
+}
+
+/// Prints the syntax tree of `script`, as JSON if `json` is set, or its
+/// syntax error. Nothing more is reported if the output can't be written,
+/// e.g. when it is piped to `head`.
+pub fn dump_ast(script: &str, json: bool) -> ExitStatus {
+    let ast = match parser::parse(script) {
+        Ok(ast) => ast,
+        Err(parser::ParseError::Empty) => parser::Ast { terms: Vec::new() },
+        Err(parser::ParseError::Fatal(diagnostic) | parser::ParseError::Incomplete(diagnostic)) => {
+            eprint!("toysh: syntax error: {}", diagnostic.render(script));
+            return ExitStatus::ExitedWith(2);
+        }
+    };
+    let mut stdout = std::io::stdout().lock();
+    let written = if json {
+        match serde_json::to_string_pretty(&ast) {
+            Ok(json) => writeln!(stdout, "{}", json),
+            Err(err) => {
+                eprintln!("toysh: parse: {}", err);
+                return ExitStatus::ExitedWith(1);
+            }
+        }
+    } else {
+        writeln!(stdout, "{:#?}", ast)
+    };
+    match written {
+        Ok(()) => ExitStatus::ExitedWith(0),
+        Err(_) => ExitStatus::ExitedWith(1),
+    }
+}
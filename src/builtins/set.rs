@@ -0,0 +1,66 @@
+use crate::{completion::MatchMode, keymap::EditingMode, process::ExitStatus, shell::Shell};
+
+/// `set -o NAME` enables and `set +o NAME` disables an option. `set -o` alone
+/// lists the current settings.
+pub fn set(shell: &mut Shell, argv: &[String]) -> ExitStatus {
+    let mut args = argv.iter().skip(1);
+    let mut status = ExitStatus::ExitedWith(0);
+    if argv.len() == 2 && argv[1] == "-o" {
+        print_options(shell);
+        return status;
+    }
+
+    while let Some(arg) = args.next() {
+        let enable = match arg.as_str() {
+            "-o" => true,
+            "+o" => false,
+            _ => {
+                eprintln!("toysh: set: {}: invalid option", arg);
+                return ExitStatus::ExitedWith(2);
+            }
+        };
+
+        let name = match args.next() {
+            Some(name) => name,
+            None => {
+                print_options(shell);
+                continue;
+            }
+        };
+
+        let keymaps = shell.keymaps_mut();
+        match (name.as_str(), enable) {
+            ("vi", true) | ("emacs", false) => keymaps.mode = EditingMode::Vi,
+            ("emacs", true) | ("vi", false) => keymaps.mode = EditingMode::Emacs,
+            ("fuzzycomplete", _) => shell.completer_mut().set_match_mode(if enable {
+                MatchMode::Fuzzy
+            } else {
+                MatchMode::Prefix
+            }),
+            ("globstar", _) => shell.options_mut().globstar = enable,
+            ("nullglob", _) => shell.options_mut().nullglob = enable,
+            ("pipefail", _) => shell.options_mut().pipefail = enable,
+            _ => {
+                eprintln!("toysh: set: {}: invalid option name", name);
+                status = ExitStatus::ExitedWith(1);
+            }
+        }
+    }
+
+    status
+}
+
+fn print_options(shell: &Shell) {
+    let mode = shell.keymaps().mode;
+    let on_off = |enabled: bool| if enabled { "on" } else { "off" };
+    println!("{:<15}{}", "emacs", on_off(mode == EditingMode::Emacs));
+    println!(
+        "{:<15}{}",
+        "fuzzycomplete",
+        on_off(shell.completer().match_mode() == MatchMode::Fuzzy)
+    );
+    println!("{:<15}{}", "globstar", on_off(shell.options().globstar));
+    println!("{:<15}{}", "nullglob", on_off(shell.options().nullglob));
+    println!("{:<15}{}", "pipefail", on_off(shell.options().pipefail));
+    println!("{:<15}{}", "vi", on_off(mode == EditingMode::Vi));
+}
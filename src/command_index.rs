@@ -0,0 +1,165 @@
+//! An index of the executables on `$PATH`.
+//!
+//! Reading every directory on `$PATH` can take a while, e.g. on network file
+//! systems, so the index is built in a background thread and afterwards only
+//! the directories which have been modified since are read again.
+
+use std::{
+    collections::BTreeSet,
+    os::unix::fs::PermissionsExt,
+    sync::{Arc, Mutex},
+    thread::JoinHandle,
+    time::SystemTime,
+};
+
+/// The executables in one directory on `$PATH`.
+#[derive(Debug)]
+struct Directory {
+    path: String,
+    /// When the directory was modified as of the last read, or `None` if it
+    /// hasn't been read.
+    modified: Option<SystemTime>,
+    names: BTreeSet<String>,
+}
+
+#[derive(Debug)]
+pub struct CommandIndex {
+    /// The value of `$PATH` the index is for.
+    path: String,
+    directories: Arc<Mutex<Vec<Directory>>>,
+    /// The thread reading the directories which have changed.
+    update: Option<JoinHandle<()>>,
+    /// Whether the first update has been waited for.
+    built: bool,
+}
+
+impl CommandIndex {
+    /// Starts indexing the directories in `path`, a value of `$PATH`.
+    pub fn new(path: String) -> Self {
+        let directories = path
+            .split(':')
+            .filter(|dir| !dir.is_empty())
+            .map(|dir| Directory {
+                path: dir.to_owned(),
+                modified: None,
+                names: BTreeSet::new(),
+            })
+            .collect();
+        let mut index = Self {
+            path,
+            directories: Arc::new(Mutex::new(directories)),
+            update: None,
+            built: false,
+        };
+        index.start_update();
+        index
+    }
+
+    /// The value of `$PATH` the index is for.
+    pub fn path(&self) -> &str {
+        &self.path
+    }
+
+    /// Returns the names of the executables, waiting for the index to be
+    /// built the first time. Afterwards the names are returned right away and
+    /// the directories which have changed are read again in the background,
+    /// so changes show up the time after.
+    pub fn names(&mut self) -> BTreeSet<String> {
+        if !self.built {
+            if let Some(update) = self.update.take() {
+                update.join().ok();
+            }
+            self.built = true;
+        } else if self.update.as_ref().is_none_or(JoinHandle::is_finished) {
+            self.start_update();
+        }
+
+        let directories = self.directories.lock().unwrap();
+        directories
+            .iter()
+            .flat_map(|dir| dir.names.iter().cloned())
+            .collect()
+    }
+
+    fn start_update(&mut self) {
+        let directories = Arc::clone(&self.directories);
+        self.update = Some(std::thread::spawn(move || update(&directories)));
+    }
+}
+
+/// Reads the directories which have been modified since they were last
+/// read. The lock is not held while reading so that the names can be used
+/// in the meantime.
+fn update(directories: &Mutex<Vec<Directory>>) {
+    let stale: Vec<(usize, String, Option<SystemTime>)> = {
+        let directories = directories.lock().unwrap();
+        directories
+            .iter()
+            .enumerate()
+            .filter_map(|(i, dir)| {
+                let modified = std::fs::metadata(&dir.path)
+                    .and_then(|metadata| metadata.modified())
+                    .ok();
+                (dir.modified.is_none() || dir.modified != modified)
+                    .then(|| (i, dir.path.clone(), modified))
+            })
+            .collect()
+    };
+
+    for (i, path, modified) in stale {
+        let names = find_executables(&path);
+        let mut directories = directories.lock().unwrap();
+        directories[i].names = names;
+        directories[i].modified = modified;
+    }
+}
+
+/// Returns the names of the executable files in `dir`.
+pub fn find_executables(dir: &str) -> BTreeSet<String> {
+    let mut executables = BTreeSet::new();
+    let entries = match std::fs::read_dir(dir) {
+        Ok(entries) => entries,
+        Err(_) => return executables,
+    };
+    for entry in entries.filter_map(Result::ok) {
+        let is_executable = std::fs::metadata(entry.path())
+            .is_ok_and(|metadata| metadata.is_file() && metadata.permissions().mode() & 0o111 != 0);
+        if let (true, Ok(name)) = (is_executable, entry.file_name().into_string()) {
+            executables.insert(name);
+        }
+    }
+    executables
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn add_file(path: &std::path::Path, mode: u32) {
+        std::fs::write(path, "").unwrap();
+        std::fs::set_permissions(path, std::fs::Permissions::from_mode(mode)).unwrap();
+    }
+
+    #[test]
+    fn index_executables() {
+        let dir = std::env::temp_dir().join(format!("toysh-index-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(dir.join("sub")).unwrap();
+        add_file(&dir.join("run"), 0o755);
+        add_file(&dir.join("data"), 0o644);
+
+        let path = format!("{}::{}", dir.display(), dir.join("missing").display());
+        let mut index = CommandIndex::new(path.clone());
+        assert_eq!(index.path(), path);
+        assert_eq!(index.names().into_iter().collect::<Vec<_>>(), ["run"]);
+
+        // A new executable shows up once the directory has been read again.
+        add_file(&dir.join("new"), 0o755);
+        index.names();
+        index.update.take().unwrap().join().unwrap();
+        assert_eq!(
+            index.names().into_iter().collect::<Vec<_>>(),
+            ["new", "run"]
+        );
+    }
+}
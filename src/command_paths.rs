@@ -0,0 +1,83 @@
+//! The paths of the commands which have been run, remembered so that
+//! `$PATH` isn't searched every time they are run.
+
+use std::{collections::BTreeMap, ffi::OsString, path::PathBuf};
+
+use crate::process;
+
+#[derive(Debug, Default)]
+pub struct CommandPaths {
+    /// The value of `$PATH` the paths were found on, which are forgotten
+    /// once it changes.
+    path: Option<OsString>,
+    entries: BTreeMap<String, Entry>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Entry {
+    pub path: PathBuf,
+    /// How many times the command has been run from the path.
+    pub hits: usize,
+}
+
+impl CommandPaths {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Returns the path of the command `name` to run it, which is searched
+    /// on `$PATH` only if it isn't remembered or has been removed since.
+    pub fn find(&mut self, name: &str) -> Option<PathBuf> {
+        if name.contains('/') {
+            return Some(PathBuf::from(name));
+        }
+        let entry = self.lookup(name)?;
+        entry.hits += 1;
+        Some(entry.path.clone())
+    }
+
+    /// Searches `$PATH` for the command `name` and remembers where it is
+    /// without running it, for `hash name`. Returns whether it is found.
+    pub fn add(&mut self, name: &str) -> bool {
+        self.lookup(name).is_some()
+    }
+
+    /// Forgets all the paths.
+    pub fn clear(&mut self) {
+        self.entries.clear();
+    }
+
+    /// The commands and their paths, sorted by name.
+    pub fn entries(&mut self) -> impl Iterator<Item = (&str, &Entry)> {
+        self.check_path();
+        self.entries
+            .iter()
+            .map(|(name, entry)| (name.as_str(), entry))
+    }
+
+    fn lookup(&mut self, name: &str) -> Option<&mut Entry> {
+        self.check_path();
+        if self
+            .entries
+            .get(name)
+            .is_some_and(|entry| !process::is_executable(&entry.path))
+        {
+            self.entries.remove(name);
+        }
+        if !self.entries.contains_key(name) {
+            let path = process::find_command(name)?;
+            self.entries
+                .insert(name.to_owned(), Entry { path, hits: 0 });
+        }
+        self.entries.get_mut(name)
+    }
+
+    /// Forgets the paths if `$PATH` has changed since they were found.
+    fn check_path(&mut self) {
+        let path = std::env::var_os("PATH");
+        if path != self.path {
+            self.entries.clear();
+            self.path = path;
+        }
+    }
+}
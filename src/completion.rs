@@ -0,0 +1,1062 @@
+//! Completion of the word under the cursor.
+
+use std::{
+    collections::{BTreeMap, BTreeSet, HashMap},
+    path::{Path, PathBuf},
+};
+
+use serde::Deserialize;
+use tracing::debug;
+
+use crate::{builtins, command_index::CommandIndex, users};
+
+/// Characters which have to be escaped in a completed word so that it is
+/// read back as a single word.
+const SPECIAL_CHARS: &str = " \t\n\\'\"`$&|;()<>*?[]#~{}!";
+
+/// The directory in the home directory the completion definitions are read
+/// from.
+const COMPLETIONS_DIR_NAME: &str = ".toysh_completions";
+
+/// The commands whose arguments are host names unless set otherwise with
+/// `complete`.
+const HOSTNAME_COMMANDS: &[&str] = &["ping", "scp", "sftp", "ssh"];
+
+/// Characters which separate words on the command line.
+const WORD_BREAKS: &str = " \t\n;|&<>()";
+
+/// A possible completion of a word.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Candidate {
+    /// The whole word, unescaped.
+    pub replacement: String,
+    /// What to show when listing the candidates, e.g. only the file name.
+    pub display: String,
+    /// Appended after the word when it is the only candidate: `/` after a
+    /// directory to continue with its contents, or a space otherwise.
+    pub suffix: &'static str,
+    /// The file the candidate names, if it is a path.
+    pub file: Option<PathBuf>,
+}
+
+/// The candidates for the word starting at byte offset `start` and ending at
+/// the cursor.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Completions {
+    pub start: usize,
+    /// The word as typed, unescaped.
+    pub word: String,
+    pub candidates: Vec<Candidate>,
+}
+
+impl Completions {
+    /// The longest prefix all the candidates share.
+    pub fn common_prefix(&self) -> &str {
+        let mut candidates = self.candidates.iter().map(|c| c.replacement.as_str());
+        let mut prefix = match candidates.next() {
+            Some(first) => first,
+            None => return "",
+        };
+        for candidate in candidates {
+            let len = prefix
+                .char_indices()
+                .zip(candidate.chars())
+                .take_while(|((_, a), b)| a == b)
+                .last()
+                .map_or(0, |((i, ch), _)| i + ch.len_utf8());
+            prefix = &prefix[..len];
+        }
+        prefix
+    }
+}
+
+/// The subcommands and flags of a command, read from `COMMAND.json` in
+/// `~/.toysh_completions`, e.g.
+///
+/// ```json
+/// {
+///     "flags": ["--help", "--version"],
+///     "subcommands": {
+///         "build": { "flags": ["--release"] },
+///         "new": { "files": false }
+///     }
+/// }
+/// ```
+///
+/// Flags are completed after `-`. Otherwise the subcommands are, or files if
+/// there are none and `files` is not false.
+#[derive(Clone, Debug, Deserialize)]
+#[serde(default)]
+struct Definition {
+    flags: Vec<String>,
+    subcommands: BTreeMap<String, Definition>,
+    files: bool,
+}
+
+impl Default for Definition {
+    fn default() -> Self {
+        Self {
+            flags: Vec::new(),
+            subcommands: BTreeMap::new(),
+            files: true,
+        }
+    }
+}
+
+impl Definition {
+    /// Reads the definition of `command`, if there is one.
+    fn load(command: &str) -> Option<Self> {
+        let path = Path::new(&std::env::var_os("HOME")?)
+            .join(COMPLETIONS_DIR_NAME)
+            .join(format!("{}.json", command));
+        let text = std::fs::read_to_string(&path).ok()?;
+        match serde_json::from_str(&text) {
+            Ok(definition) => Some(definition),
+            Err(err) => {
+                debug!(?path, %err, "invalid completion definition");
+                None
+            }
+        }
+    }
+}
+
+/// How the word being completed is compared with the candidates.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub enum MatchMode {
+    /// The candidates start with the word.
+    #[default]
+    Prefix,
+    /// The characters of the word appear in the candidates in order, e.g.
+    /// `gco` matches `git-checkout-helper`. Lower-case letters match either
+    /// case.
+    Fuzzy,
+}
+
+impl MatchMode {
+    /// Returns how well `name` matches `word`, lower being better, or `None`
+    /// if it doesn't match. Prefixes come first, then substrings, then the
+    /// other matches with fewer characters skipped and more characters at the
+    /// beginning of the parts of the name.
+    fn rank(self, word: &str, name: &str) -> Option<usize> {
+        if name.starts_with(word) {
+            return Some(0);
+        }
+        if self == MatchMode::Prefix {
+            return None;
+        }
+        if let Some(i) = name.find(word) {
+            return Some(1 + i);
+        }
+
+        let mut skipped = 0;
+        let mut not_at_boundary = 0;
+        let mut prev: Option<char> = None;
+        let mut chars = name.chars();
+        for wanted in word.chars() {
+            loop {
+                let ch = chars.next()?;
+                let at_boundary = prev.is_none_or(|prev| {
+                    "-_./ ".contains(prev) || (prev.is_lowercase() && ch.is_uppercase())
+                });
+                prev = Some(ch);
+                if ch == wanted || (wanted.is_lowercase() && ch.to_lowercase().eq([wanted])) {
+                    if !at_boundary {
+                        not_at_boundary += 1;
+                    }
+                    break;
+                }
+                skipped += 1;
+            }
+        }
+        Some(1 + name.len() + 2 * skipped + not_at_boundary)
+    }
+}
+
+/// How to complete the arguments of a command, registered with the
+/// `complete` builtin.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum CompletionSpec {
+    /// `-W`: one of the words.
+    Words(Vec<String>),
+    /// `-F`: the lines a shell function prints.
+    Function(String),
+    /// `-A hostname`: the hosts in `~/.ssh/config` and `~/.ssh/known_hosts`.
+    Hostnames,
+}
+
+/// The names the shell defines itself, which are completed along with the
+/// ones found on the system.
+#[derive(Debug, Default)]
+pub struct ShellNames {
+    /// The aliases and the functions, which are completed as commands.
+    pub commands: Vec<String>,
+    /// The names and values of the variables, exported or not.
+    pub variables: Vec<(String, String)>,
+}
+
+/// Completes words on the command line, keeping what is expensive to look
+/// up between completions.
+#[derive(Debug, Default)]
+pub struct Completer {
+    commands: Option<CommandIndex>,
+    specs: HashMap<String, CompletionSpec>,
+    /// The definitions read so far, or `None` for the commands without one.
+    definitions: HashMap<String, Option<Definition>>,
+    match_mode: MatchMode,
+}
+
+impl Completer {
+    pub fn new() -> Self {
+        let mut completer = Self::default();
+        // Start indexing right away so that it is ready by the first time.
+        let path = std::env::var("PATH").unwrap_or_default();
+        completer.commands = Some(CommandIndex::new(path));
+        for command in HOSTNAME_COMMANDS {
+            completer.set_spec(command, CompletionSpec::Hostnames);
+        }
+        completer
+    }
+
+    pub fn match_mode(&self) -> MatchMode {
+        self.match_mode
+    }
+
+    pub fn set_match_mode(&mut self, mode: MatchMode) {
+        self.match_mode = mode;
+    }
+
+    /// Forgets the executables found on `$PATH`, so that they are looked up
+    /// again from scratch.
+    pub fn forget_commands(&mut self) {
+        self.commands = None;
+    }
+
+    /// The commands with a completion spec and their specs, sorted by name.
+    pub fn specs(&self) -> Vec<(&str, &CompletionSpec)> {
+        let mut specs: Vec<_> = self
+            .specs
+            .iter()
+            .map(|(command, spec)| (command.as_str(), spec))
+            .collect();
+        specs.sort_by_key(|(command, _)| *command);
+        specs
+    }
+
+    pub fn spec(&self, command: &str) -> Option<&CompletionSpec> {
+        self.specs.get(command)
+    }
+
+    pub fn set_spec(&mut self, command: &str, spec: CompletionSpec) {
+        self.specs.insert(command.to_owned(), spec);
+    }
+
+    /// Returns false if `command` had no spec.
+    pub fn remove_spec(&mut self, command: &str) -> bool {
+        self.specs.remove(command).is_some()
+    }
+
+    pub fn clear_specs(&mut self) {
+        self.specs.clear();
+    }
+
+    /// Completes the word before `cursor` (a byte offset) in `line`, where
+    /// the shell defines `names`. `call_function` calls a shell function
+    /// with arguments and returns what it prints, for `complete -F`.
+    pub fn complete(
+        &mut self,
+        line: &str,
+        cursor: usize,
+        names: &ShellNames,
+        call_function: &mut dyn FnMut(&str, &[String]) -> String,
+    ) -> Completions {
+        let (start, word) = word_before(&line[..cursor]);
+        // An explanation at the end of a line is not completed.
+        if in_comment(&line[..cursor]) {
+            return Completions {
+                start,
+                word,
+                candidates: Vec::new(),
+            };
+        }
+        let mode = self.match_mode;
+        if let Some(completions) = complete_tilde(&line[start..cursor], &word, mode) {
+            return Completions {
+                start: start + completions.start,
+                ..completions
+            };
+        }
+        if let Some(completions) = complete_variable(line, start, cursor, &names.variables, mode) {
+            return completions;
+        }
+        let candidates = if is_command_position(&line[..start]) && !word.contains('/') {
+            self.complete_command(&word, &names.commands)
+        } else {
+            self.complete_argument(&line[..start], &word, call_function)
+        };
+        Completions {
+            start,
+            word,
+            candidates,
+        }
+    }
+
+    /// Completes an argument of the command whose words are in `before`,
+    /// following its spec or definition file if it has one.
+    fn complete_argument(
+        &mut self,
+        before: &str,
+        word: &str,
+        call_function: &mut dyn FnMut(&str, &[String]) -> String,
+    ) -> Vec<Candidate> {
+        let words = command_words(before);
+        let name = match words.first() {
+            Some(name) => *name,
+            None => return complete_path(word, self.match_mode),
+        };
+        match self.specs.get(name) {
+            Some(CompletionSpec::Words(candidates)) => {
+                return complete_words(candidates, word, self.match_mode)
+            }
+            // The function gets the command, the word, and the word before
+            // it as in other shells.
+            Some(CompletionSpec::Function(function)) => {
+                let previous = words.last().copied().unwrap_or_default();
+                let args = [name, word, previous].map(str::to_owned);
+                let candidates: Vec<String> = call_function(function, &args)
+                    .lines()
+                    .map(str::to_owned)
+                    .collect();
+                return complete_words(&candidates, word, self.match_mode);
+            }
+            Some(CompletionSpec::Hostnames) if !word.contains('/') => {
+                return complete_host(word, self.match_mode)
+            }
+            Some(CompletionSpec::Hostnames) => return complete_path(word, self.match_mode),
+            None => (),
+        }
+
+        let definition = self
+            .definitions
+            .entry(name.to_owned())
+            .or_insert_with(|| Definition::load(name));
+        let mut definition = match definition {
+            Some(definition) => &*definition,
+            None => return complete_path(word, self.match_mode),
+        };
+        for arg in &words[1..] {
+            if let Some(subcommand) = definition.subcommands.get(*arg) {
+                definition = subcommand;
+            }
+        }
+        if word.starts_with('-') {
+            complete_words(&definition.flags, word, self.match_mode)
+        } else if !definition.subcommands.is_empty() {
+            complete_words(definition.subcommands.keys(), word, self.match_mode)
+        } else if definition.files {
+            complete_path(word, self.match_mode)
+        } else {
+            Vec::new()
+        }
+    }
+
+    /// Lists the builtins, the aliases and functions in `defined`, and the
+    /// executables on `$PATH` whose names match `word`.
+    fn complete_command(&mut self, word: &str, defined: &[String]) -> Vec<Candidate> {
+        let path = std::env::var("PATH").unwrap_or_default();
+        if self
+            .commands
+            .as_ref()
+            .is_none_or(|commands| commands.path() != path)
+        {
+            self.commands = Some(CommandIndex::new(path));
+        }
+        let commands = self.commands.as_mut().unwrap().names();
+
+        let mut names: BTreeSet<&str> = commands.iter().map(String::as_str).collect();
+        names.extend(defined.iter().map(String::as_str));
+        for name in builtins::builtin_names() {
+            names.insert(name);
+        }
+        rank_candidates(
+            names.into_iter().map(|name| Candidate {
+                replacement: name.to_owned(),
+                display: name.to_owned(),
+                suffix: " ",
+                file: None,
+            }),
+            word,
+            self.match_mode,
+            |candidate| &candidate.replacement,
+        )
+    }
+}
+
+/// Completes a user name after `~`, or a path under `~user/`, given the word
+/// as typed and unescaped. The `~user/` prefix is kept as is since escaping
+/// it would stop it from being expanded, so `start` is relative to the word.
+fn complete_tilde(text: &str, word: &str, mode: MatchMode) -> Option<Completions> {
+    let user = text.strip_prefix('~')?;
+    let user = &user[..user.find('/').unwrap_or(user.len())];
+    if user.contains(|ch| SPECIAL_CHARS.contains(ch)) {
+        return None;
+    }
+
+    let prefix_len = user.len() + 2;
+    if text.len() < prefix_len {
+        let candidates = rank_candidates(
+            users::names().into_iter().map(|name| Candidate {
+                display: format!("~{}", name),
+                replacement: name,
+                suffix: "/",
+                file: None,
+            }),
+            user,
+            mode,
+            |candidate| &candidate.replacement,
+        );
+        return Some(Completions {
+            start: 1,
+            word: user.to_owned(),
+            candidates,
+        });
+    }
+
+    let home = users::home_dir(user)?;
+    let home = format!("{}/", home.to_string_lossy().trim_end_matches('/'));
+    let rest = &word[prefix_len..];
+    let candidates = complete_path(&format!("{}{}", home, rest), mode)
+        .into_iter()
+        .map(|candidate| Candidate {
+            replacement: candidate.replacement[home.len()..].to_owned(),
+            ..candidate
+        })
+        .collect();
+    Some(Completions {
+        start: prefix_len,
+        word: rest.to_owned(),
+        candidates,
+    })
+}
+
+/// Completes the name of one of `variables` after `$` or `${` at the end of
+/// the word from `start` to `cursor`. Only the name itself is replaced, so
+/// the candidates are not escaped.
+fn complete_variable(
+    line: &str,
+    start: usize,
+    cursor: usize,
+    variables: &[(String, String)],
+    mode: MatchMode,
+) -> Option<Completions> {
+    let text = &line[start..cursor];
+    let dollar = text.rfind('$')?;
+    if text[..dollar].ends_with('\\') || text[..dollar].matches('\'').count() % 2 == 1 {
+        return None;
+    }
+    let (braced, name) = match text[dollar + 1..].strip_prefix('{') {
+        Some(name) => (true, name),
+        None => (false, &text[dollar + 1..]),
+    };
+    if !name
+        .chars()
+        .all(|ch| ch.is_ascii_alphanumeric() || ch == '_')
+    {
+        return None;
+    }
+
+    let candidates = variables.iter().map(|(key, value)| {
+        let suffix = if braced {
+            "}"
+        } else if Path::new(value).is_dir() {
+            "/"
+        } else {
+            " "
+        };
+        Candidate {
+            display: format!("${}", key),
+            replacement: key.clone(),
+            suffix,
+            file: None,
+        }
+    });
+    Some(Completions {
+        start: cursor - name.len(),
+        word: name.to_owned(),
+        candidates: rank_candidates(candidates, name, mode, |candidate| &candidate.replacement),
+    })
+}
+
+/// Returns whether `before` ends in a comment, i.e. a `#` outside quotes at
+/// the beginning of a word starts the line it ends in.
+fn in_comment(before: &str) -> bool {
+    let mut quote = None;
+    let mut comment = false;
+    let mut prev = None;
+    let mut chars = before.chars();
+    while let Some(ch) = chars.next() {
+        match (quote, ch) {
+            (_, '\n') if comment => comment = false,
+            _ if comment => (),
+            (Some('\''), '\'') | (Some('"'), '"') => quote = None,
+            (Some('\''), _) => (),
+            (_, '\\') => {
+                chars.next();
+            }
+            (Some(_), _) => (),
+            (None, '\'' | '"') => quote = Some(ch),
+            (None, '#') => {
+                comment =
+                    prev.is_none_or(|prev: char| prev.is_whitespace() || ";|&()".contains(prev))
+            }
+            _ => (),
+        }
+        prev = Some(ch);
+    }
+    comment
+}
+
+/// Whether a word after `before` is a command name rather than an argument.
+fn is_command_position(before: &str) -> bool {
+    let before = before.trim_end();
+    before.is_empty() || before.ends_with(|ch| ";|&(".contains(ch))
+}
+
+/// Returns the words of the command `before` ends in, i.e. the ones after
+/// the last command separator.
+fn command_words(before: &str) -> Vec<&str> {
+    let start = before.rfind(|ch| ";|&(".contains(ch)).map_or(0, |i| i + 1);
+    before[start..].split_whitespace().collect()
+}
+
+/// Lists the known hosts which match `word`, or the part of it after `user@`.
+fn complete_host(word: &str, mode: MatchMode) -> Vec<Candidate> {
+    let (user, host) = match word.rfind('@') {
+        Some(i) => word.split_at(i + 1),
+        None => ("", word),
+    };
+    let hosts = known_hosts();
+    rank_candidates(
+        hosts.iter().map(|name| Candidate {
+            replacement: format!("{}{}", user, name),
+            display: name.clone(),
+            suffix: " ",
+            file: None,
+        }),
+        host,
+        mode,
+        |candidate| &candidate.display,
+    )
+}
+
+/// Returns the host names in `~/.ssh/config` and `~/.ssh/known_hosts`,
+/// sorted and without duplicates.
+fn known_hosts() -> BTreeSet<String> {
+    let mut hosts = BTreeSet::new();
+    let ssh_dir = match users::home_dir("") {
+        Some(home) => home.join(".ssh"),
+        None => return hosts,
+    };
+    if let Ok(config) = std::fs::read_to_string(ssh_dir.join("config")) {
+        add_config_hosts(&config, &mut hosts);
+    }
+    if let Ok(known_hosts) = std::fs::read_to_string(ssh_dir.join("known_hosts")) {
+        add_known_hosts(&known_hosts, &mut hosts);
+    }
+    hosts
+}
+
+/// Adds the names in the `Host` lines of an ssh config file to `hosts`,
+/// leaving out patterns.
+fn add_config_hosts(config: &str, hosts: &mut BTreeSet<String>) {
+    for line in config.lines() {
+        // `Host a b` or `Host=a b`, with a keyword in any case.
+        let line = line.trim_start();
+        let (keyword, rest) = line
+            .split_once(|ch: char| ch.is_whitespace() || ch == '=')
+            .unwrap_or((line, ""));
+        if keyword.eq_ignore_ascii_case("host") {
+            let names = rest.trim_start_matches(|ch: char| ch.is_whitespace() || ch == '=');
+            hosts.extend(
+                names
+                    .split_whitespace()
+                    .filter(|host| !is_host_pattern(host))
+                    .map(str::to_owned),
+            );
+        }
+    }
+}
+
+/// Adds the names in a `known_hosts` file to `hosts`, leaving out patterns
+/// and hashed names.
+fn add_known_hosts(known_hosts: &str, hosts: &mut BTreeSet<String>) {
+    for line in known_hosts.lines() {
+        let mut fields = line.split_whitespace();
+        // Skip markers such as `@cert-authority`.
+        let names = match fields.next() {
+            Some(marker) if marker.starts_with('@') => fields.next(),
+            names => names,
+        };
+        let names = match names {
+            Some(names) if !names.starts_with('#') && !names.starts_with('|') => names,
+            _ => continue,
+        };
+        for name in names.split(',') {
+            // `[host]:port`
+            let name = match name.strip_prefix('[') {
+                Some(name) => name.split(']').next().unwrap_or(name),
+                None => name,
+            };
+            if !name.is_empty() && !is_host_pattern(name) {
+                hosts.insert(name.to_owned());
+            }
+        }
+    }
+}
+
+fn is_host_pattern(host: &str) -> bool {
+    host.contains(['*', '?', '!'])
+}
+
+/// Lists the `candidates` which match `word`.
+fn complete_words<'a>(
+    candidates: impl IntoIterator<Item = &'a String>,
+    word: &str,
+    mode: MatchMode,
+) -> Vec<Candidate> {
+    rank_candidates(
+        candidates.into_iter().map(|candidate| Candidate {
+            replacement: candidate.clone(),
+            display: candidate.clone(),
+            suffix: " ",
+            file: None,
+        }),
+        word,
+        mode,
+        |candidate| &candidate.replacement,
+    )
+}
+
+/// Finds the word which ends at the end of `line`. Returns its byte offset
+/// and the word with quotes and backslashes removed.
+fn word_before(line: &str) -> (usize, String) {
+    let mut start = 0;
+    let mut word = String::new();
+    let mut quote = None;
+    let mut chars = line.char_indices();
+    while let Some((i, ch)) = chars.next() {
+        match (quote, ch) {
+            (None, '\\') => {
+                if let Some((_, next)) = chars.next() {
+                    word.push(next);
+                }
+            }
+            (None, '\'' | '"') => quote = Some(ch),
+            (Some(q), _) if q == ch => quote = None,
+            (None, _) if WORD_BREAKS.contains(ch) => {
+                start = i + ch.len_utf8();
+                word.clear();
+            }
+            _ => word.push(ch),
+        }
+    }
+    (start, word)
+}
+
+/// Lists the files and directories whose paths match `word`. Hidden files
+/// are included only if the file name being typed starts with `.`.
+fn complete_path(word: &str, mode: MatchMode) -> Vec<Candidate> {
+    let (dir, prefix) = match word.rfind('/') {
+        Some(i) => (&word[..=i], &word[i + 1..]),
+        None => ("", word),
+    };
+    let entries = match std::fs::read_dir(if dir.is_empty() { "." } else { dir }) {
+        Ok(entries) => entries,
+        Err(_) => return Vec::new(),
+    };
+
+    let mut names: Vec<(usize, String)> = entries
+        .filter_map(Result::ok)
+        .filter_map(|entry| {
+            let name = entry.file_name().into_string().ok()?;
+            if name.starts_with('.') && !prefix.starts_with('.') {
+                return None;
+            }
+            Some((mode.rank(prefix, &name)?, name))
+        })
+        .collect();
+    names.sort();
+    names
+        .into_iter()
+        .map(|(_, name)| {
+            let replacement = format!("{}{}", dir, name);
+            // Follow symbolic links so that links to directories count.
+            let is_dir = Path::new(&replacement).is_dir();
+            Candidate {
+                display: if is_dir { format!("{}/", name) } else { name },
+                suffix: if is_dir { "/" } else { " " },
+                file: Some(PathBuf::from(&replacement)),
+                replacement,
+            }
+        })
+        .collect()
+}
+
+/// Keeps the candidates whose `key` matches `word`, the best matches first
+/// and equally good ones in order of their replacements.
+fn rank_candidates(
+    candidates: impl IntoIterator<Item = Candidate>,
+    word: &str,
+    mode: MatchMode,
+    key: impl Fn(&Candidate) -> &str,
+) -> Vec<Candidate> {
+    let mut ranked: Vec<(usize, Candidate)> = candidates
+        .into_iter()
+        .filter_map(|candidate| Some((mode.rank(word, key(&candidate))?, candidate)))
+        .collect();
+    ranked.sort_by(|(a, x), (b, y)| a.cmp(b).then_with(|| x.replacement.cmp(&y.replacement)));
+    ranked.into_iter().map(|(_, candidate)| candidate).collect()
+}
+
+/// Escapes `word` with backslashes so that the shell reads it back as is.
+pub fn escape(word: &str) -> String {
+    let mut escaped = String::with_capacity(word.len());
+    for ch in word.chars() {
+        if SPECIAL_CHARS.contains(ch) {
+            escaped.push('\\');
+        }
+        escaped.push(ch);
+    }
+    escaped
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Creates a directory for `test` with `files` in it. Names ending with
+    /// `/` are created as directories. Returns its path ending with `/`.
+    fn fixture(test: &str, files: &[&str]) -> String {
+        let dir =
+            std::env::temp_dir().join(format!("toysh-completion-{}-{}", test, std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        for file in files {
+            match file.strip_suffix('/') {
+                Some(name) => std::fs::create_dir_all(dir.join(name)).unwrap(),
+                None => std::fs::write(dir.join(file), "").unwrap(),
+            }
+        }
+        format!("{}/", dir.display())
+    }
+
+    fn completions(replacements: &[&str]) -> Completions {
+        Completions {
+            start: 0,
+            word: String::new(),
+            candidates: replacements
+                .iter()
+                .map(|replacement| Candidate {
+                    replacement: replacement.to_string(),
+                    display: replacement.to_string(),
+                    suffix: " ",
+                    file: None,
+                })
+                .collect(),
+        }
+    }
+
+    #[test]
+    fn find_word_before_cursor() {
+        assert_eq!(word_before(""), (0, "".to_owned()));
+        assert_eq!(word_before("ls src/ma"), (3, "src/ma".to_owned()));
+        assert_eq!(word_before("cat a|grep"), (6, "grep".to_owned()));
+        assert_eq!(word_before("ls my\\ fi"), (3, "my fi".to_owned()));
+        assert_eq!(word_before("ls 'my fi"), (3, "my fi".to_owned()));
+        assert_eq!(word_before("ls \"a b\"c"), (3, "a bc".to_owned()));
+    }
+
+    #[test]
+    fn escape_specials() {
+        assert_eq!(escape("main.rs"), "main.rs");
+        assert_eq!(escape("my file"), "my\\ file");
+        assert_eq!(escape("a'b\"c"), "a\\'b\\\"c");
+        assert_eq!(escape("$x&(y)*"), "\\$x\\&\\(y\\)\\*");
+        assert_eq!(escape("back\\slash"), "back\\\\slash");
+    }
+
+    #[test]
+    fn common_prefix() {
+        assert_eq!(completions(&[]).common_prefix(), "");
+        assert_eq!(completions(&["main.rs"]).common_prefix(), "main.rs");
+        assert_eq!(completions(&["main.rs", "mod.rs"]).common_prefix(), "m");
+        assert_eq!(completions(&["ab", "cd"]).common_prefix(), "");
+        assert_eq!(
+            completions(&["\u{e9}t\u{e9}", "\u{e9}t\u{e0}"]).common_prefix(),
+            "\u{e9}t"
+        );
+    }
+
+    #[test]
+    fn complete_paths() {
+        let dir = fixture("paths", &["main.rs", "mod.rs", "src/", ".hidden", "README"]);
+
+        let candidates = complete_path(&format!("{}m", dir), MatchMode::Prefix);
+        let names: Vec<_> = candidates.iter().map(|c| c.display.as_str()).collect();
+        assert_eq!(names, ["main.rs", "mod.rs"]);
+        assert_eq!(candidates[0].replacement, format!("{}main.rs", dir));
+        assert_eq!(candidates[0].suffix, " ");
+
+        let candidates = complete_path(&format!("{}s", dir), MatchMode::Prefix);
+        assert_eq!(candidates.len(), 1);
+        assert_eq!(candidates[0].display, "src/");
+        assert_eq!(candidates[0].suffix, "/");
+
+        assert_eq!(complete_path(&dir, MatchMode::Prefix).len(), 4);
+        let candidates = complete_path(&format!("{}.", dir), MatchMode::Prefix);
+        assert_eq!(candidates[0].display, ".hidden");
+        assert!(complete_path(&format!("{}missing/", dir), MatchMode::Prefix).is_empty());
+    }
+
+    #[test]
+    fn command_position() {
+        assert!(is_command_position(""));
+        assert!(is_command_position("  "));
+        assert!(is_command_position("ls; "));
+        assert!(is_command_position("cat a | "));
+        assert!(is_command_position("true &&"));
+        assert!(is_command_position("("));
+        assert!(!is_command_position("ls "));
+        assert!(!is_command_position("echo a "));
+    }
+
+    #[test]
+    fn comments() {
+        assert!(in_comment("# fo"));
+        assert!(in_comment("ls # fo"));
+        assert!(in_comment("ls;#fo"));
+        assert!(in_comment("echo 'a' # it's"));
+        assert!(!in_comment("ls a#b"));
+        assert!(!in_comment("echo '# fo"));
+        assert!(!in_comment("echo \"a # b"));
+        assert!(!in_comment("echo \\# fo"));
+        assert!(!in_comment("# fo\nls "));
+
+        let mut completer = Completer::default();
+        let line = "ls # fo";
+        let completions =
+            completer.complete(line, line.len(), &ShellNames::default(), &mut |_, _| {
+                String::new()
+            });
+        assert_eq!((completions.start, completions.word.as_str()), (5, "fo"));
+        assert!(completions.candidates.is_empty());
+    }
+
+    #[test]
+    fn complete_variables() {
+        let dir = std::env::temp_dir().display().to_string();
+        let variables = [
+            ("PATH", "/bin:/usr/bin"),
+            ("PAGER", "less"),
+            ("PARENT", dir.as_str()),
+            ("HOME", "/"),
+        ]
+        .map(|(name, value)| (name.to_owned(), value.to_owned()));
+        let complete = |line: &str, cursor: usize| {
+            complete_variable(line, 5, cursor, &variables, MatchMode::Prefix)
+        };
+        let names = |completions: &Completions| -> Vec<(String, &'static str)> {
+            completions
+                .candidates
+                .iter()
+                .map(|c| (c.replacement.clone(), c.suffix))
+                .collect()
+        };
+
+        let completions = complete("echo ${PA", 9).unwrap();
+        assert_eq!((completions.start, completions.word.as_str()), (7, "PA"));
+        assert_eq!(completions.candidates[0].display, "$PAGER");
+        assert_eq!(
+            names(&completions),
+            [
+                ("PAGER".to_owned(), "}"),
+                ("PARENT".to_owned(), "}"),
+                ("PATH".to_owned(), "}")
+            ]
+        );
+
+        let completions = complete("echo a$PA", 9).unwrap();
+        assert_eq!(completions.start, 7);
+        assert_eq!(
+            names(&completions),
+            [
+                ("PAGER".to_owned(), " "),
+                ("PARENT".to_owned(), "/"),
+                ("PATH".to_owned(), " ")
+            ]
+        );
+
+        assert!(complete("echo '$PA", 9).is_none());
+        assert!(complete("echo \\$PA", 9).is_none());
+        assert!(complete("echo $PA-", 9).is_none());
+        assert!(complete("echo PA", 7).is_none());
+    }
+
+    #[test]
+    fn complete_user_names() {
+        let completions = complete_tilde("~roo", "~roo", MatchMode::Prefix).unwrap();
+        assert_eq!((completions.start, completions.word.as_str()), (1, "roo"));
+        let root = &completions.candidates[0];
+        assert_eq!(
+            (
+                root.replacement.as_str(),
+                root.display.as_str(),
+                root.suffix
+            ),
+            ("root", "~root", "/")
+        );
+
+        assert!(complete_tilde("~no-such-user/", "~no-such-user/", MatchMode::Prefix).is_none());
+        assert!(complete_tilde("~a\\ b", "~a b", MatchMode::Prefix).is_none());
+        assert!(complete_tilde("a~", "a~", MatchMode::Prefix).is_none());
+    }
+
+    #[test]
+    fn complete_with_specs() {
+        assert_eq!(command_words("git "), ["git"]);
+        assert_eq!(command_words("ls; git commit "), ["git", "commit"]);
+        assert_eq!(command_words("a | (  make -j "), ["make", "-j"]);
+        assert!(command_words("ls; ").is_empty());
+
+        let mut completer = Completer::new();
+        let words = ["status", "stash", "commit"].map(str::to_owned).to_vec();
+        completer.set_spec("git", CompletionSpec::Words(words));
+        let line = "echo x; git st";
+        let completions =
+            completer.complete(line, line.len(), &ShellNames::default(), &mut |_, _| {
+                String::new()
+            });
+        assert_eq!(completions.start, 12);
+        let replacements: Vec<_> = completions
+            .candidates
+            .iter()
+            .map(|c| c.replacement.as_str())
+            .collect();
+        assert_eq!(replacements, ["stash", "status"]);
+
+        assert!(completer.remove_spec("git"));
+        assert!(!completer.remove_spec("git"));
+
+        completer.set_spec("make", CompletionSpec::Function("_make".to_owned()));
+        let mut calls = Vec::new();
+        let line = "make -j al";
+        let completions = completer.complete(
+            line,
+            line.len(),
+            &ShellNames::default(),
+            &mut |function, args| {
+                calls.push((function.to_owned(), args.to_vec()));
+                "all\nalways\nclean\n".to_owned()
+            },
+        );
+        let replacements: Vec<_> = completions
+            .candidates
+            .iter()
+            .map(|c| c.replacement.as_str())
+            .collect();
+        assert_eq!(replacements, ["all", "always"]);
+        assert_eq!(
+            calls,
+            [(
+                "_make".to_owned(),
+                ["make", "al", "-j"].map(str::to_owned).to_vec()
+            )]
+        );
+    }
+
+    #[test]
+    fn complete_from_definitions() {
+        let definition: Definition = serde_json::from_str(
+            r#"{
+                "flags": ["--help", "--version"],
+                "subcommands": {
+                    "build": { "flags": ["--release"] },
+                    "new": { "files": false }
+                }
+            }"#,
+        )
+        .unwrap();
+        let mut completer = Completer::new();
+        completer
+            .definitions
+            .insert("cargo".to_owned(), Some(definition));
+        let complete = |completer: &mut Completer, line: &str| -> Vec<String> {
+            let completions =
+                completer.complete(line, line.len(), &ShellNames::default(), &mut |_, _| {
+                    String::new()
+                });
+            completions
+                .candidates
+                .into_iter()
+                .map(|c| c.replacement)
+                .collect()
+        };
+
+        assert_eq!(complete(&mut completer, "cargo "), ["build", "new"]);
+        assert_eq!(complete(&mut completer, "cargo -"), ["--help", "--version"]);
+        assert_eq!(complete(&mut completer, "cargo build --r"), ["--release"]);
+        assert!(complete(&mut completer, "cargo new ").is_empty());
+    }
+
+    #[test]
+    fn rank_matches() {
+        assert_eq!(MatchMode::Prefix.rank("gi", "git"), Some(0));
+        assert_eq!(MatchMode::Prefix.rank("it", "git"), None);
+        assert_eq!(MatchMode::Fuzzy.rank("gi", "git"), Some(0));
+        assert_eq!(MatchMode::Fuzzy.rank("it", "git"), Some(2));
+        assert_eq!(MatchMode::Fuzzy.rank("gc", "GitCheckout"), Some(16));
+        assert_eq!(MatchMode::Fuzzy.rank("GC", "gitcheckout"), None);
+        assert_eq!(MatchMode::Fuzzy.rank("gco", "goc"), None);
+
+        let names = ["git-checkout", "nope", "gcxo", "g-c-o", "xgco", "gco-tool"];
+        let candidates = names.map(|name| Candidate {
+            replacement: name.to_owned(),
+            display: name.to_owned(),
+            suffix: " ",
+            file: None,
+        });
+        let ranked = rank_candidates(candidates, "gco", MatchMode::Fuzzy, |candidate| {
+            &candidate.replacement
+        });
+        let ranked: Vec<_> = ranked.iter().map(|c| c.replacement.as_str()).collect();
+        assert_eq!(
+            ranked,
+            ["gco-tool", "xgco", "gcxo", "g-c-o", "git-checkout"]
+        );
+    }
+
+    #[test]
+    fn parse_hosts() {
+        let mut hosts = BTreeSet::new();
+        add_config_hosts(
+            "Host a b\n  HostName a.example.com\nhost=c\nHost *.internal !d e?\nHostName f\n",
+            &mut hosts,
+        );
+        assert_eq!(hosts.iter().collect::<Vec<_>>(), ["a", "b", "c"]);
+
+        let mut hosts = BTreeSet::new();
+        add_known_hosts(
+            "g,10.0.0.1 ssh-ed25519 AAAA\n\
+             [h]:2222 ssh-rsa AAAA\n\
+             |1|c2FsdA==|aGFzaA== ssh-ed25519 AAAA\n\
+             @cert-authority *.example.com ssh-rsa AAAA\n\
+             @revoked i ssh-rsa AAAA\n\
+             # comment\n\
+             \n",
+            &mut hosts,
+        );
+        assert_eq!(
+            hosts.iter().collect::<Vec<_>>(),
+            ["10.0.0.1", "g", "h", "i"]
+        );
+    }
+}
@@ -0,0 +1,71 @@
+//! Errors at positions in scripts, shown with the lines they are on.
+
+/// An error at the byte offset `position` of a script.
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct Diagnostic {
+    pub message: String,
+    pub position: usize,
+    /// A note about another position, e.g. where a quote which is not closed
+    /// starts.
+    pub hint: Option<(usize, String)>,
+}
+
+impl Diagnostic {
+    pub fn new(message: impl Into<String>, position: usize) -> Self {
+        Self {
+            message: message.into(),
+            position,
+            hint: None,
+        }
+    }
+
+    pub fn with_hint(mut self, position: usize, hint: impl Into<String>) -> Self {
+        self.hint = Some((position, hint.into()));
+        self
+    }
+
+    /// Renders the message followed by the line of `script` with the error,
+    /// with a caret under its position, and the same for the hint, e.g.
+    ///
+    /// ```text
+    /// unexpected end of input
+    /// 1 | echo "abc
+    ///   |           ^
+    /// 1 | echo "abc
+    ///   |      ^ unterminated double quote started here
+    /// ```
+    pub fn render(&self, script: &str) -> String {
+        let mut rendered = format!("{}\n", self.message);
+        rendered.push_str(&snippet(script, self.position, ""));
+        if let Some((position, hint)) = &self.hint {
+            rendered.push_str(&snippet(script, *position, hint));
+        }
+        rendered
+    }
+}
+
+/// Returns the line of `script` at `position` after its number, and a caret
+/// under the position followed by `label`.
+fn snippet(script: &str, position: usize, label: &str) -> String {
+    // The end of a script is shown at the end of its last line rather than
+    // on an empty line after it.
+    let position = position.min(script.trim_end_matches('\n').len());
+    let start = script[..position].rfind('\n').map_or(0, |i| i + 1);
+    let end = script[position..]
+        .find('\n')
+        .map_or(script.len(), |i| position + i);
+    let number = (script[..start].matches('\n').count() + 1).to_string();
+    // Tabs are kept so that the caret lines up with the line.
+    let indent: String = script[start..position]
+        .chars()
+        .map(|ch| if ch == '\t' { '\t' } else { ' ' })
+        .collect();
+    let caret = format!("{}^ {}", indent, label);
+    format!(
+        "{} | {}\n{} | {}\n",
+        number,
+        &script[start..end],
+        " ".repeat(number.len()),
+        caret.trim_end()
+    )
+}
@@ -1,22 +1,199 @@
-use std::{io::Write, time::Duration};
+use std::{
+    collections::VecDeque,
+    io::Write,
+    time::{Duration, Instant},
+};
 
-use crossterm::event::{Event as TermEvent, KeyCode, KeyEvent, KeyModifiers};
+use crossterm::event::{
+    DisableBracketedPaste, EnableBracketedPaste, Event as TermEvent, KeyCode, KeyEvent,
+    KeyModifiers,
+};
 use crossterm::terminal::{Clear, ClearType};
 use crossterm::{cursor, execute};
 use crossterm::{
     queue,
-    style::{Attribute, Print, SetAttribute},
+    style::{
+        Attribute, ContentStyle, Print, PrintStyledContent, SetAttribute, StyledContent, Stylize,
+    },
     terminal::{self, disable_raw_mode, enable_raw_mode},
 };
 use tracing::debug;
+use unicode_segmentation::UnicodeSegmentation;
+use unicode_width::UnicodeWidthStr;
 
+use crate::completion::{self, Candidate, Completions};
+use crate::keymap::{EditCommand, EditingMode, Key, Keymap, Lookup};
+use crate::ls_colors::LsColors;
+use crate::parser;
+use crate::process::{self, ExitStatus};
+use crate::render::{FrameBuilder, Renderer};
 use crate::shell::Shell;
+use crate::signal;
+
+/// Characters which separate words for word-wise motions and deletions.
+/// `/` and `-` are included so that path components and flags such as
+/// `--prefix=/usr/local` can be traversed piece by piece.
+const DEFAULT_WORD_DELIMITERS: &str = " \t/-=:;|&<>()'\"";
+
+const KILL_RING_CAPACITY: usize = 16;
+
+/// The upper bound of numeric arguments, so that a mistyped count doesn't
+/// freeze the editor.
+const MAX_NUMERIC_ARGUMENT: usize = 9999;
+
+/// Shown at the beginning of the lines of the input after the first, like
+/// `$PS2` in other shells.
+const CONTINUATION_PROMPT: &str = "> ";
+
+/// Which side of the cursor a kill command removed text from. Consecutive
+/// kills are merged into one entry, so the direction decides whether the new
+/// text is appended or prepended.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+enum KillDirection {
+    Forward,
+    Backward,
+}
+
+/// A readline-like kill ring. The most recent kill is at the front.
+#[derive(Clone, Debug)]
+struct KillRing {
+    entries: VecDeque<String>,
+    /// The entry that was pasted by the last yank, used by `rotate`.
+    yank_index: usize,
+}
+
+impl KillRing {
+    pub fn new() -> Self {
+        Self {
+            entries: VecDeque::with_capacity(KILL_RING_CAPACITY),
+            yank_index: 0,
+        }
+    }
+
+    /// Records killed text. If `merge` is true the text is combined with the
+    /// most recent entry instead of creating a new one.
+    pub fn push(&mut self, text: String, direction: KillDirection, merge: bool) {
+        if text.is_empty() {
+            return;
+        }
+
+        match self.entries.front_mut() {
+            Some(front) if merge => match direction {
+                KillDirection::Forward => front.push_str(&text),
+                KillDirection::Backward => front.insert_str(0, &text),
+            },
+            _ => {
+                if self.entries.len() == KILL_RING_CAPACITY {
+                    self.entries.pop_back();
+                }
+                self.entries.push_front(text);
+            }
+        }
+    }
+
+    /// Returns the most recent kill.
+    pub fn yank(&mut self) -> Option<&str> {
+        self.yank_index = 0;
+        self.entries.front().map(|s| s.as_str())
+    }
+
+    /// Moves to the next older entry, wrapping around at the end.
+    pub fn rotate(&mut self) -> Option<&str> {
+        if self.entries.is_empty() {
+            return None;
+        }
+        self.yank_index = (self.yank_index + 1) % self.entries.len();
+        self.entries.get(self.yank_index).map(|s| s.as_str())
+    }
+}
+
+/// The kind of the previous editing command. Some commands behave differently
+/// depending on what was done right before them.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+enum LastAction {
+    Kill,
+    /// A yank inserted the characters in `start..end`.
+    Yank {
+        start: usize,
+        end: usize,
+    },
+    /// A prefix search of the history, which the next one continues.
+    HistorySearch,
+    Other,
+}
+
+/// The state of an incremental history search (`Ctrl-R`).
+#[derive(Clone, Debug)]
+struct HistorySearch {
+    query: String,
+    /// The history index the search started from. Only older entries
+    /// match.
+    start: usize,
+    /// The matching entry and the byte offset of the match in it.
+    found: Option<(usize, usize)>,
+    /// Whether the last search found nothing, in which case `found` is the
+    /// previous match.
+    failed: bool,
+}
+
+/// The candidates of an ambiguous completion, shown below the input.
+#[derive(Clone, Debug)]
+struct CompletionMenu {
+    completions: Completions,
+    /// The text which was in place of the word when the menu opened,
+    /// restored if it is cancelled.
+    original: String,
+    /// The candidate inserted in place of the word, if any has been chosen.
+    selected: Option<usize>,
+    /// The number of candidates per row, as last drawn.
+    columns: usize,
+}
+
+impl CompletionMenu {
+    /// Moves the selection by `offset` candidates, wrapping around at either
+    /// end.
+    fn select_by(&mut self, offset: isize) {
+        let len = self.completions.candidates.len() as isize;
+        let next = match self.selected {
+            Some(selected) => (selected as isize + offset).rem_euclid(len),
+            None if offset < 0 => len - 1,
+            None => 0,
+        };
+        self.selected = Some(next as usize);
+    }
+}
+
+/// A primitive modification of the input buffer. Every change to the buffer
+/// is recorded as one of these so that it can be undone and redone. `pos` is
+/// a byte offset since grapheme boundaries may move as text is combined.
+#[derive(Clone, Debug, PartialEq, Eq)]
+enum Edit {
+    Insert { pos: usize, text: String },
+    Remove { pos: usize, text: String },
+}
+
+impl Edit {
+    fn inverse(&self) -> Self {
+        match self {
+            Edit::Insert { pos, text } => Edit::Remove {
+                pos: *pos,
+                text: text.clone(),
+            },
+            Edit::Remove { pos, text } => Edit::Insert {
+                pos: *pos,
+                text: text.clone(),
+            },
+        }
+    }
+}
 
 #[derive(Clone, Debug)]
 struct UserInput {
     input: String,
     cursor: usize,
     indices: Vec<usize>,
+    undo_stack: Vec<Edit>,
+    redo_stack: Vec<Edit>,
 }
 
 impl UserInput {
@@ -25,15 +202,14 @@ impl UserInput {
             input: String::with_capacity(256),
             cursor: 0,
             indices: Vec::with_capacity(256),
+            undo_stack: Vec::new(),
+            redo_stack: Vec::new(),
         }
     }
 
+    /// The number of grapheme clusters (not bytes) in the input.
     pub fn len(&self) -> usize {
-        self.input.len()
-    }
-
-    pub fn cursor(&self) -> usize {
-        self.cursor
+        self.indices.len()
     }
 
     pub fn is_empty(&self) -> bool {
@@ -44,46 +220,140 @@ impl UserInput {
         self.input.as_str()
     }
 
-    fn byte_index(&self) -> usize {
-        if self.cursor == self.indices.len() {
-            self.input.len()
-        } else {
-            self.indices[self.cursor]
-        }
+    fn byte_index_of(&self, pos: usize) -> usize {
+        self.indices.get(pos).copied().unwrap_or(self.input.len())
     }
 
     fn update_indices(&mut self) {
         self.indices.clear();
-        for index in self.input.char_indices() {
-            self.indices.push(index.0);
+        for (index, _) in self.input.grapheme_indices(true) {
+            self.indices.push(index);
         }
     }
 
+    /// Converts a byte offset into the index of the grapheme starting there.
+    fn position_of_byte(&self, byte: usize) -> usize {
+        self.indices.partition_point(|&index| index < byte)
+    }
+
+    /// The part of the input before the cursor.
+    pub fn before_cursor(&self) -> &str {
+        &self.input[..self.byte_index_of(self.cursor)]
+    }
+
+    /// Applies `edit` to the buffer without recording it and moves the cursor
+    /// to the end of the affected range.
+    fn apply(&mut self, edit: &Edit) {
+        match edit {
+            Edit::Insert { pos, text } => {
+                self.input.insert_str(*pos, text);
+                self.update_indices();
+                self.cursor = self.position_of_byte(pos + text.len());
+            }
+            Edit::Remove { pos, text } => {
+                self.input.drain(*pos..pos + text.len());
+                self.update_indices();
+                self.cursor = self.position_of_byte(*pos);
+            }
+        }
+    }
+
+    /// Applies `edit` and records it in the undo history. Consecutive
+    /// insertions of adjacent text are merged so that undo removes a whole
+    /// run of typed characters at once.
+    fn perform(&mut self, edit: Edit) {
+        self.apply(&edit);
+        self.redo_stack.clear();
+
+        if let (
+            Some(Edit::Insert {
+                pos: last_pos,
+                text: last_text,
+            }),
+            Edit::Insert { pos, text },
+        ) = (self.undo_stack.last_mut(), &edit)
+        {
+            if *last_pos + last_text.len() == *pos && !last_text.ends_with(' ') {
+                last_text.push_str(text);
+                return;
+            }
+        }
+
+        self.undo_stack.push(edit);
+    }
+
     pub fn insert(&mut self, ch: char) {
-        self.input.insert(self.byte_index(), ch);
-        self.update_indices();
-        self.cursor += 1;
+        self.insert_str(&ch.to_string());
+    }
+
+    pub fn insert_str(&mut self, s: &str) {
+        if !s.is_empty() {
+            self.perform(Edit::Insert {
+                pos: self.byte_index_of(self.cursor),
+                text: s.to_owned(),
+            });
+        }
     }
 
     pub fn delete(&mut self) {
         if self.cursor < self.len() {
-            self.input.remove(self.byte_index());
-            self.update_indices();
+            self.remove_range(self.cursor, self.cursor + 1);
         }
     }
 
     pub fn backspace(&mut self) {
         if self.cursor > 0 {
-            self.cursor -= 1;
-            self.input.remove(self.byte_index());
-            self.update_indices();
+            self.remove_range(self.cursor - 1, self.cursor);
         }
     }
 
+    /// Reverts the most recent edit. Returns `false` if there is nothing to
+    /// undo.
+    pub fn undo(&mut self) -> bool {
+        match self.undo_stack.pop() {
+            Some(edit) => {
+                self.apply(&edit.inverse());
+                self.redo_stack.push(edit);
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Re-applies the most recently undone edit. Returns `false` if there is
+    /// nothing to redo.
+    pub fn redo(&mut self) -> bool {
+        match self.redo_stack.pop() {
+            Some(edit) => {
+                self.apply(&edit);
+                self.undo_stack.push(edit);
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Clears the buffer and its edit history to start a new line.
     pub fn clear(&mut self) {
         self.cursor = 0;
         self.input.clear();
         self.indices.clear();
+        self.undo_stack.clear();
+        self.redo_stack.clear();
+    }
+
+    /// Replaces the whole buffer with `text`, e.g. a history entry, and
+    /// moves the cursor to the end. The undo history is discarded.
+    pub fn set(&mut self, text: &str) {
+        self.clear();
+        self.input.push_str(text);
+        self.update_indices();
+        self.cursor = self.len();
+    }
+
+    /// Moves the cursor to the grapheme at byte offset `byte`.
+    pub fn move_to_byte(&mut self, byte: usize) {
+        self.cursor = self.position_of_byte(byte);
     }
 
     pub fn move_by(&mut self, offset: isize) {
@@ -93,21 +363,400 @@ impl UserInput {
             self.cursor = std::cmp::min(self.len(), self.cursor + offset.unsigned_abs());
         }
     }
+
+    /// Removes the characters in `start..end` and returns them. The cursor is
+    /// placed at `start`.
+    pub fn remove_range(&mut self, start: usize, end: usize) -> String {
+        let text = self.input[self.byte_index_of(start)..self.byte_index_of(end)].to_owned();
+        if !text.is_empty() {
+            self.perform(Edit::Remove {
+                pos: self.byte_index_of(start),
+                text: text.clone(),
+            });
+        }
+        self.cursor = start;
+        text
+    }
+
+    /// Removes the whitespace-delimited word before the cursor, like readline's
+    /// `unix-word-rubout`.
+    pub fn kill_backward_word(&mut self) -> String {
+        let start = self.backward_word_position(" \t");
+        self.remove_range(start, self.cursor)
+    }
+
+    pub fn kill_to_beginning(&mut self) -> String {
+        self.remove_range(0, self.cursor)
+    }
+
+    pub fn kill_to_end(&mut self) -> String {
+        let cursor = self.cursor;
+        self.remove_range(cursor, self.len())
+    }
+
+    pub fn is_blank_at(&self, index: usize) -> bool {
+        self.vi_char_class(index) == 0
+    }
+
+    fn grapheme_at(&self, index: usize) -> &str {
+        &self.input[self.byte_index_of(index)..self.byte_index_of(index + 1)]
+    }
+
+    fn is_delimiter_at(&self, index: usize, delimiters: &str) -> bool {
+        self.grapheme_at(index)
+            .chars()
+            .next()
+            .is_some_and(|ch| delimiters.contains(ch))
+    }
+
+    /// Returns the position of the beginning of the word before the cursor.
+    fn backward_word_position(&self, delimiters: &str) -> usize {
+        self.backward_word_position_from(self.cursor, delimiters)
+    }
+
+    fn backward_word_position_from(&self, mut pos: usize, delimiters: &str) -> usize {
+        while pos > 0 && self.is_delimiter_at(pos - 1, delimiters) {
+            pos -= 1;
+        }
+        while pos > 0 && !self.is_delimiter_at(pos - 1, delimiters) {
+            pos -= 1;
+        }
+        pos
+    }
+
+    /// Returns the position of the end of the word after the cursor.
+    fn forward_word_position(&self, delimiters: &str) -> usize {
+        self.forward_word_position_from(self.cursor, delimiters)
+    }
+
+    fn forward_word_position_from(&self, mut pos: usize, delimiters: &str) -> usize {
+        while pos < self.len() && self.is_delimiter_at(pos, delimiters) {
+            pos += 1;
+        }
+        while pos < self.len() && !self.is_delimiter_at(pos, delimiters) {
+            pos += 1;
+        }
+        pos
+    }
+
+    /// Replaces the characters in `start..end` with `text`, leaving the cursor
+    /// after the replacement.
+    fn replace_range(&mut self, start: usize, end: usize, text: &str) {
+        self.remove_range(start, end);
+        self.insert_str(text);
+    }
+
+    /// Replaces the text from byte offset `start` to the cursor with `text`.
+    pub fn replace_before_cursor(&mut self, start: usize, text: &str) {
+        let start = self.position_of_byte(start);
+        self.replace_range(start, self.cursor, text);
+    }
+
+    /// Swaps the character before the cursor with the one under it and moves
+    /// forward. At the end of the line the last two characters are swapped.
+    pub fn transpose_chars(&mut self) {
+        if self.len() < 2 || self.cursor == 0 {
+            return;
+        }
+
+        let start = if self.cursor == self.len() {
+            self.cursor - 2
+        } else {
+            self.cursor - 1
+        };
+        let swapped = format!("{}{}", self.grapheme_at(start + 1), self.grapheme_at(start));
+        self.replace_range(start, start + 2, &swapped);
+    }
+
+    /// Swaps the word before the cursor with the word after it and moves
+    /// past both. At the end of the line the last two words are swapped.
+    pub fn transpose_words(&mut self, delimiters: &str) {
+        let w2_end = self.forward_word_position_from(self.cursor, delimiters);
+        let w2_start = self.backward_word_position_from(w2_end, delimiters);
+        let w1_start = self.backward_word_position_from(w2_start, delimiters);
+        let w1_end = self.forward_word_position_from(w1_start, delimiters);
+        if w1_start == w2_start || w2_start < w1_end {
+            return;
+        }
+
+        let slice = |start: usize, end: usize| {
+            self.input[self.byte_index_of(start)..self.byte_index_of(end)].to_owned()
+        };
+        let swapped = format!(
+            "{}{}{}",
+            slice(w2_start, w2_end),
+            slice(w1_end, w2_start),
+            slice(w1_start, w1_end)
+        );
+        self.replace_range(w1_start, w2_end, &swapped);
+    }
+
+    /// Converts the text from the cursor to the end of the current word with
+    /// `convert` and moves the cursor past it.
+    pub fn convert_word(&mut self, delimiters: &str, convert: fn(&str) -> String) {
+        let start = self.cursor;
+        let end = self.forward_word_position(delimiters);
+        let text = &self.input[self.byte_index_of(start)..self.byte_index_of(end)];
+        let converted = convert(text);
+        if converted == text {
+            self.cursor = end;
+        } else {
+            // This leaves the cursor after the converted text, whose length
+            // may differ from the original (e.g. `ß` becomes `SS`).
+            self.replace_range(start, end, &converted);
+        }
+    }
+
+    pub fn move_backward_word(&mut self, delimiters: &str) {
+        self.cursor = self.backward_word_position(delimiters);
+    }
+
+    pub fn move_forward_word(&mut self, delimiters: &str) {
+        self.cursor = self.forward_word_position(delimiters);
+    }
+
+    /// Classifies a grapheme the way vi does for word motions: blanks, word
+    /// characters, and other punctuation each form separate words.
+    fn vi_char_class(&self, index: usize) -> u8 {
+        match self.grapheme_at(index).chars().next() {
+            Some(ch) if ch.is_whitespace() => 0,
+            Some(ch) if ch.is_alphanumeric() || ch == '_' => 1,
+            _ => 2,
+        }
+    }
+
+    /// The position `w` moves to: the start of the next word.
+    fn vi_forward_word_position(&self) -> usize {
+        let mut pos = self.cursor;
+        if pos >= self.len() {
+            return pos;
+        }
+        let class = self.vi_char_class(pos);
+        while pos < self.len() && class != 0 && self.vi_char_class(pos) == class {
+            pos += 1;
+        }
+        while pos < self.len() && self.vi_char_class(pos) == 0 {
+            pos += 1;
+        }
+        pos
+    }
+
+    /// The position `b` moves to: the start of the current or previous word.
+    fn vi_backward_word_position(&self) -> usize {
+        let mut pos = self.cursor;
+        while pos > 0 && self.vi_char_class(pos - 1) == 0 {
+            pos -= 1;
+        }
+        if pos > 0 {
+            let class = self.vi_char_class(pos - 1);
+            while pos > 0 && self.vi_char_class(pos - 1) == class {
+                pos -= 1;
+            }
+        }
+        pos
+    }
+
+    /// The position `e` moves to: the last character of the current or next
+    /// word.
+    fn vi_end_word_position(&self) -> usize {
+        let mut pos = self.cursor + 1;
+        while pos < self.len() && self.vi_char_class(pos) == 0 {
+            pos += 1;
+        }
+        if pos >= self.len() {
+            return self.len().saturating_sub(1);
+        }
+        let class = self.vi_char_class(pos);
+        while pos + 1 < self.len() && self.vi_char_class(pos + 1) == class {
+            pos += 1;
+        }
+        pos
+    }
+
+    pub fn move_vi_forward_word(&mut self) {
+        self.cursor = self.vi_forward_word_position();
+    }
+
+    pub fn move_vi_backward_word(&mut self) {
+        self.cursor = self.vi_backward_word_position();
+    }
+
+    pub fn move_vi_end_word(&mut self) {
+        self.cursor = self.vi_end_word_position();
+    }
+
+    /// In vi command mode the cursor sits on a character, so it can't be
+    /// past the end of the line.
+    pub fn clamp_for_vi_command_mode(&mut self) {
+        if self.cursor > 0 && self.cursor >= self.len() {
+            self.cursor = self.len() - 1;
+        }
+    }
+
+    pub fn move_to_beginning(&mut self) {
+        self.cursor = 0;
+    }
+
+    pub fn move_to_end(&mut self) {
+        self.cursor = self.len();
+    }
+}
+
+/// Puts the terminal into the state the line editor needs: raw mode so that
+/// keys are delivered one by one, and bracketed paste so that pasted text is
+/// not interpreted as key bindings.
+fn enter_editing_mode() {
+    enable_raw_mode().ok();
+    execute!(std::io::stdout(), EnableBracketedPaste).ok();
+}
+
+/// Restores the terminal for running commands or exiting the shell.
+fn leave_editing_mode() {
+    execute!(std::io::stdout(), DisableBracketedPaste).ok();
+    disable_raw_mode().ok();
+}
+
+/// Escapes control characters in the input and starts each line after the
+/// first with the continuation prompt.
+fn display_input(s: &str) -> String {
+    escape_control_chars(s).replace('\n', &format!("\n{}", CONTINUATION_PROMPT))
+}
+
+/// Replaces control characters other than newlines with the caret notation
+/// (e.g. `^[` for ESC) so that ones inserted by `quoted-insert` don't
+/// mess up the terminal.
+fn escape_control_chars(s: &str) -> std::borrow::Cow<'_, str> {
+    if !s.chars().any(|ch| ch.is_ascii_control() && ch != '\n') {
+        return std::borrow::Cow::Borrowed(s);
+    }
+
+    let mut escaped = String::with_capacity(s.len() + 8);
+    for ch in s.chars() {
+        match ch {
+            '\n' => escaped.push(ch),
+            '\x7f' => escaped.push_str("^?"),
+            ch if ch.is_ascii_control() => {
+                escaped.push('^');
+                escaped.push((ch as u8 + b'@') as char);
+            }
+            ch => escaped.push(ch),
+        }
+    }
+    std::borrow::Cow::Owned(escaped)
+}
+
+/// Converts a key to the character `quoted-insert` inserts for it.
+fn literal_char(key: Key) -> Option<char> {
+    match key.code {
+        KeyCode::Char(ch) if key.modifiers.contains(KeyModifiers::CONTROL) => {
+            match ch.to_ascii_uppercase() {
+                ch @ '@'..='_' => Some((ch as u8 - b'@') as char),
+                // Ctrl-4 to Ctrl-7 are how the terminal reports \x1c to \x1f.
+                ch @ '4'..='7' => Some((ch as u8 - b'4' + 0x1c) as char),
+                _ => None,
+            }
+        }
+        KeyCode::Char(ch) => Some(ch),
+        KeyCode::Tab => Some('\t'),
+        KeyCode::Enter => Some('\r'),
+        KeyCode::Esc => Some('\x1b'),
+        KeyCode::Backspace => Some('\x7f'),
+        _ => None,
+    }
+}
+
+/// Waits for a key to be pressed, ignoring other events.
+fn read_key() -> Option<Key> {
+    loop {
+        match crossterm::event::read() {
+            Ok(TermEvent::Key(ev)) => return Some(Key::from(&ev)),
+            Ok(_) => (),
+            Err(_) => return None,
+        }
+    }
+}
+
+/// The names to list the candidates by, safe to print.
+fn candidate_names(completions: &Completions) -> Vec<String> {
+    completions
+        .candidates
+        .iter()
+        .map(|candidate| escape_control_chars(&candidate.display).into_owned())
+        .collect()
+}
+
+/// The style to list `candidate` in if it names a file.
+fn file_style(ls_colors: &LsColors, candidate: &Candidate) -> ContentStyle {
+    match &candidate.file {
+        Some(file) => ls_colors.style(file),
+        None => ContentStyle::new(),
+    }
+}
+
+/// Returns the width of a column to list `names` in and how many columns fit
+/// in `columns` with two spaces between them.
+fn column_layout(names: &[String], columns: usize) -> (usize, usize) {
+    let width = names.iter().map(|name| name.width()).max().unwrap_or(0);
+    (width, ((columns + 2) / (width + 2)).max(1))
+}
+
+/// Upper-cases the first alphanumeric character of `word` and lower-cases the
+/// rest.
+fn capitalize(word: &str) -> String {
+    let mut capitalized = String::with_capacity(word.len());
+    let mut seen_alphanumeric = false;
+    for ch in word.chars() {
+        if seen_alphanumeric {
+            capitalized.extend(ch.to_lowercase());
+        } else if ch.is_alphanumeric() {
+            capitalized.extend(ch.to_uppercase());
+            seen_alphanumeric = true;
+        } else {
+            capitalized.push(ch);
+        }
+    }
+    capitalized
 }
 
 pub struct ShellState {
     shell: Shell,
     columns: usize,
     lines: usize,
-    prompt_len: usize,
+    prompt: String,
     input: UserInput,
-    clear_above: usize,
-    clear_below: usize,
+    renderer: Renderer,
+    word_delimiters: String,
+    kill_ring: KillRing,
+    last_action: LastAction,
+    /// The history entry being shown, or `None` for the line being typed.
+    history_index: Option<usize>,
+    /// The partially-typed line, kept while history entries are shown.
+    history_draft: String,
+    /// The text before the cursor when a prefix search of the history
+    /// started, kept while it is repeated.
+    history_prefix: String,
+    /// Set while the candidates of a completion are shown.
+    menu: Option<CompletionMenu>,
+    /// Set while searching the history incrementally.
+    search: Option<HistorySearch>,
+    /// Keys read so far which are a prefix of a multi-key binding.
+    pending_keys: Vec<Key>,
+    /// Whether vi mode is in insert mode rather than command mode.
+    vi_insert: bool,
+    /// A vi operator such as `d` waiting for its motion, with the count
+    /// to apply the motion.
+    pending_operator: Option<(EditCommand, usize)>,
+    /// Set by `quoted-insert` to insert the next key literally.
+    quoted_insert: bool,
+    /// A numeric argument (e.g. `Alt-4`) for the next command.
+    numeric_argument: Option<usize>,
+    /// Set when the user asked to leave the shell.
+    exiting: bool,
 }
 
 impl Drop for ShellState {
     fn drop(&mut self) {
-        disable_raw_mode().ok();
+        leave_editing_mode();
     }
 }
 
@@ -117,29 +766,115 @@ impl ShellState {
             shell,
             columns: 0,
             lines: 0,
-            prompt_len: 0,
+            prompt: String::new(),
             input: UserInput::new(),
-            clear_above: 0,
-            clear_below: 0,
+            renderer: Renderer::new(),
+            word_delimiters: DEFAULT_WORD_DELIMITERS.to_owned(),
+            kill_ring: KillRing::new(),
+            last_action: LastAction::Other,
+            history_index: None,
+            history_draft: String::new(),
+            history_prefix: String::new(),
+            search: None,
+            menu: None,
+            pending_keys: Vec::new(),
+            vi_insert: true,
+            pending_operator: None,
+            quoted_insert: false,
+            numeric_argument: None,
+            exiting: false,
         }
     }
 
+    /// Moves the cursor below the whole input, printing `marker` at the end of
+    /// it, so that subsequent output doesn't overwrite the line.
+    fn finish_line(&mut self, marker: &str) {
+        let mut frame = FrameBuilder::new(self.columns);
+        frame.push_str(&self.prompt);
+        frame.push_str(&display_input(self.input.as_str()));
+        frame.push_str(marker);
+        frame.set_cursor();
+        self.renderer.render(&frame.build());
+        self.renderer.move_below();
+    }
+
     fn run_command(&mut self) {
-        self.print_user_input();
+        self.finish_line("");
+        match self.shell.history().expand(self.input.as_str()) {
+            Ok(Some(expanded)) => {
+                // Show what is going to be run.
+                execute!(
+                    std::io::stdout(),
+                    Print(expanded.replace('\n', "\r\n")),
+                    Print("\r\n")
+                )
+                .ok();
+                self.input.set(&expanded);
+            }
+            Ok(None) => (),
+            Err(err) => {
+                eprint!("toysh: {}\r\n", err);
+                self.reset_history_navigation();
+                self.input.clear();
+                self.vi_insert = true;
+                self.render_prompt();
+                return;
+            }
+        }
 
-        execute!(std::io::stdout(), Print("\r\n")).ok();
-        disable_raw_mode().ok();
-        self.shell.run_script(self.input.as_str());
-        enable_raw_mode().ok();
+        self.shell.history_mut().start(self.input.as_str());
+        self.reset_history_navigation();
+        leave_editing_mode();
+        let started = Instant::now();
+        signal::clear_interrupt();
+        let status = self.shell.run_script(self.input.as_str());
+        debug!(?status);
+        if signal::interrupted() {
+            // Ctrl-C leaves the cursor after `^C`.
+            eprintln!();
+        }
+        std::io::stdout().flush().ok();
+        let ExitStatus::ExitedWith(code) = status;
+        // Show how the line failed, as zsh does with PRINT_EXIT_VALUE.
+        if code != 0 {
+            eprintln!("toysh: exit {}", code);
+        }
+        if let Err(err) = self.shell.history_mut().finish(code, started.elapsed()) {
+            let path = self.shell.history().path().unwrap();
+            eprintln!("toysh: {}: {}", path.display(), err);
+        }
+        self.shell.notify_jobs();
+        enter_editing_mode();
 
         self.input.clear();
-        self.clear_above = 0;
-        self.clear_below = 0;
-
+        self.vi_insert = true;
         self.render_prompt();
-        self.print_user_input();
     }
 
+    /// Opens the current input in `$VISUAL` or `$EDITOR` and runs the edited
+    /// script when the editor exits successfully.
+    fn edit_and_execute(&mut self) {
+        self.finish_line("");
+        leave_editing_mode();
+        let editor = process::editor_from_env(&["VISUAL", "EDITOR"]);
+        let result = process::edit_text(&editor, &format!("{}\n", self.input.as_str()));
+        enter_editing_mode();
+
+        self.input.clear();
+        self.vi_insert = true;
+        match result {
+            Ok(script) => {
+                self.input.insert_str(script.trim_end_matches('\n'));
+                self.run_command();
+            }
+            Err(err) => {
+                eprint!("toysh: {}\r\n", err);
+                self.render_prompt();
+            }
+        }
+    }
+
+    /// Starts a new line: shows the prompt and the input.
     pub fn render_prompt(&mut self) {
         let screen_size = terminal::size().unwrap();
         self.columns = screen_size.0 as usize;
@@ -147,6 +882,9 @@ impl ShellState {
 
         debug!(self.columns);
 
+        // Mark the end of output which lacks a trailing newline and go to a
+        // fresh line: ` $` fills the row, so the carriage return only goes
+        // back to its beginning if the cursor was at the first column.
         let mut stdout = std::io::stdout();
         queue!(
             stdout,
@@ -161,118 +899,1058 @@ impl ShellState {
             ))
         )
         .ok();
+        stdout.flush().ok();
 
-        let mut prompt_str = String::new();
-        let mut prompt_len = 0;
-        prompt_str.push_str(" $ ");
-        queue!(stdout, Print(prompt_str.replace('\n', "\r\n"))).ok();
-        prompt_len += prompt_str.len();
-        stdout.flush().unwrap();
-        self.prompt_len = prompt_len;
+        self.prompt = " $ ".to_owned();
+        self.renderer.reset();
+        self.print_user_input();
     }
 
-    fn print_user_input(&mut self) {
-        let mut stdout = std::io::stdout();
-        queue!(stdout, cursor::Hide).ok();
-
-        queue!(
-            stdout,
-            Print("\r"),
-            cursor::MoveRight(self.prompt_len as u16),
-            Clear(ClearType::UntilNewLine),
-            Print(self.input.input.replace('\n', "\r\n"))
+    /// Clears the whole terminal and redraws the prompt and the input at the
+    /// top.
+    fn clear_screen(&mut self) {
+        execute!(
+            std::io::stdout(),
+            Clear(ClearType::All),
+            cursor::MoveTo(0, 0)
         )
         .ok();
+        self.render_prompt();
+    }
+
+    /// Redraws everything after the terminal is resized, since the terminal
+    /// may have reflowed what was on the screen.
+    fn handle_resize(&mut self, columns: u16, lines: u16) {
+        self.columns = columns as usize;
+        self.lines = lines as usize;
+        self.renderer.invalidate();
+        self.print_user_input();
+    }
+
+    fn print_user_input(&mut self) {
+        let mut frame = FrameBuilder::new(self.columns);
+        if let Some(search) = &self.search {
+            // Show the match in place of the prompt and the input, with the
+            // cursor at the matched text.
+            frame.push_str(&format!(
+                "({}reverse-i-search)`{}': ",
+                if search.failed { "failed " } else { "" },
+                escape_control_chars(&search.query)
+            ));
+            let (line, offset) = match search.found {
+                Some((index, offset)) => (self.shell.history().get(index).unwrap(), offset),
+                None => (self.input.as_str(), self.input.before_cursor().len()),
+            };
+            frame.push_str(&escape_control_chars(&line[..offset]));
+            frame.set_cursor();
+            frame.push_str(&escape_control_chars(&line[offset..]));
+            self.renderer.render(&frame.build());
+            return;
+        }
+
+        frame.push_str(&self.prompt);
+        frame.push_str(&display_input(self.input.before_cursor()));
+        frame.set_cursor();
+        frame.push_str(&display_input(
+            &self.input.as_str()[self.input.before_cursor().len()..],
+        ));
+        if let Some(suggestion) = self.suggestion() {
+            frame.push_styled(&display_input(suggestion), ContentStyle::new().dim());
+        }
+        if self.menu.is_some() {
+            self.push_menu(&mut frame);
+        }
+        self.renderer.render(&frame.build());
+    }
+
+    /// Lays the completion menu out in rows below the input, with as many
+    /// candidates per row as fit. If not all the rows fit on the screen, only
+    /// the ones around the selected candidate are shown.
+    fn push_menu(&mut self, frame: &mut FrameBuilder) {
+        let menu = self.menu.as_mut().unwrap();
+        let names = candidate_names(&menu.completions);
+        let ls_colors = LsColors::from_env();
+        let width;
+        (width, menu.columns) = column_layout(&names, self.columns);
+        let rows = names.len().div_ceil(menu.columns);
+
+        let available = self.lines.saturating_sub(frame.height()).max(1);
+        let (first, shown) = if rows <= available {
+            (0, rows)
+        } else {
+            // Leave a row to tell which rows are shown.
+            let shown = available.saturating_sub(1).max(1);
+            let selected_row = menu.selected.unwrap_or(0) / menu.columns;
+            (selected_row.saturating_sub(shown - 1), shown)
+        };
+
+        for row in first..first + shown {
+            frame.push_str("\n");
+            let start = row * menu.columns;
+            let end = (start + menu.columns).min(names.len());
+            for (i, name) in names.iter().enumerate().take(end).skip(start) {
+                let padding = " ".repeat(width - name.width());
+                if menu.selected == Some(i) {
+                    frame.push_styled(
+                        &format!("{}{}", name, padding),
+                        ContentStyle::new().reverse(),
+                    );
+                } else {
+                    frame.push_styled(
+                        name,
+                        file_style(&ls_colors, &menu.completions.candidates[i]),
+                    );
+                    frame.push_str(&padding);
+                }
+                if i + 1 < end {
+                    frame.push_str("  ");
+                }
+            }
+        }
+        if shown < rows {
+            frame.push_str("\n");
+            frame.push_styled(
+                &format!("rows {} to {} of {}", first + 1, first + shown, rows),
+                ContentStyle::new().dim(),
+            );
+        }
+    }
 
-        let current_x = self.prompt_len + self.input.len();
-        if current_x % self.columns == 0 {
-            queue!(stdout, Print("\r\n")).ok();
+    /// The rest of the newest history entry which starts with the input,
+    /// shown after the cursor when it is at the end of the line.
+    fn suggestion(&self) -> Option<&str> {
+        if self.search.is_some()
+            || self.menu.is_some()
+            || self.input.is_empty()
+            || self.input.before_cursor().len() < self.input.as_str().len()
+        {
+            return None;
         }
+        let line = self.input.as_str();
+        let entry = self.shell.history().suggest(line)?;
+        Some(&entry[line.len()..])
+    }
+
+    /// Inserts the suggestion, or only its first word if `word` is true.
+    /// Returns false if there is no suggestion.
+    fn accept_suggestion(&mut self, word: bool) -> bool {
+        let suggestion = match self.suggestion() {
+            Some(suggestion) => suggestion,
+            None => return false,
+        };
+        let len = if word {
+            let is_delimiter = |ch: char| self.word_delimiters.contains(ch);
+            let start = suggestion
+                .find(|ch| !is_delimiter(ch))
+                .unwrap_or(suggestion.len());
+            suggestion[start..]
+                .find(is_delimiter)
+                .map_or(suggestion.len(), |end| start + end)
+        } else {
+            suggestion.len()
+        };
+        let text = suggestion[..len].to_owned();
+        self.input.insert_str(&text);
+        true
+    }
 
-        let input_height = current_x / self.columns;
-        let cursor_y = (self.prompt_len + self.input.cursor) / self.columns;
-        let cursor_x = (self.prompt_len + self.input.cursor) % self.columns;
-        let cursor_y_diff = input_height - cursor_y;
+    fn kill(&mut self, text: String, direction: KillDirection, last_action: LastAction) {
+        self.kill_ring
+            .push(text, direction, last_action == LastAction::Kill);
+        self.last_action = LastAction::Kill;
+    }
 
-        if cursor_y_diff > 0 {
-            queue!(stdout, cursor::MoveUp(cursor_y_diff as u16)).ok();
+    /// Replaces the input with the history entry before the one being shown,
+    /// saving the line being typed when leaving it.
+    fn previous_history(&mut self) {
+        self.sync_history();
+        let history = self.shell.history();
+        let index = self.history_index.unwrap_or(history.len());
+        if index == 0 {
+            return;
         }
-        queue!(stdout, Print("\r")).ok();
-        if cursor_x > 0 {
-            queue!(stdout, cursor::MoveRight(cursor_x as u16)).ok();
+        if self.history_index.is_none() {
+            self.history_draft = self.input.as_str().to_owned();
         }
+        self.input.set(history.get(index - 1).unwrap());
+        self.history_index = Some(index - 1);
+    }
 
-        queue!(stdout, cursor::Show).ok();
+    /// Replaces the input with the history entry after the one being shown,
+    /// or the saved line when reaching the bottom.
+    fn next_history(&mut self) {
+        let index = match self.history_index {
+            Some(index) => index + 1,
+            None => return,
+        };
+        match self.shell.history().get(index) {
+            Some(entry) => {
+                self.input.set(entry);
+                self.history_index = Some(index);
+            }
+            None => {
+                let draft = std::mem::take(&mut self.history_draft);
+                self.input.set(&draft);
+                self.history_index = None;
+            }
+        }
+    }
+
+    /// Replaces the input with the next older (or newer if `backward` is
+    /// false) entry which starts with the text typed before the cursor.
+    fn search_history_prefix(&mut self, backward: bool, last_action: LastAction) {
+        if last_action != LastAction::HistorySearch {
+            self.history_prefix = self.input.before_cursor().to_owned();
+        }
+        self.last_action = LastAction::HistorySearch;
+
+        self.sync_history();
+        let history = self.shell.history();
+        let mut index = self.history_index;
+        // Skip entries which look the same as the line shown.
+        let found = loop {
+            let found = match (backward, index) {
+                (true, _) => history
+                    .search_prefix_backward(&self.history_prefix, index.unwrap_or(history.len())),
+                (false, Some(index)) => history.search_prefix_forward(&self.history_prefix, index),
+                (false, None) => return,
+            };
+            match found {
+                Some(i) if history.get(i) == Some(self.input.as_str()) => index = Some(i),
+                found => break found,
+            }
+        };
+
+        match found {
+            Some(index) => {
+                if self.history_index.is_none() {
+                    self.history_draft = self.input.as_str().to_owned();
+                }
+                self.input.set(history.get(index).unwrap());
+                self.history_index = Some(index);
+            }
+            None if !backward => {
+                // Back at the bottom.
+                let draft = std::mem::take(&mut self.history_draft);
+                self.input.set(&draft);
+                self.history_index = None;
+            }
+            None => (),
+        }
+    }
+
+    /// Completes the word before the cursor. If there are several
+    /// candidates, inserts what they have in common and shows them in a menu
+    /// to choose from.
+    fn complete(&mut self) {
+        // The completer is taken out of the shell while it calls functions
+        // in it.
+        let mut completer = std::mem::take(self.shell.completer_mut());
+        let names = self.shell.completion_names();
+        let shell = &mut self.shell;
+        let completions = completer.complete(
+            self.input.as_str(),
+            self.input.before_cursor().len(),
+            &names,
+            &mut |function, args| shell.capture_function(function, args),
+        );
+        *self.shell.completer_mut() = completer;
+        match completions.candidates.as_slice() {
+            [] => (),
+            [candidate] => {
+                let text = format!(
+                    "{}{}",
+                    completion::escape(&candidate.replacement),
+                    candidate.suffix
+                );
+                self.input.replace_before_cursor(completions.start, &text);
+            }
+            _ => {
+                let prefix = completions.common_prefix();
+                // Fuzzy matches may not start with the word.
+                if prefix.len() > completions.word.len() && prefix.starts_with(&completions.word) {
+                    let prefix = completion::escape(prefix);
+                    self.input.replace_before_cursor(completions.start, &prefix);
+                }
+                let names = candidate_names(&completions);
+                let (width, per_row) = column_layout(&names, self.columns);
+                let rows = names.len().div_ceil(per_row);
+                if self.renderer.height() + rows > self.lines {
+                    // Too many to choose from in a menu.
+                    let ls_colors = LsColors::from_env();
+                    let cells: Vec<(&String, &Candidate)> =
+                        names.iter().zip(&completions.candidates).collect();
+                    let rows: Vec<String> = cells
+                        .chunks(per_row)
+                        .map(|row| {
+                            let mut text = String::new();
+                            for (i, (name, candidate)) in row.iter().enumerate() {
+                                let style = file_style(&ls_colors, candidate);
+                                text.push_str(&StyledContent::new(style, name).to_string());
+                                if i + 1 < row.len() {
+                                    text.push_str(&" ".repeat(width - name.width() + 2));
+                                }
+                            }
+                            text
+                        })
+                        .collect();
+                    self.print_paged(&rows);
+                    return;
+                }
 
-        self.clear_above = cursor_y;
-        self.clear_below = input_height - cursor_y;
+                let original = self.input.before_cursor()[completions.start..].to_owned();
+                self.menu = Some(CompletionMenu {
+                    completions,
+                    original,
+                    selected: None,
+                    columns: 1,
+                });
+            }
+        }
+    }
 
+    /// Prints `rows` below the input a screenful at a time. At `--More--`,
+    /// Space shows the next screenful, Enter the next row, and any other key
+    /// stops. The input is drawn again after them.
+    fn print_paged(&mut self, rows: &[String]) {
+        self.renderer.move_below();
+        let mut stdout = std::io::stdout();
+        let page = self.lines.saturating_sub(1).max(1);
+        let mut remaining = page;
+        for row in rows {
+            if remaining == 0 {
+                execute!(stdout, PrintStyledContent("--More--".reverse())).ok();
+                let key = read_key();
+                queue!(stdout, Print("\r"), Clear(ClearType::CurrentLine)).ok();
+                match key.map(|key| key.code) {
+                    Some(KeyCode::Char(' ')) => remaining = page,
+                    Some(KeyCode::Enter | KeyCode::Down) => remaining = 1,
+                    _ => break,
+                }
+            }
+            queue!(stdout, Print(row), Print("\r\n")).ok();
+            remaining -= 1;
+        }
         stdout.flush().ok();
     }
 
+    /// Handles a key while the completion menu is shown. `Tab` and the arrow
+    /// keys move the selection and put the candidate in place of the word,
+    /// `Enter` accepts it and `Esc` brings back what was typed. Returns false
+    /// if the key closes the menu and should be handled as usual.
+    fn handle_menu_key(&mut self, key: Key) -> bool {
+        let complete_again =
+            self.current_keymap().lookup(&[key]) == Lookup::Found(EditCommand::Complete);
+        let menu = self.menu.as_mut().unwrap();
+        let columns = menu.columns as isize;
+        match key.code {
+            _ if complete_again => menu.select_by(1),
+            KeyCode::BackTab | KeyCode::Left => menu.select_by(-1),
+            KeyCode::Right => menu.select_by(1),
+            KeyCode::Up => menu.select_by(-columns),
+            KeyCode::Down => menu.select_by(columns),
+            KeyCode::Enter if menu.selected.is_some() => {
+                let menu = self.menu.take().unwrap();
+                let candidate = &menu.completions.candidates[menu.selected.unwrap()];
+                self.input.insert_str(candidate.suffix);
+                self.print_user_input();
+                return true;
+            }
+            _ if key.code == KeyCode::Esc || key == Key::ctrl('g') => {
+                let menu = self.menu.take().unwrap();
+                self.input
+                    .replace_before_cursor(menu.completions.start, &menu.original);
+                self.print_user_input();
+                return true;
+            }
+            _ => {
+                self.menu = None;
+                return false;
+            }
+        }
+
+        let menu = self.menu.as_ref().unwrap();
+        let candidate = &menu.completions.candidates[menu.selected.unwrap()];
+        let text = completion::escape(&candidate.replacement);
+        self.input
+            .replace_before_cursor(menu.completions.start, &text);
+        self.print_user_input();
+        true
+    }
+
+    fn start_history_search(&mut self) {
+        self.sync_history();
+        self.search = Some(HistorySearch {
+            query: String::new(),
+            start: self
+                .history_index
+                .unwrap_or_else(|| self.shell.history().len()),
+            found: None,
+            failed: false,
+        });
+    }
+
+    /// Searches for `query`, starting at the entry before `before`, and
+    /// updates the match shown.
+    fn search_history(&mut self, before: usize) {
+        let search = self.search.as_mut().unwrap();
+        match self.shell.history().search_backward(&search.query, before) {
+            Some(found) => {
+                search.found = Some(found);
+                search.failed = false;
+            }
+            None => search.failed = true,
+        }
+    }
+
+    /// Handles a key while searching the history. Returns false if the key
+    /// ends the search and should be handled as usual.
+    fn handle_search_key(&mut self, key: Key) -> bool {
+        let search_again = self.current_keymap().lookup(&[key])
+            == Lookup::Found(EditCommand::ReverseSearchHistory);
+        let search = self.search.as_mut().unwrap();
+        if search_again {
+            if !search.query.is_empty() {
+                let before = search.found.map_or(search.start, |(index, _)| index);
+                self.search_history(before);
+            }
+        } else if key.code == KeyCode::Esc || key == Key::ctrl('g') {
+            // Cancel, leaving the original line untouched.
+            self.search = None;
+        } else if key.code == KeyCode::Backspace {
+            search.query.pop();
+            search.found = None;
+            search.failed = false;
+            if !search.query.is_empty() {
+                let start = search.start;
+                self.search_history(start);
+            }
+        } else if let Some(ch) = key.printable() {
+            search.query.push(ch);
+            // The current match may still match the longer query.
+            let before = search.found.map_or(search.start, |(index, _)| index + 1);
+            self.search_history(before);
+        } else {
+            self.accept_history_search();
+            return false;
+        }
+        self.print_user_input();
+        true
+    }
+
+    /// Ends the search, replacing the input with the match.
+    fn accept_history_search(&mut self) {
+        let search = match self.search.take() {
+            Some(search) => search,
+            None => return,
+        };
+        if let Some((index, offset)) = search.found {
+            if self.history_index.is_none() {
+                self.history_draft = self.input.as_str().to_owned();
+            }
+            self.input.set(self.shell.history().get(index).unwrap());
+            self.input.move_to_byte(offset);
+            self.history_index = Some(index);
+        }
+    }
+
+    /// Picks up the commands other shells have run since the history file
+    /// was last read. This is done when starting to go through the history,
+    /// so that the entries being shown don't move.
+    fn sync_history(&mut self) {
+        if self.history_index.is_some() {
+            return;
+        }
+        if let Err(err) = self.shell.history_mut().sync() {
+            let path = self.shell.history().path().unwrap();
+            eprint!("toysh: {}: {}\r\n", path.display(), err);
+        }
+    }
+
+    fn reset_history_navigation(&mut self) {
+        self.history_index = None;
+        self.history_draft.clear();
+    }
+
+    fn current_keymap(&self) -> &Keymap {
+        let keymaps = self.shell.keymaps();
+        match keymaps.mode {
+            EditingMode::Emacs => &keymaps.emacs,
+            EditingMode::Vi if self.vi_insert => &keymaps.vi_insert,
+            EditingMode::Vi => &keymaps.vi_command,
+        }
+    }
+
+    fn in_vi_command_mode(&self) -> bool {
+        self.shell.keymaps().mode == EditingMode::Vi && !self.vi_insert
+    }
+
+    /// Adds the digit of `key` to the numeric argument.
+    fn push_digit_argument(&mut self, key: Key) {
+        let digit = match key.code {
+            KeyCode::Char(ch) => ch.to_digit(10).unwrap_or(0) as usize,
+            _ => 0,
+        };
+        let count = self.numeric_argument.unwrap_or(0) * 10 + digit;
+        self.numeric_argument = Some(count.min(MAX_NUMERIC_ARGUMENT));
+    }
+
     pub fn handle_key_event(&mut self, ev: &KeyEvent) {
-        let mut needs_redraw = true;
-        match (ev.code, ev.modifiers) {
-            (KeyCode::Char('c'), KeyModifiers::CONTROL) => {
-                let mut stdout = std::io::stdout();
-                execute!(stdout, Print("\r\n")).ok();
-                self.render_prompt();
+        let key = Key::from(ev);
+        if self.quoted_insert {
+            self.quoted_insert = false;
+            if let Some(ch) = literal_char(key) {
+                self.input.insert(ch);
+            }
+            self.print_user_input();
+            return;
+        }
+
+        if self.search.is_some() && self.handle_search_key(key) {
+            return;
+        }
+        if self.menu.is_some() && self.pending_keys.is_empty() && self.handle_menu_key(key) {
+            return;
+        }
+
+        // Like readline, plain digits typed after a digit argument continue it.
+        if self.pending_keys.is_empty() && self.numeric_argument.is_some() {
+            if let Some('0'..='9') = key.printable() {
+                self.push_digit_argument(key);
+                return;
+            }
+        }
+
+        self.pending_keys.push(key);
+        let lookup = self.current_keymap().lookup(&self.pending_keys);
+        if lookup == Lookup::Prefix {
+            return;
+        }
+
+        let keys = std::mem::take(&mut self.pending_keys);
+        if lookup == Lookup::Found(EditCommand::DigitArgument) {
+            self.push_digit_argument(key);
+            return;
+        }
+
+        let count = self.numeric_argument.take().unwrap_or(1);
+        let needs_redraw = match lookup {
+            Lookup::Found(command) => {
+                if let Some((operator, operator_count)) = self.pending_operator.take() {
+                    self.apply_vi_operator(operator, command, operator_count * count);
+                    true
+                } else if matches!(command, EditCommand::ViDelete | EditCommand::ViChange) {
+                    self.pending_operator = Some((command, count));
+                    true
+                } else {
+                    let repeat = if command.is_repeatable() { count } else { 1 };
+                    let mut needs_redraw = true;
+                    for _ in 0..repeat {
+                        let last_action =
+                            std::mem::replace(&mut self.last_action, LastAction::Other);
+                        needs_redraw = self.execute_edit_command(command, last_action);
+                    }
+                    needs_redraw
+                }
+            }
+            _ => {
+                self.pending_operator = None;
+                self.last_action = LastAction::Other;
+                match keys.as_slice() {
+                    [key] if !self.in_vi_command_mode() => {
+                        if let Some(ch) = key.printable() {
+                            for _ in 0..count {
+                                self.input.insert(ch);
+                            }
+                        }
+                    }
+                    _ => debug!(?keys, "unbound key sequence"),
+                }
+                true
+            }
+        };
+
+        if self.in_vi_command_mode() {
+            self.input.clamp_for_vi_command_mode();
+        }
+
+        if needs_redraw {
+            self.print_user_input();
+        }
+    }
+
+    /// Runs a vi operator (`d` or `c`) over the text the motion `command`
+    /// moves across. Repeating the operator (`dd`, `cc`) applies it to the
+    /// whole line.
+    fn apply_vi_operator(&mut self, operator: EditCommand, command: EditCommand, count: usize) {
+        let (start, end) = if command == operator {
+            (0, self.input.len())
+        } else if command.is_motion() {
+            let origin = self.input.cursor;
+            // Like vi, `cw` on a word changes to the end of it and leaves the
+            // following blanks alone.
+            let command = if operator == EditCommand::ViChange
+                && command == EditCommand::ViForwardWord
+                && !self.input.is_blank_at(origin)
+            {
+                EditCommand::ViEndWord
+            } else {
+                command
+            };
+            for _ in 0..count {
+                self.execute_edit_command(command, LastAction::Other);
+            }
+            let target = self.input.cursor;
+            if command == EditCommand::ViEndWord {
+                // `e` is an inclusive motion.
+                (
+                    origin.min(target),
+                    (origin.max(target) + 1).min(self.input.len()),
+                )
+            } else {
+                (origin.min(target), origin.max(target))
+            }
+        } else {
+            return;
+        };
+
+        let killed = self.input.remove_range(start, end);
+        self.kill(killed, KillDirection::Forward, LastAction::Other);
+        if operator == EditCommand::ViChange {
+            self.vi_insert = true;
+        }
+    }
+
+    /// Runs an editing command. Returns whether the input needs redrawing.
+    fn execute_edit_command(&mut self, command: EditCommand, last_action: LastAction) -> bool {
+        match command {
+            // Keep reading lines until the script is complete, e.g. the
+            // lines of a here document until its delimiter.
+            EditCommand::AcceptLine
+                if matches!(
+                    parser::parse(self.input.as_str()),
+                    Err(parser::ParseError::Incomplete(_))
+                ) =>
+            {
+                self.input.move_to_end();
+                self.input.insert('\n');
+            }
+            EditCommand::AcceptLine => {
+                self.run_command();
+                return false;
+            }
+            EditCommand::Interrupt => {
+                // Discard the line. Raw mode is enabled while editing, so this
+                // is a key press rather than SIGINT.
+                self.finish_line("^C");
+                self.reset_history_navigation();
                 self.input.clear();
+                self.vi_insert = true;
+                self.render_prompt();
+            }
+            EditCommand::Exit => {
+                self.exiting = true;
+                return false;
+            }
+            EditCommand::ClearScreen => self.clear_screen(),
+            EditCommand::BackwardChar => self.input.move_by(-1),
+            EditCommand::ForwardChar => {
+                if !self.accept_suggestion(false) {
+                    self.input.move_by(1);
+                }
             }
-            (KeyCode::Char('d'), KeyModifiers::CONTROL) => {
+            EditCommand::BeginningOfLine => self.input.move_to_beginning(),
+            EditCommand::EndOfLine => {
+                if !self.accept_suggestion(false) {
+                    self.input.move_to_end();
+                }
+            }
+            EditCommand::BackwardWord => self.input.move_backward_word(&self.word_delimiters),
+            EditCommand::ForwardWord => {
+                if !self.accept_suggestion(true) {
+                    self.input.move_forward_word(&self.word_delimiters);
+                }
+            }
+            EditCommand::PreviousHistory => self.previous_history(),
+            EditCommand::NextHistory => self.next_history(),
+            EditCommand::HistorySearchBackward => self.search_history_prefix(true, last_action),
+            EditCommand::HistorySearchForward => self.search_history_prefix(false, last_action),
+            EditCommand::ReverseSearchHistory => self.start_history_search(),
+            EditCommand::Complete => self.complete(),
+            EditCommand::DeleteChar => self.input.delete(),
+            EditCommand::DeleteCharOrEof => {
                 if self.input.is_empty() {
-                    unreachable!();
-                } else {
-                    self.input.delete();
+                    execute!(std::io::stdout(), Print("\r\n")).ok();
+                    self.exiting = true;
+                    return false;
+                }
+                self.input.delete();
+            }
+            EditCommand::BackwardDeleteChar => self.input.backspace(),
+            EditCommand::UnixWordRubout => {
+                let killed = self.input.kill_backward_word();
+                self.kill(killed, KillDirection::Backward, last_action);
+            }
+            EditCommand::UnixLineDiscard => {
+                let killed = self.input.kill_to_beginning();
+                self.kill(killed, KillDirection::Backward, last_action);
+            }
+            EditCommand::KillLine => {
+                let killed = self.input.kill_to_end();
+                self.kill(killed, KillDirection::Forward, last_action);
+            }
+            EditCommand::Yank => {
+                if let Some(text) = self.kill_ring.yank() {
+                    let start = self.input.cursor;
+                    self.input.insert_str(text);
+                    self.last_action = LastAction::Yank {
+                        start,
+                        end: self.input.cursor,
+                    };
                 }
             }
-            (KeyCode::Left, KeyModifiers::NONE) => {
+            EditCommand::YankPop => {
+                // Like readline, `yank-pop` only works right after a yank.
+                if let LastAction::Yank { start, end } = last_action {
+                    if let Some(text) = self.kill_ring.rotate() {
+                        self.input.remove_range(start, end);
+                        self.input.insert_str(text);
+                        self.last_action = LastAction::Yank {
+                            start,
+                            end: self.input.cursor,
+                        };
+                    }
+                }
+            }
+            EditCommand::Undo => {
+                self.input.undo();
+            }
+            EditCommand::Redo => {
+                self.input.redo();
+            }
+            EditCommand::TransposeChars => self.input.transpose_chars(),
+            EditCommand::TransposeWords => self.input.transpose_words(&self.word_delimiters),
+            EditCommand::UpcaseWord => {
+                self.input
+                    .convert_word(&self.word_delimiters, str::to_uppercase);
+            }
+            EditCommand::DowncaseWord => {
+                self.input
+                    .convert_word(&self.word_delimiters, str::to_lowercase);
+            }
+            EditCommand::CapitalizeWord => {
+                self.input.convert_word(&self.word_delimiters, capitalize);
+            }
+            EditCommand::QuotedInsert => self.quoted_insert = true,
+            EditCommand::EditAndExecute => {
+                self.edit_and_execute();
+                return false;
+            }
+            EditCommand::ViCommandMode => {
+                self.vi_insert = false;
                 self.input.move_by(-1);
             }
-            (KeyCode::Right, KeyModifiers::NONE) => {
+            EditCommand::ViInsertMode => self.vi_insert = true,
+            EditCommand::ViAppendMode => {
                 self.input.move_by(1);
+                self.vi_insert = true;
             }
-            // misc
-            (KeyCode::Backspace, KeyModifiers::NONE) => {
-                self.input.backspace();
+            EditCommand::ViInsertBeginningOfLine => {
+                self.input.move_to_beginning();
+                self.vi_insert = true;
             }
-            (KeyCode::Char(ch), KeyModifiers::NONE) => {
-                self.input.insert(ch);
+            EditCommand::ViAppendEndOfLine => {
+                self.input.move_to_end();
+                self.vi_insert = true;
             }
-            (KeyCode::Enter, KeyModifiers::NONE) => {
-                self.run_command();
-                needs_redraw = false;
+            EditCommand::ViForwardWord => self.input.move_vi_forward_word(),
+            EditCommand::ViBackwardWord => self.input.move_vi_backward_word(),
+            EditCommand::ViEndWord => self.input.move_vi_end_word(),
+            // These are handled in `handle_key_event` since they need to know
+            // about the numeric argument.
+            EditCommand::DigitArgument | EditCommand::ViDelete | EditCommand::ViChange => (),
+            EditCommand::ViChangeToEnd => {
+                let killed = self.input.kill_to_end();
+                self.kill(killed, KillDirection::Forward, last_action);
+                self.vi_insert = true;
+            }
+            EditCommand::ViSubstituteChar => {
+                self.input.delete();
+                self.vi_insert = true;
             }
-            (KeyCode::Esc, KeyModifiers::NONE) => {
-                disable_raw_mode().ok();
-                std::process::exit(0);
+            EditCommand::ViPut => {
+                if !self.input.is_empty() {
+                    self.input.move_by(1);
+                }
+                return self.execute_edit_command(EditCommand::Yank, last_action);
             }
-            _ => (),
         }
 
-        if needs_redraw {
-            self.print_user_input();
-        }
+        true
+    }
+
+    /// Inserts pasted text literally. Newlines are kept in the buffer, so a
+    /// multi-line paste runs as one script when Enter is pressed.
+    fn handle_paste(&mut self, text: &str) {
+        let text = text.replace("\r\n", "\n").replace('\r', "\n");
+        self.input.insert_str(&text);
+        self.last_action = LastAction::Other;
+        self.menu = None;
+        self.print_user_input();
     }
 
     pub fn run(&mut self) {
-        enable_raw_mode().ok();
+        enter_editing_mode();
         self.render_prompt();
         debug!("start");
-        loop {
-            match crossterm::event::poll(Duration::from_millis(100)) {
-                Ok(true) => loop {
-                    if let Ok(TermEvent::Key(ev)) = crossterm::event::read() {
-                        self.handle_key_event(&ev)
+        while !self.exiting {
+            if let Ok(true) = crossterm::event::poll(Duration::from_millis(100)) {
+                loop {
+                    match crossterm::event::read() {
+                        Ok(TermEvent::Key(ev)) => self.handle_key_event(&ev),
+                        Ok(TermEvent::Paste(text)) => self.handle_paste(&text),
+                        Ok(TermEvent::Resize(columns, lines)) => self.handle_resize(columns, lines),
+                        _ => (),
                     }
 
+                    if self.exiting {
+                        break;
+                    }
                     match crossterm::event::poll(Duration::from_millis(0)) {
                         Ok(true) => (),
                         _ => break,
                     }
-                },
-                _ => (),
+                }
             }
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn input(text: &str) -> UserInput {
+        let mut input = UserInput::new();
+        for ch in text.chars() {
+            input.insert(ch);
+        }
+        input
+    }
+
+    #[test]
+    fn delete_in_multibyte_input() {
+        let mut input = input("aあいb");
+        assert_eq!(input.len(), 4);
+        input.move_by(-2);
+        input.delete();
+        assert_eq!(input.as_str(), "aあb");
+        input.backspace();
+        assert_eq!(input.as_str(), "ab");
+        input.insert('う');
+        assert_eq!(input.as_str(), "aうb");
+        input.move_by(10);
+        input.delete();
+        assert_eq!(input.as_str(), "aうb");
+        input.move_by(-10);
+        input.backspace();
+        assert_eq!(input.as_str(), "aうb");
+    }
+
+    #[test]
+    fn word_motions() {
+        let mut input = input("ls --color=auto /usr/lib");
+        input.move_backward_word(DEFAULT_WORD_DELIMITERS);
+        assert_eq!(input.cursor, 21);
+        input.move_backward_word(DEFAULT_WORD_DELIMITERS);
+        assert_eq!(input.cursor, 17);
+        input.move_backward_word(DEFAULT_WORD_DELIMITERS);
+        assert_eq!(input.cursor, 11);
+        input.move_backward_word(DEFAULT_WORD_DELIMITERS);
+        assert_eq!(input.cursor, 5);
+        input.move_forward_word(DEFAULT_WORD_DELIMITERS);
+        assert_eq!(input.cursor, 10);
+        input.move_forward_word(DEFAULT_WORD_DELIMITERS);
+        assert_eq!(input.cursor, 15);
+        input.move_to_beginning();
+        input.move_backward_word(DEFAULT_WORD_DELIMITERS);
+        assert_eq!(input.cursor, 0);
+        input.move_to_end();
+        input.move_forward_word(DEFAULT_WORD_DELIMITERS);
+        assert_eq!(input.cursor, 24);
+    }
+
+    #[test]
+    fn kill_words() {
+        let mut input = input("cd /usr/local  ");
+        assert_eq!(input.kill_backward_word(), "/usr/local  ");
+        assert_eq!(input.as_str(), "cd ");
+        input.move_to_beginning();
+        assert_eq!(input.kill_backward_word(), "");
+        assert_eq!(input.remove_range(0, 2), "cd");
+        assert_eq!((input.as_str(), input.cursor), (" ", 0));
+    }
+
+    #[test]
+    fn kill_lines() {
+        let mut input = input("echo hello world");
+        input.move_by(-6);
+        assert_eq!(input.kill_to_end(), " world");
+        assert_eq!(input.kill_to_beginning(), "echo hello");
+        assert!(input.is_empty());
+        input.insert_str("x y");
+        assert_eq!((input.as_str(), input.cursor), ("x y", 3));
+    }
+
+    #[test]
+    fn kill_ring() {
+        let mut ring = KillRing::new();
+        assert_eq!(ring.yank(), None);
+        assert_eq!(ring.rotate(), None);
+        ring.push("one".to_owned(), KillDirection::Forward, false);
+        ring.push(" two".to_owned(), KillDirection::Forward, true);
+        ring.push("zero ".to_owned(), KillDirection::Backward, true);
+        ring.push("three".to_owned(), KillDirection::Forward, false);
+        ring.push(String::new(), KillDirection::Forward, false);
+        assert_eq!(ring.yank(), Some("three"));
+        assert_eq!(ring.rotate(), Some("zero one two"));
+        assert_eq!(ring.rotate(), Some("three"));
+        assert_eq!(ring.yank(), Some("three"));
+
+        for i in 0..KILL_RING_CAPACITY {
+            ring.push(i.to_string(), KillDirection::Forward, false);
+        }
+        assert_eq!(ring.entries.len(), KILL_RING_CAPACITY);
+        assert_eq!(ring.entries.back().map(String::as_str), Some("0"));
+    }
+
+    #[test]
+    fn undo_and_redo() {
+        let mut input = input("echo hello");
+        input.backspace();
+        input.move_to_beginning();
+        input.kill_to_end();
+        assert!(input.undo());
+        assert_eq!(input.as_str(), "echo hell");
+        assert!(input.undo());
+        assert_eq!(input.as_str(), "echo hello");
+        // Typed characters are undone up to and including each space.
+        assert!(input.undo());
+        assert_eq!(input.as_str(), "echo ");
+        assert!(input.undo());
+        assert_eq!(input.as_str(), "");
+        assert!(!input.undo());
+
+        assert!(input.redo());
+        assert!(input.redo());
+        assert_eq!((input.as_str(), input.cursor), ("echo hello", 10));
+        input.insert('!');
+        assert!(!input.redo());
+    }
+
+    #[test]
+    fn transpose() {
+        let mut input = input("abc");
+        input.transpose_chars();
+        assert_eq!((input.as_str(), input.cursor), ("acb", 3));
+        input.move_to_beginning();
+        input.transpose_chars();
+        assert_eq!(input.as_str(), "acb");
+        input.move_by(1);
+        input.transpose_chars();
+        assert_eq!((input.as_str(), input.cursor), ("cab", 2));
+
+        let mut input = self::input("one two three");
+        input.move_by(-7);
+        input.transpose_words(DEFAULT_WORD_DELIMITERS);
+        assert_eq!((input.as_str(), input.cursor), ("two one three", 7));
+        input.move_to_end();
+        input.transpose_words(DEFAULT_WORD_DELIMITERS);
+        assert_eq!(input.as_str(), "two three one");
+
+        let mut input = self::input("word");
+        input.transpose_words(DEFAULT_WORD_DELIMITERS);
+        assert_eq!(input.as_str(), "word");
+    }
+
+    #[test]
+    fn graphemes_and_width() {
+        // `e` and a combining acute accent make one grapheme.
+        let mut input = input("cafe\u{301}あ");
+        assert_eq!(input.len(), 5);
+        input.move_by(-1);
+        assert_eq!(input.before_cursor(), "cafe\u{301}");
+        input.backspace();
+        assert_eq!(input.as_str(), "cafあ");
+        assert!(input.undo());
+        assert_eq!((input.as_str(), input.cursor), ("cafe\u{301}あ", 4));
+        input.move_to_end();
+        input.transpose_chars();
+        assert_eq!(input.as_str(), "cafあe\u{301}");
+    }
+
+    #[test]
+    fn vi_word_motions() {
+        let mut input = input("foo.bar  baz");
+        let mut positions = Vec::new();
+        input.move_to_beginning();
+        for _ in 0..4 {
+            input.move_vi_forward_word();
+            positions.push(input.cursor);
+        }
+        assert_eq!(positions, [3, 4, 9, 12]);
+
+        positions.clear();
+        for _ in 0..4 {
+            input.move_vi_backward_word();
+            positions.push(input.cursor);
+        }
+        assert_eq!(positions, [9, 4, 3, 0]);
+
+        positions.clear();
+        for _ in 0..4 {
+            input.move_vi_end_word();
+            positions.push(input.cursor);
+        }
+        assert_eq!(positions, [2, 3, 6, 11]);
+
+        input.move_to_end();
+        input.clamp_for_vi_command_mode();
+        assert_eq!(input.cursor, 11);
+    }
+
+    #[test]
+    fn convert_words() {
+        let mut input = input("straße hELLO 'wORLD");
+        input.move_to_beginning();
+        input.convert_word(DEFAULT_WORD_DELIMITERS, str::to_uppercase);
+        assert_eq!((input.as_str(), input.cursor), ("STRASSE hELLO 'wORLD", 7));
+        input.convert_word(DEFAULT_WORD_DELIMITERS, str::to_lowercase);
+        assert_eq!((input.as_str(), input.cursor), ("STRASSE hello 'wORLD", 13));
+        input.convert_word(DEFAULT_WORD_DELIMITERS, capitalize);
+        assert_eq!((input.as_str(), input.cursor), ("STRASSE hello 'World", 20));
+        input.move_by(-3);
+        input.convert_word(DEFAULT_WORD_DELIMITERS, str::to_uppercase);
+        assert_eq!((input.as_str(), input.cursor), ("STRASSE hello 'WoRLD", 20));
+        assert_eq!(capitalize("1st-PLACE"), "1st-place");
+    }
+
+    #[test]
+    fn quoted_insert() {
+        assert_eq!(literal_char(Key::ctrl('a')), Some('\x01'));
+        assert_eq!(literal_char(Key::ctrl('[')), Some('\x1b'));
+        assert_eq!(literal_char(Key::ctrl('7')), Some('\x1f'));
+        assert_eq!(literal_char(Key::plain('x')), Some('x'));
+        assert_eq!(
+            literal_char(Key::new(KeyCode::Tab, KeyModifiers::NONE)),
+            Some('\t')
+        );
+        assert_eq!(
+            literal_char(Key::new(KeyCode::Home, KeyModifiers::NONE)),
+            None
+        );
+
+        assert_eq!(escape_control_chars("a\nb"), "a\nb");
+        assert_eq!(escape_control_chars("\x1b[A\x01\x7f"), "^[[A^A^?");
+    }
+}
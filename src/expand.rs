@@ -0,0 +1,581 @@
+//! Expansions of words into the fields which a command gets as arguments:
+//! tildes, variables, command substitutions and arithmetic, the splitting of
+//! their results, and pathnames.
+
+use crate::{
+    arith, glob,
+    parser::{ParameterOp, Span, Word},
+    process::{self, ExitStatus},
+    regex,
+    shell::Shell,
+    users,
+};
+
+/// The characters which separate fields in the results of unquoted
+/// expansions when `$IFS` is unset.
+const DEFAULT_IFS: &str = " \t\n";
+
+/// Expands `words` into fields. Braces make a word for each alternative
+/// first. The results of unquoted expansions are split at the characters in
+/// `$IFS`, so a word can become any number of fields, and then the fields
+/// with patterns are replaced with the paths matching them.
+pub fn expand_words(shell: &mut Shell, words: &[Word]) -> Result<Vec<String>, String> {
+    let mut fields = Fields {
+        pathnames: Some(Pathnames {
+            nullglob: shell.options().nullglob,
+            globstar: shell.options().globstar,
+        }),
+        ..Fields::default()
+    };
+    for word in words.iter().flat_map(expand_braces) {
+        expand_into(shell, &word, &mut fields, true)?;
+        fields.finish();
+    }
+    Ok(fields.fields)
+}
+
+/// Makes a word for each combination of the alternatives of the braces in
+/// `word`, e.g. `a{b,c}{d,e}` becomes `abd`, `abe`, `acd` and `ace`.
+fn expand_braces(word: &Word) -> Vec<Word> {
+    let mut words = vec![Vec::new()];
+    for span in &word.0 {
+        match span {
+            Span::Brace { alternatives, .. } => {
+                let alternatives: Vec<Word> = alternatives.iter().flat_map(expand_braces).collect();
+                words = words
+                    .iter()
+                    .flat_map(|spans| {
+                        alternatives.iter().map(move |Word(alternative)| {
+                            spans.iter().chain(alternative).cloned().collect()
+                        })
+                    })
+                    .collect();
+            }
+            span => {
+                for spans in &mut words {
+                    spans.push(span.clone());
+                }
+            }
+        }
+    }
+    words.into_iter().map(Word).collect()
+}
+
+/// Expands `word` into a single string without splitting it, e.g. for a
+/// here string.
+pub fn expand_word(shell: &mut Shell, word: &Word) -> Result<String, String> {
+    let mut fields = Fields::default();
+    expand_into(shell, word, &mut fields, false)?;
+    fields.finish();
+    Ok(fields.fields.concat())
+}
+
+/// Expands the file name of a redirection, which has to be a single field.
+pub fn expand_path(shell: &mut Shell, word: &Word) -> Result<String, String> {
+    let mut fields = expand_words(shell, std::slice::from_ref(word))?;
+    match fields.len() {
+        1 => Ok(fields.pop().unwrap()),
+        _ => Err("ambiguous redirect".to_owned()),
+    }
+}
+
+fn expand_into(
+    shell: &mut Shell,
+    word: &Word,
+    fields: &mut Fields,
+    split: bool,
+) -> Result<(), String> {
+    for span in &word.0 {
+        match span {
+            Span::Literal(s) | Span::Brace { text: s, .. } => fields.push_unquoted(s),
+            Span::Quoted(s) => fields.push(s),
+            // An unknown user is left alone.
+            Span::Tilde(user) => match users::home_dir(user) {
+                Some(dir) => fields.push(&dir.to_string_lossy()),
+                None => fields.push(&format!("~{}", user)),
+            },
+            // The value has no whitespace to split at.
+            Span::Arithmetic(expr) => {
+                let value = arith::evaluate(expr, shell.variables_mut())?;
+                fields.push(&value.to_string());
+            }
+            Span::Command { script, quoted } => {
+                let output = substitute_command(shell, script)?;
+                push_result(shell, fields, &output, split && !quoted);
+            }
+            Span::Process { script, output } => {
+                fields.push(&shell.substitute_process(script, *output)?);
+            }
+            // `"$@"` makes a field of each positional parameter, and
+            // `"${name[@]}"` of each element.
+            Span::Parameter {
+                name,
+                index,
+                op: None,
+                quoted: true,
+            } if split && (index.as_deref() == Some("@") || (name == "@" && index.is_none())) => {
+                let values = match index {
+                    Some(_) => parameter_values(shell, name),
+                    None => shell.positional().to_vec(),
+                };
+                for (i, value) in values.iter().enumerate() {
+                    if i > 0 {
+                        fields.finish();
+                    }
+                    fields.push(value);
+                }
+            }
+            // `${#name[@]}` is the number of elements.
+            Span::Parameter {
+                name,
+                index: Some(index),
+                op: Some(ParameterOp::Length),
+                ..
+            } if index == "@" || index == "*" => {
+                fields.push(&parameter_values(shell, name).len().to_string());
+            }
+            Span::Parameter {
+                name,
+                index,
+                op,
+                quoted,
+            } => {
+                let (name, value) = match index {
+                    Some(index) => (
+                        format!("{}[{}]", name, index),
+                        indexed_parameter(shell, name, index)?,
+                    ),
+                    None => (name.clone(), parameter(shell, name)),
+                };
+                let name = name.as_str();
+                match op {
+                    Some(op) => {
+                        expand_parameter_op(shell, name, value, op, fields, split && !quoted)?
+                    }
+                    None => {
+                        push_result(shell, fields, &value.unwrap_or_default(), split && !quoted)
+                    }
+                }
+            }
+        }
+    }
+    Ok(())
+}
+
+fn push_result(shell: &Shell, fields: &mut Fields, result: &str, split: bool) {
+    if split {
+        fields.push_split(result, &ifs(shell));
+    } else {
+        fields.push(result);
+    }
+}
+
+/// Expands `${name<op>...}`, where `value` is that of `name`.
+fn expand_parameter_op(
+    shell: &mut Shell,
+    name: &str,
+    value: Option<String>,
+    op: &ParameterOp,
+    fields: &mut Fields,
+    split: bool,
+) -> Result<(), String> {
+    let is_set = |colon: bool| {
+        value
+            .as_ref()
+            .is_some_and(|value| !colon || !value.is_empty())
+    };
+    let result = match op {
+        ParameterOp::Length => value.map_or(0, |value| value.chars().count()).to_string(),
+        ParameterOp::Default { word, colon } => {
+            if !is_set(*colon) {
+                return expand_into(shell, word, fields, split);
+            }
+            value.unwrap()
+        }
+        ParameterOp::Assign { word, colon } => {
+            if is_set(*colon) {
+                value.unwrap()
+            } else if !is_variable_name(name) || name.contains('[') {
+                return Err(format!("${}: cannot assign in this way", name));
+            } else {
+                let value = expand_word(shell, word)?;
+                shell.variables_mut().set(name, value.clone());
+                value
+            }
+        }
+        ParameterOp::Error { word, colon } => {
+            if !is_set(*colon) {
+                let message = expand_word(shell, word)?;
+                if message.is_empty() {
+                    return Err(format!("{}: parameter null or not set", name));
+                }
+                return Err(format!("{}: {}", name, message));
+            }
+            value.unwrap()
+        }
+        ParameterOp::Alternative { word, colon } => {
+            if is_set(*colon) {
+                return expand_into(shell, word, fields, split);
+            }
+            String::new()
+        }
+        ParameterOp::RemovePrefix { pattern, longest } => {
+            let value = value.unwrap_or_default();
+            let pattern = expand_pattern(shell, pattern)?;
+            let mut ends: Vec<usize> = boundaries(&value).collect();
+            if *longest {
+                ends.reverse();
+            }
+            match ends
+                .into_iter()
+                .find(|&end| glob::matches(&pattern, &value[..end]))
+            {
+                Some(end) => value[end..].to_owned(),
+                None => value,
+            }
+        }
+        ParameterOp::RemoveSuffix { pattern, longest } => {
+            let value = value.unwrap_or_default();
+            let pattern = expand_pattern(shell, pattern)?;
+            let mut starts: Vec<usize> = boundaries(&value).collect();
+            if !*longest {
+                starts.reverse();
+            }
+            match starts
+                .into_iter()
+                .find(|&start| glob::matches(&pattern, &value[start..]))
+            {
+                Some(start) => value[..start].to_owned(),
+                None => value,
+            }
+        }
+        ParameterOp::Replace {
+            pattern,
+            string,
+            all,
+        } => {
+            let value = value.unwrap_or_default();
+            let pattern = expand_pattern(shell, pattern)?;
+            let string = expand_word(shell, string)?;
+            replace(&value, &pattern, &string, *all)
+        }
+    };
+    push_result(shell, fields, &result, split);
+    Ok(())
+}
+
+/// Expands `word` into a pattern, in which the characters from quotes only
+/// match themselves.
+pub fn expand_pattern(shell: &mut Shell, word: &Word) -> Result<String, String> {
+    expand_escaping_quotes(shell, word, glob::escape)
+}
+
+/// Expands `word` into a regex for `[[ s =~ regex ]]`, in which the
+/// characters from quotes only match themselves.
+pub fn expand_regex(shell: &mut Shell, word: &Word) -> Result<String, String> {
+    expand_escaping_quotes(shell, word, regex::escape)
+}
+
+fn expand_escaping_quotes(
+    shell: &mut Shell,
+    word: &Word,
+    escape: fn(&str) -> String,
+) -> Result<String, String> {
+    let mut pattern = String::new();
+    for span in &word.0 {
+        let text = expand_word(shell, &Word(vec![span.clone()]))?;
+        match span {
+            Span::Literal(_)
+            | Span::Command { quoted: false, .. }
+            | Span::Parameter { quoted: false, .. } => pattern.push_str(&text),
+            _ => pattern.push_str(&escape(&text)),
+        }
+    }
+    Ok(pattern)
+}
+
+/// Replaces the longest match of `pattern` with `string`, or every one if
+/// `all` is set.
+fn replace(value: &str, pattern: &str, string: &str, all: bool) -> String {
+    if pattern.is_empty() {
+        return value.to_owned();
+    }
+    let mut replaced = String::new();
+    let mut start = 0;
+    while let Some(ch) = value[start..].chars().next() {
+        let end = boundaries(value)
+            .rev()
+            .take_while(|&end| end > start)
+            .find(|&end| glob::matches(pattern, &value[start..end]));
+        match end {
+            Some(end) => {
+                replaced.push_str(string);
+                start = end;
+                if !all {
+                    break;
+                }
+            }
+            None => {
+                replaced.push(ch);
+                start += ch.len_utf8();
+            }
+        }
+    }
+    replaced.push_str(&value[start..]);
+    replaced
+}
+
+/// The positions between the characters of `s`, including both ends.
+fn boundaries(s: &str) -> impl DoubleEndedIterator<Item = usize> + '_ {
+    s.char_indices()
+        .map(|(i, _)| i)
+        .chain(std::iter::once(s.len()))
+}
+
+fn is_variable_name(name: &str) -> bool {
+    name.starts_with(|c: char| c == '_' || c.is_ascii_alphabetic())
+}
+
+/// Returns the elements of the parameter `name` as an array. `PIPESTATUS` is
+/// the only array, and like in bash any other parameter which is set is an
+/// array of its value alone.
+fn parameter_values(shell: &Shell, name: &str) -> Vec<String> {
+    if name != "PIPESTATUS" {
+        return parameter(shell, name).into_iter().collect();
+    }
+    shell
+        .pipe_status()
+        .iter()
+        .map(|ExitStatus::ExitedWith(code)| code.to_string())
+        .collect()
+}
+
+/// Returns `${name[index]}`: all the elements joined like `$@` if `index` is
+/// `@` or `*`, and otherwise the one the arithmetic expression `index`
+/// evaluates to, counted from the end if it is negative.
+fn indexed_parameter(shell: &mut Shell, name: &str, index: &str) -> Result<Option<String>, String> {
+    let values = parameter_values(shell, name);
+    if index == "@" || index == "*" {
+        return Ok((!values.is_empty()).then(|| values.join(" ")));
+    }
+    let i = arith::evaluate(index, shell.variables_mut())?;
+    let i = if i < 0 { i + values.len() as i64 } else { i };
+    Ok(usize::try_from(i).ok().and_then(|i| values.get(i).cloned()))
+}
+
+/// Returns the value of the variable or the special parameter `name`.
+fn parameter(shell: &Shell, name: &str) -> Option<String> {
+    let positional = shell.positional();
+    match name {
+        "$" => Some(std::process::id().to_string()),
+        "0" => Some("toysh".to_owned()),
+        "#" => Some(positional.len().to_string()),
+        "@" => Some(positional.join(" ")),
+        // The positional parameters joined with the first character of
+        // `$IFS`.
+        "*" => {
+            let separator = ifs(shell).chars().next().map(String::from);
+            Some(positional.join(separator.as_deref().unwrap_or("")))
+        }
+        "?" => {
+            let ExitStatus::ExitedWith(code) = shell.last_status();
+            Some(code.to_string())
+        }
+        // Without an index, the statuses are joined like `$@`.
+        "PIPESTATUS" => Some(parameter_values(shell, name).join(" ")),
+        "!" => shell.last_background().map(|pid| pid.to_string()),
+        // The other special parameters are not supported yet.
+        "-" => None,
+        name if is_variable_name(name) => shell.variables().get(name),
+        n => n
+            .parse::<usize>()
+            .ok()
+            .and_then(|n| positional.get(n.checked_sub(1)?).cloned()),
+    }
+}
+
+fn ifs(shell: &Shell) -> String {
+    shell
+        .variables()
+        .get("IFS")
+        .unwrap_or_else(|| DEFAULT_IFS.to_owned())
+}
+
+/// Runs `script` and returns what it prints, without trailing newlines.
+fn substitute_command(shell: &mut Shell, script: &str) -> Result<String, String> {
+    let output = process::capture(|| shell.run_script(script))?;
+    let mut output = String::from_utf8_lossy(&output).into_owned();
+    output.truncate(output.trim_end_matches('\n').len());
+    Ok(output)
+}
+
+/// The fields made so far, and the one being made if any. A field exists
+/// once anything is pushed to it, even if it is an empty string from
+/// quotes, but not if an unquoted expansion results in nothing.
+#[derive(Debug, Default)]
+struct Fields {
+    fields: Vec<String>,
+    current: Option<Field>,
+    /// How the fields are expanded into pathnames, or `None` if they aren't,
+    /// e.g. in here strings.
+    pathnames: Option<Pathnames>,
+}
+
+#[derive(Debug, Default)]
+struct Field {
+    text: String,
+    /// The text as a pattern, where the characters from quotes are escaped.
+    pattern: String,
+    /// Whether there are unquoted `*`, `?` or `[`.
+    magic: bool,
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Pathnames {
+    /// Whether a pattern which matches nothing is removed instead of left as
+    /// it is.
+    nullglob: bool,
+    /// Whether `**` matches directories recursively.
+    globstar: bool,
+}
+
+impl Fields {
+    /// Pushes quoted text, which is not a pattern.
+    fn push(&mut self, s: &str) {
+        let field = self.current.get_or_insert_with(Field::default);
+        field.text.push_str(s);
+        field.pattern.push_str(&glob::escape(s));
+    }
+
+    fn push_unquoted(&mut self, s: &str) {
+        let field = self.current.get_or_insert_with(Field::default);
+        field.text.push_str(s);
+        field.pattern.push_str(s);
+        field.magic |= glob::has_magic(s);
+    }
+
+    /// Pushes `s` ending the current field at the characters in `ifs`. A run
+    /// of whitespace in `ifs` is one separator and is ignored at either end,
+    /// while each of the others separates a field, which can be empty, e.g.
+    /// `a::b` is split into `a`, `` and `b` with `IFS=:`.
+    fn push_split(&mut self, s: &str, ifs: &str) {
+        // Whether whitespace has just ended a field, so that a separator
+        // after it belongs to the same one.
+        let mut after_whitespace = false;
+        for ch in s.chars() {
+            if !ifs.contains(ch) {
+                self.push_unquoted(ch.encode_utf8(&mut [0; 4]));
+                after_whitespace = false;
+            } else if DEFAULT_IFS.contains(ch) {
+                if self.current.is_some() {
+                    self.finish();
+                    after_whitespace = true;
+                }
+            } else {
+                if !after_whitespace {
+                    self.current.get_or_insert_with(Field::default);
+                    self.finish();
+                }
+                after_whitespace = false;
+            }
+        }
+    }
+
+    fn finish(&mut self) {
+        let field = match self.current.take() {
+            Some(field) => field,
+            None => return,
+        };
+        match self.pathnames {
+            Some(pathnames) if field.magic => {
+                let paths = glob::expand(&field.pattern, pathnames.globstar);
+                if !paths.is_empty() {
+                    self.fields.extend(paths);
+                } else if !pathnames.nullglob {
+                    self.fields.push(field.text);
+                }
+            }
+            _ => self.fields.push(field.text),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::{self, Command};
+
+    /// Parses `word` and returns the texts of the words its braces make.
+    fn braces(word: &str) -> Vec<String> {
+        let ast = parser::parse(&format!("echo {}", word)).unwrap();
+        let word = match &ast.terms[0].first.commands[0] {
+            Command::Simple { argv, .. } => argv[1].clone(),
+            command => panic!("{:?} is not a simple command", command),
+        };
+        expand_braces(&word)
+            .into_iter()
+            .map(|Word(spans)| {
+                spans
+                    .into_iter()
+                    .map(|span| match span {
+                        Span::Literal(text) | Span::Quoted(text) => text,
+                        span => panic!("unexpected {:?}", span),
+                    })
+                    .collect()
+            })
+            .collect()
+    }
+
+    #[test]
+    fn brace_lists() {
+        assert_eq!(braces("a{b,c}d"), ["abd", "acd"]);
+        assert_eq!(braces("{a,b}{1,2}"), ["a1", "a2", "b1", "b2"]);
+        assert_eq!(braces("x{,y}"), ["x", "xy"]);
+        assert_eq!(braces("{a,'b c'}"), ["a", "b c"]);
+        assert_eq!(braces("{a}"), ["{a}"]);
+    }
+
+    #[test]
+    fn brace_ranges() {
+        assert_eq!(braces("{1..3}"), ["1", "2", "3"]);
+        assert_eq!(braces("{3..1}"), ["3", "2", "1"]);
+        assert_eq!(braces("{01..03}"), ["01", "02", "03"]);
+        assert_eq!(braces("{-1..1}"), ["-1", "0", "1"]);
+        assert_eq!(braces("{1..7..3}"), ["1", "4", "7"]);
+        assert_eq!(braces("{a..c}"), ["a", "b", "c"]);
+        assert_eq!(braces("f{1..2}.txt"), ["f1.txt", "f2.txt"]);
+        assert_eq!(
+            braces("{-9223372036854775808..9223372036854775807..4611686018427387904}"),
+            [
+                "-9223372036854775808",
+                "-4611686018427387904",
+                "0",
+                "4611686018427387904"
+            ]
+        );
+    }
+
+    #[test]
+    fn huge_brace_ranges_are_literal() {
+        assert_eq!(
+            braces("{-9223372036854775808..9223372036854775807}"),
+            ["{-9223372036854775808..9223372036854775807}"]
+        );
+        assert_eq!(
+            braces("{0..9223372036854775807}"),
+            ["{0..9223372036854775807}"]
+        );
+        assert_eq!(braces("x{1..1000000}"), ["x{1..1000000}"]);
+        assert_eq!(
+            braces("{1..99999999999999999999}"),
+            ["{1..99999999999999999999}"]
+        );
+    }
+
+    #[test]
+    fn nested_braces() {
+        assert_eq!(braces("{a,{b,c}}"), ["a", "b", "c"]);
+        assert_eq!(braces("x{a,b{1,2}}"), ["xa", "xb1", "xb2"]);
+        assert_eq!(braces("{a,{1..2}}z"), ["az", "1z", "2z"]);
+    }
+}
@@ -0,0 +1,340 @@
+//! Shell pattern matching as used by `case`, pathname expansion, and
+//! `$HISTIGNORE`.
+
+use std::{collections::HashSet, os::unix::fs::MetadataExt, path::Path};
+
+/// How deep `**` descends into directories.
+const MAX_GLOBSTAR_DEPTH: usize = 64;
+
+/// Returns whether `text` matches the whole `pattern`.
+///
+/// - `*` matches any string, including the empty one.
+/// - `?` matches any single character.
+/// - `[...]` matches one of the enclosed characters or ranges such as `a-z`.
+///   `[!...]` or `[^...]` matches any other character.
+/// - `\` makes the next character match itself.
+pub fn matches(pattern: &str, text: &str) -> bool {
+    let pattern: Vec<char> = pattern.chars().collect();
+    let text: Vec<char> = text.chars().collect();
+    matches_at(&pattern, &text)
+}
+
+fn matches_at(pattern: &[char], text: &[char]) -> bool {
+    // Where to resume after the last `*`: the pattern after it and the text
+    // position it has consumed up to.
+    let mut backtrack: Option<(usize, usize)> = None;
+    let (mut p, mut t) = (0, 0);
+    while t < text.len() {
+        match pattern.get(p) {
+            Some('*') => {
+                backtrack = Some((p + 1, t));
+                p += 1;
+                continue;
+            }
+            Some('?') => {
+                p += 1;
+                t += 1;
+                continue;
+            }
+            Some('[') => {
+                if let Some((matched, len)) = match_bracket(&pattern[p..], text[t]) {
+                    if matched {
+                        p += len;
+                        t += 1;
+                        continue;
+                    }
+                } else if text[t] == '[' {
+                    // An unterminated bracket matches itself.
+                    p += 1;
+                    t += 1;
+                    continue;
+                }
+            }
+            Some('\\') if pattern.get(p + 1) == Some(&text[t]) => {
+                p += 2;
+                t += 1;
+                continue;
+            }
+            Some(&ch) if ch != '\\' && ch == text[t] => {
+                p += 1;
+                t += 1;
+                continue;
+            }
+            _ => (),
+        }
+
+        // Mismatch: let the last `*` consume one more character.
+        match backtrack {
+            Some((star_p, star_t)) => {
+                backtrack = Some((star_p, star_t + 1));
+                p = star_p;
+                t = star_t + 1;
+            }
+            None => return false,
+        }
+    }
+
+    pattern[p..].iter().all(|&ch| ch == '*')
+}
+
+/// Matches `ch` against the bracket expression at the start of `pattern`.
+/// Returns whether it matched and the length of the expression, or `None` if
+/// the bracket isn't closed.
+fn match_bracket(pattern: &[char], ch: char) -> Option<(bool, usize)> {
+    let mut i = 1;
+    let negated = matches!(pattern.get(i), Some('!' | '^'));
+    if negated {
+        i += 1;
+    }
+
+    let mut matched = false;
+    let mut first = true;
+    loop {
+        let c = *pattern.get(i)?;
+        // `]` right after the opening bracket is a member.
+        if c == ']' && !first {
+            break;
+        }
+        first = false;
+        if pattern.get(i + 1) == Some(&'-') && pattern.get(i + 2).is_some_and(|&end| end != ']') {
+            let end = pattern[i + 2];
+            matched |= c <= ch && ch <= end;
+            i += 3;
+        } else {
+            matched |= c == ch;
+            i += 1;
+        }
+    }
+
+    Some((matched != negated, i + 1))
+}
+
+/// Returns whether `pattern` has any of `*`, `?` and `[` which are not
+/// escaped, so that it has to be matched against file names.
+pub fn has_magic(pattern: &str) -> bool {
+    let mut chars = pattern.chars();
+    while let Some(ch) = chars.next() {
+        match ch {
+            '\\' => {
+                chars.next();
+            }
+            '*' | '?' | '[' => return true,
+            _ => (),
+        }
+    }
+    false
+}
+
+/// Escapes the characters of `text` which are special in patterns, so that
+/// they only match themselves.
+pub fn escape(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for ch in text.chars() {
+        if "*?[\\".contains(ch) {
+            escaped.push('\\');
+        }
+        escaped.push(ch);
+    }
+    escaped
+}
+
+/// Removes the backslashes which escape the characters of `pattern`.
+pub fn unescape(pattern: &str) -> String {
+    let mut unescaped = String::with_capacity(pattern.len());
+    let mut chars = pattern.chars();
+    while let Some(ch) = chars.next() {
+        match ch {
+            '\\' => unescaped.extend(chars.next()),
+            ch => unescaped.push(ch),
+        }
+    }
+    unescaped
+}
+
+/// Returns the paths matching `pattern`, sorted. Each component of the path
+/// is matched against the names in the directory, and a name starting with
+/// `.` only matches if the component starts with `.` as well. With
+/// `globstar`, a `**` component matches any number of directories, or any
+/// path if it is the last one.
+pub fn expand(pattern: &str, globstar: bool) -> Vec<String> {
+    let (mut paths, rest) = match pattern.strip_prefix('/') {
+        Some(rest) => (vec!["/".to_owned()], rest),
+        None => (vec![String::new()], pattern),
+    };
+    let components: Vec<&str> = rest.split('/').collect();
+    for (i, component) in components.iter().enumerate() {
+        let last = i + 1 == components.len();
+        let mut matched = Vec::new();
+        for path in paths {
+            // `dir/` only matches directories.
+            if component.is_empty() {
+                if !last || Path::new(&path).is_dir() {
+                    matched.push(path);
+                }
+                continue;
+            }
+
+            if globstar && *component == "**" {
+                let mut dirs = Vec::new();
+                walk(&path, 0, &mut HashSet::new(), &mut dirs);
+                if !last {
+                    matched.extend(dirs);
+                    continue;
+                }
+                for dir in dirs {
+                    matched.extend(
+                        list(&dir)
+                            .into_iter()
+                            .filter(|name| !name.starts_with('.'))
+                            .map(|name| format!("{}{}", dir, name)),
+                    );
+                }
+                continue;
+            }
+
+            if !has_magic(component) {
+                let path = path + &unescape(component);
+                if !last {
+                    matched.push(path + "/");
+                } else if std::fs::symlink_metadata(&path).is_ok() {
+                    matched.push(path);
+                }
+                continue;
+            }
+
+            for name in list(&path) {
+                if name.starts_with('.') && !component.starts_with('.') {
+                    continue;
+                }
+                if !matches(component, &name) {
+                    continue;
+                }
+                if last {
+                    matched.push(format!("{}{}", path, name));
+                } else if Path::new(&path).join(&name).is_dir() {
+                    matched.push(format!("{}{}/", path, name));
+                }
+            }
+        }
+        paths = matched;
+    }
+    paths.sort();
+    paths
+}
+
+/// Returns the names in the directory `path`, or in the current one if it
+/// is empty.
+fn list(path: &str) -> Vec<String> {
+    let dir = if path.is_empty() { "." } else { path };
+    match std::fs::read_dir(dir) {
+        Ok(entries) => entries
+            .filter_map(Result::ok)
+            .filter_map(|entry| entry.file_name().into_string().ok())
+            .collect(),
+        Err(_) => Vec::new(),
+    }
+}
+
+/// Pushes `path` and the directories under it, except hidden ones, to
+/// `dirs`. Symbolic links are followed, but a directory which has been
+/// `visited` is not entered again so that a link to a parent doesn't loop.
+fn walk(path: &str, depth: usize, visited: &mut HashSet<(u64, u64)>, dirs: &mut Vec<String>) {
+    let dir = if path.is_empty() { "." } else { path };
+    match std::fs::metadata(dir) {
+        Ok(metadata) if metadata.is_dir() && visited.insert((metadata.dev(), metadata.ino())) => (),
+        _ => return,
+    }
+    dirs.push(path.to_owned());
+    if depth >= MAX_GLOBSTAR_DEPTH {
+        return;
+    }
+    for name in list(path) {
+        if !name.starts_with('.') {
+            walk(&format!("{}{}/", path, name), depth + 1, visited, dirs);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Creates a directory for `test` with `files` in it, creating their
+    /// parent directories as well. Returns its path ending with `/`.
+    fn fixture(test: &str, files: &[&str]) -> String {
+        let dir = std::env::temp_dir().join(format!("toysh-glob-{}-{}", test, std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        for file in files {
+            let path = dir.join(file);
+            std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+            std::fs::write(path, "").unwrap();
+        }
+        format!("{}/", dir.display())
+    }
+
+    #[test]
+    fn match_patterns() {
+        assert!(matches("*", ""));
+        assert!(matches("*.rs", "main.rs"));
+        assert!(!matches("*.rs", "main.rsx"));
+        assert!(matches("a*b*c", "aXbYbc"));
+        assert!(matches("?", "x"));
+        assert!(!matches("?", ""));
+        assert!(!matches("??", "x"));
+        assert!(matches("[ab]", "a"));
+        assert!(matches("[ab]", "b"));
+        assert!(!matches("[ab]", "c"));
+        assert!(matches("x[a-c]", "xb"));
+        assert!(!matches("[!ab]", "a"));
+        assert!(matches("[^ab]", "c"));
+        assert!(matches("[]]", "]"));
+        assert!(matches("[a", "[a"));
+        assert!(matches("\\*", "*"));
+        assert!(!matches("\\*", "x"));
+    }
+
+    #[test]
+    fn expand_skips_dotfiles() {
+        let dir = fixture(
+            "dotfiles",
+            &[".hidden", "a.txt", "b.txt", "c.md", "sub/d.txt"],
+        );
+        let expand = |pattern: &str| -> Vec<String> {
+            expand(&format!("{}{}", dir, pattern), false)
+                .into_iter()
+                .map(|path| path[dir.len()..].to_owned())
+                .collect()
+        };
+        assert_eq!(expand("*"), ["a.txt", "b.txt", "c.md", "sub"]);
+        assert_eq!(expand(".*"), [".hidden"]);
+        assert_eq!(expand("[ab].txt"), ["a.txt", "b.txt"]);
+        assert_eq!(expand("*/"), ["sub/"]);
+        assert_eq!(expand("*/*.txt"), ["sub/d.txt"]);
+        assert!(expand("*.rs").is_empty());
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn expand_globstar() {
+        let dir = fixture(
+            "globstar",
+            &["a.rs", "src/b.rs", "src/x/c.rs", "src/x/d.md", ".git/e.rs"],
+        );
+        let expand = |pattern: &str, globstar: bool| -> Vec<String> {
+            expand(&format!("{}{}", dir, pattern), globstar)
+                .into_iter()
+                .map(|path| path[dir.len()..].to_owned())
+                .collect()
+        };
+        assert_eq!(expand("**/*.rs", true), ["a.rs", "src/b.rs", "src/x/c.rs"]);
+        assert_eq!(expand("src/**/*.rs", true), ["src/b.rs", "src/x/c.rs"]);
+        assert_eq!(expand("**/", true), ["", "src/", "src/x/"]);
+        assert_eq!(
+            expand("src/**", true),
+            ["src/b.rs", "src/x", "src/x/c.rs", "src/x/d.md"]
+        );
+        // Without globstar, `**` is just `*`.
+        assert_eq!(expand("**/*.rs", false), ["src/b.rs"]);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
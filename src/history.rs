@@ -0,0 +1,790 @@
+use std::{
+    fs::{File, OpenOptions},
+    io::{self, Read, Seek, SeekFrom, Write},
+    os::unix::{fs::OpenOptionsExt, io::AsRawFd},
+    path::{Path, PathBuf},
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use serde::{Deserialize, Serialize};
+
+use crate::glob;
+
+const HISTORY_FILE_NAME: &str = ".toysh_history";
+
+/// The number of entries kept when `$HISTSIZE` is not set.
+const DEFAULT_HISTORY_SIZE: usize = 500;
+
+/// How much history to keep and which lines to record, configured with the
+/// same environment variables as bash.
+#[derive(Clone, Debug, PartialEq, Eq)]
+struct Settings {
+    /// `$HISTSIZE`: the number of entries kept in memory. `None` means
+    /// unlimited.
+    size: Option<usize>,
+    /// `$HISTFILESIZE`: the number of lines kept in the history file.
+    file_size: Option<usize>,
+    /// `ignoredups` in `$HISTCONTROL`: don't record a line which is the same
+    /// as the previous entry.
+    ignore_dups: bool,
+    /// `ignorespace` in `$HISTCONTROL`: don't record lines starting with a
+    /// space. This is the default when `$HISTCONTROL` is not set, so that
+    /// commands with secrets can be kept out of the history.
+    ignore_space: bool,
+    /// `$HISTIGNORE`: patterns of lines which are not recorded, e.g.
+    /// `*password*:*TOKEN=*`. `&` is the previous entry.
+    ignore_patterns: Vec<String>,
+}
+
+impl Settings {
+    fn from_env() -> Self {
+        Self::from_vars(|name| std::env::var(name).ok())
+    }
+
+    /// Reads the settings from the variables which `var` returns the values
+    /// of.
+    fn from_vars(var: impl Fn(&str) -> Option<String>) -> Self {
+        // Like bash, a negative or non-numeric size means unlimited.
+        let limit = |name: &str| match var(name) {
+            Some(value) => value.parse::<usize>().ok(),
+            None => Some(DEFAULT_HISTORY_SIZE),
+        };
+        let size = limit("HISTSIZE");
+        let file_size = match var("HISTFILESIZE") {
+            Some(_) => limit("HISTFILESIZE"),
+            None => size,
+        };
+
+        let control = var("HISTCONTROL").unwrap_or_else(|| "ignorespace".to_owned());
+        let has = |option: &str| control.split(':').any(|value| value == option);
+        let ignore_patterns = var("HISTIGNORE")
+            .unwrap_or_default()
+            .split(':')
+            .filter(|pattern| !pattern.is_empty())
+            .map(str::to_owned)
+            .collect();
+        Self {
+            size,
+            file_size,
+            ignore_dups: has("ignoredups") || has("ignoreboth"),
+            ignore_space: has("ignorespace") || has("ignoreboth"),
+            ignore_patterns,
+        }
+    }
+}
+
+/// A line in the history and what happened when it was run. Entries read
+/// from a plain history file only have the line and maybe the timestamp.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Entry {
+    pub line: String,
+    /// When the line was run, in seconds since the Unix epoch.
+    #[serde(rename = "time", default, skip_serializing_if = "Option::is_none")]
+    pub timestamp: Option<i64>,
+    /// The working directory the line was run in.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub cwd: Option<PathBuf>,
+    /// The exit status of the line.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub status: Option<i32>,
+    /// How long the line took to run.
+    #[serde(
+        rename = "duration_ms",
+        default,
+        skip_serializing_if = "Option::is_none",
+        with = "duration_ms"
+    )]
+    pub duration: Option<Duration>,
+    /// Identifies the entry being run, see `History::start`.
+    #[serde(skip)]
+    id: u64,
+}
+
+impl Entry {
+    fn new(line: &str) -> Self {
+        let timestamp = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .ok()
+            .map(|elapsed| elapsed.as_secs() as i64);
+        Self {
+            line: line.to_owned(),
+            timestamp,
+            cwd: std::env::current_dir().ok(),
+            status: None,
+            duration: None,
+            id: 0,
+        }
+    }
+}
+
+/// Serializes durations as a number of milliseconds.
+mod duration_ms {
+    use std::time::Duration;
+
+    use serde::{Deserialize, Deserializer, Serializer};
+
+    pub fn serialize<S: Serializer>(duration: &Option<Duration>, s: S) -> Result<S::Ok, S::Error> {
+        match duration {
+            Some(duration) => s.serialize_u64(duration.as_millis() as u64),
+            None => s.serialize_none(),
+        }
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(d: D) -> Result<Option<Duration>, D::Error> {
+        Ok(Option::<u64>::deserialize(d)?.map(Duration::from_millis))
+    }
+}
+
+/// The lines entered so far, oldest first, optionally backed by a file
+/// which each new entry is appended to.
+///
+/// The file has one JSON object per line, each representing an [`Entry`].
+/// Plain history files as bash writes them, with an entry per line optionally
+/// preceded by a `#<epoch>` timestamp, can be read as well. Several shells
+/// may share the file: it is locked while being modified, and the entries
+/// other shells append are picked up by `sync`.
+#[derive(Clone, Debug, Default)]
+pub struct History {
+    entries: Vec<Entry>,
+    /// The number of entries dropped from the front to stay within
+    /// `$HISTSIZE`, so that entry numbers don't change.
+    dropped: usize,
+    path: Option<PathBuf>,
+    /// The number of entries in the history file.
+    file_entries: usize,
+    /// The size of the history file when it was last read or written. What
+    /// comes after it was appended by other shells.
+    file_offset: u64,
+    /// The id of the entry being run, which is written to the file when it
+    /// finishes.
+    running: Option<u64>,
+    next_id: u64,
+}
+
+impl History {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// The file history is kept in: `$HISTFILE`, or `~/.toysh_history`.
+    pub fn default_path() -> Option<PathBuf> {
+        if let Some(path) = std::env::var_os("HISTFILE") {
+            return Some(PathBuf::from(path));
+        }
+        std::env::var_os("HOME").map(|home| Path::new(&home).join(HISTORY_FILE_NAME))
+    }
+
+    /// Reads the entries in `path` and appends new entries to it from now on.
+    /// A missing file is not an error; it is created on the first entry.
+    pub fn load(&mut self, path: PathBuf) -> io::Result<()> {
+        let result = match std::fs::read_to_string(&path) {
+            Ok(contents) => {
+                let entries = parse_entries(&contents);
+                self.file_entries = entries.len();
+                self.file_offset = contents.len() as u64;
+                self.entries.extend(entries);
+                Ok(())
+            }
+            Err(err) if err.kind() == io::ErrorKind::NotFound => Ok(()),
+            Err(err) => Err(err),
+        };
+        self.path = Some(path);
+
+        let settings = Settings::from_env();
+        self.truncate(settings.size);
+        result?;
+        if self.file_entries > settings.file_size.unwrap_or(usize::MAX) {
+            let mut file = self.open_locked()?;
+            self.truncate_file(&mut file, settings.file_size)?;
+        }
+        Ok(())
+    }
+
+    /// Adds the entries other shells have appended to the history file
+    /// since it was last read.
+    pub fn sync(&mut self) -> io::Result<()> {
+        if self.path.is_none() {
+            return Ok(());
+        }
+        let mut file = self.open_locked()?;
+        self.read_new_entries(&mut file)
+    }
+
+    pub fn path(&self) -> Option<&Path> {
+        self.path.as_deref()
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    /// The number of the entry at `index`, as shown by `history` and used by
+    /// `!N`.
+    pub fn number(&self, index: usize) -> usize {
+        self.dropped + index + 1
+    }
+
+    pub fn get(&self, index: usize) -> Option<&str> {
+        self.entries.get(index).map(|entry| entry.line.as_str())
+    }
+
+    pub fn entry(&self, index: usize) -> Option<&Entry> {
+        self.entries.get(index)
+    }
+
+    /// The index of the entry added by `start` which is being run, if it has
+    /// been recorded.
+    pub fn running_index(&self) -> Option<usize> {
+        let id = self.running?;
+        self.entries.iter().rposition(|entry| entry.id == id)
+    }
+
+    /// Finds the newest entry older than `before` which contains `query`.
+    /// Returns the index of the entry and the byte offset of the match in it.
+    pub fn search_backward(&self, query: &str, before: usize) -> Option<(usize, usize)> {
+        self.entries[..before.min(self.entries.len())]
+            .iter()
+            .enumerate()
+            .rev()
+            .find_map(|(index, entry)| entry.line.rfind(query).map(|offset| (index, offset)))
+    }
+
+    /// Finds the newest entry older than `before` which starts with `prefix`.
+    pub fn search_prefix_backward(&self, prefix: &str, before: usize) -> Option<usize> {
+        self.entries[..before.min(self.entries.len())]
+            .iter()
+            .rposition(|entry| entry.line.starts_with(prefix))
+    }
+
+    /// Returns the newest entry which starts with `prefix` and is longer
+    /// than it, to be suggested as the completion of a line being typed.
+    pub fn suggest(&self, prefix: &str) -> Option<&str> {
+        self.entries
+            .iter()
+            .rev()
+            .map(|entry| entry.line.as_str())
+            .find(|line| line.len() > prefix.len() && line.starts_with(prefix))
+    }
+
+    /// Finds the oldest entry newer than `after` which starts with `prefix`.
+    pub fn search_prefix_forward(&self, prefix: &str, after: usize) -> Option<usize> {
+        self.entries
+            .iter()
+            .enumerate()
+            .skip(after + 1)
+            .find(|(_, entry)| entry.line.starts_with(prefix))
+            .map(|(index, _)| index)
+    }
+
+    /// Returns the index of the entry `spec` refers to among the ones
+    /// before `end`: `N` is entry `N`, `-N` is the `N`-th entry back from
+    /// `end`, and anything else is the newest entry starting with `spec`.
+    pub fn find(&self, spec: &str, end: usize) -> Option<usize> {
+        let end = end.min(self.entries.len());
+        match spec.parse::<isize>() {
+            Ok(n) if n < 0 => end.checked_sub(n.unsigned_abs()),
+            Ok(n) if n > 0 => (n as usize - 1)
+                .checked_sub(self.dropped)
+                .filter(|&index| index < end),
+            Ok(_) => None,
+            Err(_) => self.entries[..end]
+                .iter()
+                .rposition(|entry| entry.line.starts_with(spec)),
+        }
+    }
+
+    /// Expands history references in `line`:
+    ///
+    /// - `!!` is the previous command.
+    /// - `!N` is entry `N`, and `!-N` is the `N`-th previous command.
+    /// - `!PREFIX` is the newest command starting with `PREFIX`.
+    /// - `!$` is the last word of the previous command.
+    ///
+    /// Returns `None` if there is nothing to expand. A `!` in single quotes,
+    /// after a backslash, in `$!` or `${!`, or followed by a blank, `=`, `"`
+    /// or an operator such as `;` is left as is.
+    pub fn expand(&self, line: &str) -> Result<Option<String>, String> {
+        let mut expanded = String::new();
+        let mut changed = false;
+        let mut in_single_quotes = false;
+        let mut chars = line.char_indices().peekable();
+        while let Some((i, ch)) = chars.next() {
+            match ch {
+                '\\' if !in_single_quotes => {
+                    expanded.push(ch);
+                    if let Some((_, next)) = chars.next() {
+                        expanded.push(next);
+                    }
+                    continue;
+                }
+                '\'' => in_single_quotes = !in_single_quotes,
+                '!' if !in_single_quotes
+                    && !expanded.ends_with('$')
+                    && !expanded.ends_with("${") =>
+                {
+                    let rest = &line[i + 1..];
+                    if let Some((event, len)) = self.expand_event(rest)? {
+                        expanded.push_str(event);
+                        changed = true;
+                        for _ in 0..len {
+                            chars.next();
+                        }
+                        continue;
+                    }
+                }
+                _ => (),
+            }
+            expanded.push(ch);
+        }
+
+        Ok(changed.then_some(expanded))
+    }
+
+    /// Expands the reference `rest` which follows a `!`. Returns the
+    /// replacement and the number of characters of `rest` it consumed, or
+    /// `None` if `rest` doesn't start a reference.
+    fn expand_event<'a>(&'a self, rest: &str) -> Result<Option<(&'a str, usize)>, String> {
+        let not_found = |event: &str| format!("!{}: event not found", event);
+        let previous = || self.entries.last().map(|entry| entry.line.as_str());
+        let event_len = rest
+            .find(|ch: char| ch.is_whitespace() || ";|&<>()".contains(ch))
+            .unwrap_or(rest.len());
+
+        let (expansion, event) = match rest.chars().next() {
+            None | Some(' ' | '\t' | '\n' | '=' | '"') => return Ok(None),
+            Some('!') => (previous(), "!"),
+            Some('$') => (
+                previous().and_then(|line| line.split_whitespace().last()),
+                "$",
+            ),
+            Some('-' | '0'..='9') => {
+                let digits = rest[1..]
+                    .find(|ch: char| !ch.is_ascii_digit())
+                    .map_or(rest.len(), |end| end + 1);
+                let event = &rest[..digits];
+                let index = self.find(event, self.entries.len());
+                (index.and_then(|index| self.get(index)), event)
+            }
+            // An operator ends the event right away.
+            Some(_) if event_len == 0 => return Ok(None),
+            Some(_) => {
+                let prefix = &rest[..event_len];
+                let index = self.find(prefix, self.entries.len());
+                (index.and_then(|index| self.get(index)), prefix)
+            }
+        };
+
+        match expansion {
+            Some(expansion) => Ok(Some((expansion, event.chars().count()))),
+            None => Err(not_found(event)),
+        }
+    }
+
+    /// Whether `line` should be recorded. Blank lines and the lines
+    /// `$HISTCONTROL` or `$HISTIGNORE` asks to ignore are not.
+    fn should_record(&self, line: &str, settings: &Settings) -> bool {
+        let previous = self.entries.last().map(|entry| entry.line.as_str());
+        let ignored = settings
+            .ignore_patterns
+            .iter()
+            .any(|pattern| match pattern.as_str() {
+                "&" => previous == Some(line),
+                pattern => glob::matches(pattern, line),
+            });
+        !(line.trim().is_empty()
+            || ignored
+            || (settings.ignore_space && line.starts_with(' '))
+            || (settings.ignore_dups && previous == Some(line))
+            || settings.size == Some(0))
+    }
+
+    /// Records `line` and appends it to the history file.
+    pub fn push(&mut self, line: &str) -> io::Result<()> {
+        let settings = Settings::from_env();
+        if !self.should_record(line, &settings) {
+            return Ok(());
+        }
+        self.entries.push(Entry::new(line));
+        self.truncate(settings.size);
+        let entry = self.entries.last().unwrap().clone();
+        self.append(&entry, &settings)
+    }
+
+    /// Records `line` which is about to be run. It is written to the history
+    /// file with its exit status and duration by `finish`.
+    pub fn start(&mut self, line: &str) {
+        let settings = Settings::from_env();
+        if !self.should_record(line, &settings) {
+            return;
+        }
+        self.next_id += 1;
+        self.entries.push(Entry {
+            id: self.next_id,
+            ..Entry::new(line)
+        });
+        self.running = Some(self.next_id);
+        self.truncate(settings.size);
+    }
+
+    /// Records the result of the entry added by `start` and appends it to the
+    /// history file.
+    pub fn finish(&mut self, status: i32, duration: Duration) -> io::Result<()> {
+        let id = match self.running.take() {
+            Some(id) => id,
+            None => return Ok(()),
+        };
+        // The entry may have been deleted while it ran, e.g. by `history -c`.
+        let entry = match self.entries.iter_mut().rev().find(|entry| entry.id == id) {
+            Some(entry) => entry,
+            None => return Ok(()),
+        };
+        entry.status = Some(status);
+        entry.duration = Some(duration);
+        let entry = entry.clone();
+        self.append(&entry, &Settings::from_env())
+    }
+
+    /// Appends `entry` to the history file.
+    fn append(&mut self, entry: &Entry, settings: &Settings) -> io::Result<()> {
+        if self.path.is_none() {
+            return Ok(());
+        }
+
+        // Pick up the entries of other shells first so that the order in
+        // memory is close to the one in the file.
+        let mut file = self.open_locked()?;
+        self.read_new_entries(&mut file)?;
+        write_entry(&mut file, entry)?;
+        self.file_entries += 1;
+        self.file_offset = file.stream_position()?;
+        self.truncate_file(&mut file, settings.file_size)
+    }
+
+    /// Forgets all entries. The history file is left as is.
+    pub fn clear(&mut self) {
+        self.entries.clear();
+        self.dropped = 0;
+    }
+
+    /// Deletes the entry numbered `number`. Returns false if there is no such
+    /// entry.
+    pub fn remove(&mut self, number: usize) -> bool {
+        match number.checked_sub(self.dropped + 1) {
+            Some(index) if index < self.entries.len() => {
+                self.entries.remove(index);
+                true
+            }
+            _ => false,
+        }
+    }
+
+    /// Overwrites the history file with the entries in memory.
+    pub fn write(&mut self) -> io::Result<()> {
+        if self.path.is_none() {
+            return Ok(());
+        }
+
+        let mut file = self.open_locked()?;
+        let size = Settings::from_env().file_size.unwrap_or(usize::MAX);
+        let kept = &self.entries[self.entries.len().saturating_sub(size)..];
+        self.file_entries = kept.len();
+        self.file_offset = rewrite(&mut file, kept)?;
+        // The entry being run has been written.
+        self.running = None;
+        Ok(())
+    }
+
+    /// Drops the oldest entries so that at most `size` remain.
+    fn truncate(&mut self, size: Option<usize>) {
+        if let Some(size) = size {
+            let excess = self.entries.len().saturating_sub(size);
+            self.entries.drain(..excess);
+            self.dropped += excess;
+        }
+    }
+
+    /// Opens the history file for reading and appending, and locks it until
+    /// the returned file is closed. The file is created readable only by the
+    /// user since commands may contain secrets.
+    fn open_locked(&self) -> io::Result<File> {
+        let file = OpenOptions::new()
+            .create(true)
+            .read(true)
+            .append(true)
+            .mode(0o600)
+            .open(self.path.as_ref().unwrap())?;
+        // SAFETY: the file descriptor is valid while `file` is alive.
+        if unsafe { libc::flock(file.as_raw_fd(), libc::LOCK_EX) } != 0 {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(file)
+    }
+
+    /// Reads the entries appended to `file` after `file_offset`.
+    fn read_new_entries(&mut self, file: &mut File) -> io::Result<()> {
+        let len = file.metadata()?.len();
+        if len < self.file_offset {
+            // Another shell rewrote the file. The entries it kept are already
+            // known, though the new ones among them are missed.
+            let mut contents = String::new();
+            file.seek(SeekFrom::Start(0))?;
+            file.read_to_string(&mut contents)?;
+            self.file_entries = parse_entries(&contents).len();
+            self.file_offset = len;
+            return Ok(());
+        }
+
+        let mut contents = String::new();
+        file.seek(SeekFrom::Start(self.file_offset))?;
+        file.read_to_string(&mut contents)?;
+        let entries = parse_entries(&contents);
+        self.file_entries += entries.len();
+        self.file_offset = len;
+        self.entries.extend(entries);
+        self.truncate(Settings::from_env().size);
+        Ok(())
+    }
+
+    /// Removes the oldest entries from the locked history file so that at
+    /// most `size` remain.
+    fn truncate_file(&mut self, file: &mut File, size: Option<usize>) -> io::Result<()> {
+        let size = match size {
+            Some(size) if self.file_entries > size => size,
+            _ => return Ok(()),
+        };
+
+        let mut contents = String::new();
+        file.seek(SeekFrom::Start(0))?;
+        file.read_to_string(&mut contents)?;
+        let entries = parse_entries(&contents);
+        let kept = &entries[entries.len().saturating_sub(size)..];
+        self.file_entries = kept.len();
+        self.file_offset = rewrite(file, kept)?;
+        Ok(())
+    }
+}
+
+/// Reads the entries in the contents of a history file. A line which isn't
+/// a JSON object is a plain entry, and a `#` followed by digits is the
+/// timestamp of the next one.
+fn parse_entries(contents: &str) -> Vec<Entry> {
+    let mut entries = Vec::new();
+    let mut timestamp = None;
+    for line in contents.lines().filter(|line| !line.is_empty()) {
+        if line.starts_with('{') {
+            if let Ok(entry) = serde_json::from_str(line) {
+                entries.push(entry);
+                continue;
+            }
+        }
+        if let Some(Ok(epoch)) = line.strip_prefix('#').map(str::parse) {
+            timestamp = Some(epoch);
+            continue;
+        }
+        entries.push(Entry {
+            line: line.to_owned(),
+            timestamp: timestamp.take(),
+            cwd: None,
+            status: None,
+            duration: None,
+            id: 0,
+        });
+    }
+    entries
+}
+
+fn write_entry(file: &mut impl Write, entry: &Entry) -> io::Result<()> {
+    serde_json::to_writer(&mut *file, entry)?;
+    writeln!(file)
+}
+
+/// Replaces the contents of `file` with `entries` and returns the new size.
+fn rewrite(file: &mut File, entries: &[Entry]) -> io::Result<u64> {
+    // Build the contents first so that the file is never left half written.
+    let mut contents = Vec::new();
+    for entry in entries {
+        write_entry(&mut contents, entry)?;
+    }
+    file.set_len(0)?;
+    file.write_all(&contents)?;
+    Ok(contents.len() as u64)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry(line: &str, timestamp: Option<i64>) -> Entry {
+        Entry {
+            timestamp,
+            cwd: None,
+            ..Entry::new(line)
+        }
+    }
+
+    fn history() -> History {
+        History {
+            entries: ["echo first", "ls -l /tmp", "echo last word"]
+                .into_iter()
+                .map(|line| entry(line, None))
+                .collect(),
+            ..History::default()
+        }
+    }
+
+    fn expand(line: &str) -> Result<Option<String>, String> {
+        history().expand(line)
+    }
+
+    #[test]
+    fn expand_events() {
+        assert_eq!(expand("!!"), Ok(Some("echo last word".to_owned())));
+        assert_eq!(expand("!1 x"), Ok(Some("echo first x".to_owned())));
+        assert_eq!(expand("!-2"), Ok(Some("ls -l /tmp".to_owned())));
+        assert_eq!(expand("!ls; date"), Ok(Some("ls -l /tmp; date".to_owned())));
+        assert_eq!(expand("cat !$"), Ok(Some("cat word".to_owned())));
+        assert_eq!(expand("!nope"), Err("!nope: event not found".to_owned()));
+    }
+
+    #[test]
+    fn expand_leaves_non_events() {
+        for line in [
+            "echo hi",
+            "echo hi!; echo done",
+            "echo hi! there",
+            "echo a!|cat",
+            "echo !(x)",
+            "echo \"hi!\"",
+            "echo 'hi!!'",
+            "echo \\!!",
+            "wait $!; echo $?",
+            "echo ${!x}",
+            "x!=y",
+        ] {
+            assert_eq!(expand(line), Ok(None), "{}", line);
+        }
+    }
+
+    fn settings(vars: &[(&str, &str)]) -> Settings {
+        Settings::from_vars(|name| {
+            vars.iter()
+                .find(|(var, _)| *var == name)
+                .map(|(_, value)| value.to_string())
+        })
+    }
+
+    #[test]
+    fn read_settings() {
+        let defaults = settings(&[]);
+        assert_eq!(defaults.size, Some(DEFAULT_HISTORY_SIZE));
+        assert_eq!(defaults.file_size, Some(DEFAULT_HISTORY_SIZE));
+        assert!(!defaults.ignore_dups && defaults.ignore_space);
+        assert!(defaults.ignore_patterns.is_empty());
+
+        let settings = settings(&[("HISTSIZE", "10"), ("HISTFILESIZE", "-1")]);
+        assert_eq!((settings.size, settings.file_size), (Some(10), None));
+        assert_eq!(self::settings(&[("HISTSIZE", "3")]).file_size, Some(3));
+
+        let settings = self::settings(&[("HISTCONTROL", "ignoreboth")]);
+        assert!(settings.ignore_dups && settings.ignore_space);
+        let settings = self::settings(&[("HISTCONTROL", "erasedups:ignorespace")]);
+        assert!(!settings.ignore_dups && settings.ignore_space);
+        let settings = self::settings(&[("HISTCONTROL", "")]);
+        assert!(!settings.ignore_dups && !settings.ignore_space);
+
+        let settings = self::settings(&[("HISTIGNORE", "&:ls*::[bf]g")]);
+        assert_eq!(settings.ignore_patterns, ["&", "ls*", "[bf]g"]);
+    }
+
+    #[test]
+    fn record_lines() {
+        let history = history();
+        let none = settings(&[("HISTCONTROL", "")]);
+        assert!(history.should_record("echo last word", &none));
+        assert!(history.should_record(" ls", &none));
+        assert!(!history.should_record("  ", &none));
+
+        let dups = settings(&[("HISTCONTROL", "ignoredups")]);
+        assert!(!history.should_record("echo last word", &dups));
+        assert!(history.should_record("echo first", &dups));
+        assert!(history.should_record(" ls", &dups));
+
+        let space = settings(&[("HISTCONTROL", "ignorespace")]);
+        assert!(!history.should_record(" ls", &space));
+        assert!(history.should_record("echo last word", &space));
+
+        let both = settings(&[("HISTCONTROL", "ignoreboth")]);
+        assert!(!history.should_record(" ls", &both));
+        assert!(!history.should_record("echo last word", &both));
+
+        let zero = settings(&[("HISTSIZE", "0")]);
+        assert!(!history.should_record("ls", &zero));
+
+        let ignore = settings(&[("HISTCONTROL", ""), ("HISTIGNORE", "&:ls*:[bf]g")]);
+        assert!(!history.should_record("echo last word", &ignore));
+        assert!(!history.should_record("ls -l", &ignore));
+        assert!(!history.should_record("fg", &ignore));
+        assert!(history.should_record("fgrep x", &ignore));
+        assert!(history.should_record(" ls", &ignore));
+    }
+
+    #[test]
+    fn truncate_keeps_numbers() {
+        let mut history = history();
+        history.truncate(Some(2));
+        assert_eq!(history.len(), 2);
+        assert_eq!(history.expand("!2"), Ok(Some("ls -l /tmp".to_owned())));
+        assert_eq!(history.expand("!1"), Err("!1: event not found".to_owned()));
+        history.truncate(None);
+        assert_eq!(history.len(), 2);
+    }
+
+    #[test]
+    fn parse_timestamps() {
+        let entries = parse_entries("#1700000000\nls\necho hi\n\n#12\n#x\n");
+        assert_eq!(
+            entries,
+            [
+                entry("ls", Some(1700000000)),
+                entry("echo hi", None),
+                entry("#x", Some(12)),
+            ]
+        );
+    }
+
+    #[test]
+    fn json_lines() {
+        let full = Entry {
+            cwd: Some(PathBuf::from("/tmp")),
+            status: Some(1),
+            duration: Some(Duration::from_millis(1500)),
+            id: 3,
+            ..entry("false", Some(1700000000))
+        };
+        let mut file = Vec::new();
+        write_entry(&mut file, &full).unwrap();
+        write_entry(&mut file, &entry("ls", None)).unwrap();
+        let written = String::from_utf8(file).unwrap();
+        assert_eq!(
+            written,
+            concat!(
+                r#"{"line":"false","time":1700000000,"cwd":"/tmp","status":1,"duration_ms":1500}"#,
+                "\n",
+                r#"{"line":"ls"}"#,
+                "\n",
+            )
+        );
+
+        // Plain lines and JSON lines can be mixed, e.g. after upgrading.
+        let contents = format!("#5\necho old\n{}{{broken\n", written);
+        assert_eq!(
+            parse_entries(&contents),
+            [
+                entry("echo old", Some(5)),
+                Entry { id: 0, ..full },
+                entry("ls", None),
+                entry("{broken", None),
+            ]
+        );
+    }
+}
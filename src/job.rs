@@ -0,0 +1,389 @@
+//! Jobs: commands run in the background with `&` or stopped with Ctrl-Z.
+
+use std::fmt;
+
+use crate::{process::ExitStatus, signal};
+
+/// How many statuses of the processes of forgotten jobs are kept, which is
+/// what `CHILD_MAX` is on many systems. The oldest ones are dropped first.
+const MAX_STATUSES: usize = 1024;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum JobState {
+    Running,
+    Stopped,
+    Done(ExitStatus),
+    /// Killed by the signal.
+    Killed(libc::c_int),
+}
+
+impl JobState {
+    /// The status of a process which has finished, which is 128 plus the
+    /// signal number if it has been killed, as in other shells.
+    pub fn status(&self) -> Option<ExitStatus> {
+        match self {
+            JobState::Done(status) => Some(*status),
+            JobState::Killed(signal) => Some(ExitStatus::ExitedWith(128 + signal)),
+            JobState::Running | JobState::Stopped => None,
+        }
+    }
+}
+
+/// Describes the state as other shells do, e.g. `Exit 1` for a job which is
+/// done and has failed, or `Terminated` for one killed by SIGTERM.
+impl fmt::Display for JobState {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            JobState::Running => f.pad("Running"),
+            JobState::Stopped => f.pad("Stopped"),
+            JobState::Done(ExitStatus::ExitedWith(0)) => f.pad("Done"),
+            JobState::Done(ExitStatus::ExitedWith(code)) => f.pad(&format!("Exit {}", code)),
+            JobState::Killed(signal) => f.pad(&signal::description(*signal)),
+        }
+    }
+}
+
+/// A command which runs without the shell waiting for it, in the processes
+/// of the process group `pgid`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Job {
+    /// The number which refers to the job, e.g. in `%1`.
+    pub id: usize,
+    pub pgid: libc::pid_t,
+    pub command: String,
+    /// The processes of the job and their states, e.g. one for each command
+    /// of a pipeline.
+    processes: Vec<(libc::pid_t, JobState)>,
+}
+
+impl Job {
+    /// The processes of the job which have not finished yet.
+    pub fn unfinished_pids(&self) -> Vec<libc::pid_t> {
+        self.processes
+            .iter()
+            .filter(|(_, state)| state.status().is_none())
+            .map(|(pid, _)| *pid)
+            .collect()
+    }
+
+    /// The state of the process `pid` of the job, or `None` if it is not one
+    /// of them.
+    pub fn process_state(&self, pid: libc::pid_t) -> Option<JobState> {
+        self.processes
+            .iter()
+            .find(|(process, _)| *process == pid)
+            .map(|(_, state)| *state)
+    }
+
+    /// The job is stopped if any of its processes is, done once all of them
+    /// are, with the status of the last one, and running otherwise.
+    pub fn state(&self) -> JobState {
+        let states = || self.processes.iter().map(|(_, state)| *state);
+        if states().any(|state| state == JobState::Stopped) {
+            JobState::Stopped
+        } else if states().any(|state| state == JobState::Running) {
+            JobState::Running
+        } else {
+            self.processes
+                .last()
+                .map_or(JobState::Done(ExitStatus::ExitedWith(0)), |(_, state)| {
+                    *state
+                })
+        }
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct Jobs {
+    jobs: Vec<Job>,
+    /// The ids of the jobs from the one started or stopped the longest ago
+    /// to the latest. The latest is the current job, `%+`, and the one before
+    /// it is the previous job, `%-`.
+    recent: Vec<usize>,
+    /// The statuses of the processes of the jobs which have been reported
+    /// as done and forgotten, which `wait` can still return, from the oldest
+    /// to the latest.
+    statuses: Vec<(libc::pid_t, ExitStatus)>,
+}
+
+impl Jobs {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Records a job running `command` in the processes `pids` of the
+    /// process group `pgid`, which becomes the current job. It gets the
+    /// number after the highest one in use, like in other shells.
+    pub fn add(
+        &mut self,
+        pgid: libc::pid_t,
+        pids: &[libc::pid_t],
+        command: &str,
+        state: JobState,
+    ) -> &Job {
+        let id = self.jobs.iter().map(|job| job.id).max().unwrap_or(0) + 1;
+        // The pids may have belonged to processes which are long gone.
+        self.statuses.retain(|(pid, _)| !pids.contains(pid));
+        self.jobs.push(Job {
+            id,
+            pgid,
+            command: command.to_owned(),
+            processes: pids.iter().map(|pid| (*pid, state)).collect(),
+        });
+        self.recent.push(id);
+        self.jobs.last().unwrap()
+    }
+
+    /// The jobs sorted by their numbers.
+    pub fn iter(&self) -> impl Iterator<Item = &Job> {
+        self.jobs.iter()
+    }
+
+    pub fn get(&self, id: usize) -> Option<&Job> {
+        self.jobs.iter().find(|job| job.id == id)
+    }
+
+    pub fn current(&self) -> Option<&Job> {
+        self.recent.last().and_then(|id| self.get(*id))
+    }
+
+    pub fn previous(&self) -> Option<&Job> {
+        let i = self.recent.len().checked_sub(2)?;
+        self.get(self.recent[i])
+    }
+
+    /// Returns `+` for the current job, `-` for the previous one, and a space
+    /// for the others, as they are marked when listed.
+    pub fn mark(&self, id: usize) -> char {
+        if self.current().is_some_and(|job| job.id == id) {
+            '+'
+        } else if self.previous().is_some_and(|job| job.id == id) {
+            '-'
+        } else {
+            ' '
+        }
+    }
+
+    /// Describes the job `id` as `jobs` lists it, e.g.
+    /// `[1]+  Stopped                 vim`.
+    pub fn describe(&self, id: usize) -> Option<String> {
+        let job = self.get(id)?;
+        Some(format!(
+            "[{}]{}  {:<24}{}",
+            id,
+            self.mark(id),
+            job.state(),
+            job.command
+        ))
+    }
+
+    /// Returns the job `spec` refers to: `%N` is the job numbered `N`, `%+`
+    /// or `%%` the current job, `%-` the previous one, `%name` the one whose
+    /// command starts with `name`, and `%?text` the one whose command
+    /// contains `text`.
+    pub fn find(&self, spec: &str) -> Result<&Job, String> {
+        let no_such_job = || format!("{}: no such job", spec);
+        let matching = |matches: &dyn Fn(&Job) -> bool| {
+            let mut jobs = self.jobs.iter().filter(|job| matches(job));
+            match (jobs.next(), jobs.next()) {
+                (Some(job), None) => Ok(job),
+                (Some(_), Some(_)) => Err(format!("{}: ambiguous job spec", spec)),
+                (None, _) => Err(no_such_job()),
+            }
+        };
+        let rest = spec
+            .strip_prefix('%')
+            .ok_or_else(|| format!("{}: not a job spec", spec))?;
+        match rest {
+            "" | "+" | "%" => self.current().ok_or_else(no_such_job),
+            "-" => self.previous().ok_or_else(no_such_job),
+            _ => match rest.parse::<usize>() {
+                Ok(id) => self.get(id).ok_or_else(no_such_job),
+                Err(_) => match rest.strip_prefix('?') {
+                    Some(text) => matching(&|job| job.command.contains(text)),
+                    None => matching(&|job| job.command.starts_with(rest)),
+                },
+            },
+        }
+    }
+
+    /// The processes of the jobs which have not finished yet.
+    pub fn unfinished_pids(&self) -> Vec<libc::pid_t> {
+        self.jobs.iter().flat_map(Job::unfinished_pids).collect()
+    }
+
+    /// Makes the job `id` the current one, e.g. when it is stopped again.
+    pub fn make_current(&mut self, id: usize) {
+        self.recent.retain(|recent| *recent != id);
+        self.recent.push(id);
+    }
+
+    /// Records that the process `pid` is now in `state`, and returns the job
+    /// it belongs to if any.
+    pub fn update(&mut self, pid: libc::pid_t, state: JobState) -> Option<&Job> {
+        let job = self
+            .jobs
+            .iter_mut()
+            .find(|job| job.processes.iter().any(|(process, _)| *process == pid))?;
+        for (process, process_state) in &mut job.processes {
+            if *process == pid {
+                *process_state = state;
+            }
+        }
+        Some(job)
+    }
+
+    /// Forgets the job `id` once it is done and has been reported, but keeps
+    /// the statuses of its processes for [`Jobs::take_status`].
+    pub fn retire(&mut self, id: usize) {
+        if let Some(job) = self.remove(id) {
+            self.statuses.extend(
+                job.processes
+                    .iter()
+                    .filter_map(|(pid, state)| Some((*pid, state.status()?))),
+            );
+            let excess = self.statuses.len().saturating_sub(MAX_STATUSES);
+            self.statuses.drain(..excess);
+        }
+    }
+
+    /// Returns the status of the process `pid` of a job which has been
+    /// retired, and forgets it.
+    pub fn take_status(&mut self, pid: libc::pid_t) -> Option<ExitStatus> {
+        let i = self
+            .statuses
+            .iter()
+            .position(|(process, _)| *process == pid)?;
+        Some(self.statuses.remove(i).1)
+    }
+
+    /// Forgets the job `id`, e.g. once `wait` has returned its status.
+    pub fn remove(&mut self, id: usize) -> Option<Job> {
+        let i = self.jobs.iter().position(|job| job.id == id)?;
+        self.recent.retain(|recent| *recent != id);
+        Some(self.jobs.remove(i))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn jobs() -> Jobs {
+        let mut jobs = Jobs::new();
+        jobs.add(10, &[10], "sleep 10", JobState::Running);
+        jobs.add(20, &[20, 21], "vim notes | cat", JobState::Running);
+        jobs.add(30, &[30], "sleep 30", JobState::Running);
+        jobs
+    }
+
+    #[test]
+    fn find_by_spec() {
+        let jobs = jobs();
+        let id = |spec| jobs.find(spec).map(|job| job.id);
+        assert_eq!(id("%2"), Ok(2));
+        assert_eq!(id("%%"), Ok(3));
+        assert_eq!(id("%+"), Ok(3));
+        assert_eq!(id("%-"), Ok(2));
+        assert_eq!(id("%vim"), Ok(2));
+        assert_eq!(id("%?notes"), Ok(2));
+        assert_eq!(id("%sleep"), Err("%sleep: ambiguous job spec".to_owned()));
+        assert_eq!(id("%4"), Err("%4: no such job".to_owned()));
+        assert_eq!(id("2"), Err("2: not a job spec".to_owned()));
+    }
+
+    #[test]
+    fn state_of_processes() {
+        let mut jobs = jobs();
+        let state = |jobs: &Jobs| jobs.get(2).unwrap().state();
+        jobs.update(21, JobState::Done(ExitStatus::ExitedWith(0)));
+        assert_eq!(state(&jobs), JobState::Running);
+        jobs.update(20, JobState::Stopped);
+        assert_eq!(state(&jobs), JobState::Stopped);
+        jobs.update(20, JobState::Done(ExitStatus::ExitedWith(1)));
+        assert_eq!(state(&jobs), JobState::Done(ExitStatus::ExitedWith(0)));
+    }
+
+    #[test]
+    fn state_of_process() {
+        let mut jobs = jobs();
+        jobs.update(21, JobState::Stopped);
+        let job = jobs.get(2).unwrap();
+        assert_eq!(job.process_state(20), Some(JobState::Running));
+        assert_eq!(job.process_state(21), Some(JobState::Stopped));
+        assert_eq!(job.process_state(10), None);
+    }
+
+    #[test]
+    fn status_of_killed_jobs() {
+        let mut jobs = jobs();
+        jobs.update(30, JobState::Killed(libc::SIGTERM));
+        let job = jobs.get(3).unwrap();
+        assert_eq!(job.state(), JobState::Killed(libc::SIGTERM));
+        assert_eq!(job.state().status(), Some(ExitStatus::ExitedWith(143)));
+        assert_eq!(jobs.get(1).unwrap().state().status(), None);
+    }
+
+    #[test]
+    fn describe_states() {
+        let mut jobs = jobs();
+        jobs.update(10, JobState::Done(ExitStatus::ExitedWith(0)));
+        jobs.update(20, JobState::Stopped);
+        jobs.update(30, JobState::Killed(libc::SIGTERM));
+        assert_eq!(
+            jobs.describe(1).unwrap(),
+            "[1]   Done                    sleep 10"
+        );
+        assert_eq!(
+            jobs.describe(2).unwrap(),
+            "[2]-  Stopped                 vim notes | cat"
+        );
+        assert_eq!(
+            jobs.describe(3).unwrap(),
+            "[3]+  Terminated              sleep 30"
+        );
+    }
+
+    #[test]
+    fn retire_keeps_latest_statuses() {
+        let mut jobs = Jobs::new();
+        for pid in 1..=MAX_STATUSES as libc::pid_t + 2 {
+            jobs.add(
+                pid,
+                &[pid],
+                "true",
+                JobState::Done(ExitStatus::ExitedWith(0)),
+            );
+            let id = jobs.current().unwrap().id;
+            jobs.retire(id);
+        }
+        assert_eq!(jobs.statuses.len(), MAX_STATUSES);
+        assert_eq!(jobs.statuses[0].0, 3);
+
+        // A status is dropped once its pid is reused.
+        jobs.add(3, &[3], "ls", JobState::Running);
+        assert!(jobs.statuses.iter().all(|(pid, _)| *pid != 3));
+    }
+
+    #[test]
+    fn retire_keeps_statuses() {
+        let mut jobs = jobs();
+        jobs.update(20, JobState::Done(ExitStatus::ExitedWith(3)));
+        jobs.update(21, JobState::Killed(libc::SIGPIPE));
+        jobs.retire(2);
+        assert!(jobs.get(2).is_none());
+        assert_eq!(jobs.take_status(20), Some(ExitStatus::ExitedWith(3)));
+        assert_eq!(jobs.take_status(20), None);
+        jobs.add(21, &[21], "ls", JobState::Running);
+        assert_eq!(jobs.take_status(21), None);
+    }
+
+    #[test]
+    fn remove_updates_current() {
+        let mut jobs = jobs();
+        jobs.remove(3);
+        assert_eq!(jobs.current().map(|job| job.id), Some(2));
+        assert_eq!(jobs.previous().map(|job| job.id), Some(1));
+        assert_eq!(jobs.add(40, &[40], "ls", JobState::Stopped).id, 3);
+    }
+}
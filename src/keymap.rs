@@ -0,0 +1,700 @@
+use std::collections::HashMap;
+
+use crossterm::event::{KeyCode, KeyEvent, KeyModifiers};
+
+/// A key press with its modifiers.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub struct Key {
+    pub code: KeyCode,
+    pub modifiers: KeyModifiers,
+}
+
+impl Key {
+    pub const fn new(code: KeyCode, modifiers: KeyModifiers) -> Self {
+        Self { code, modifiers }
+    }
+
+    pub const fn plain(ch: char) -> Self {
+        Self::new(KeyCode::Char(ch), KeyModifiers::NONE)
+    }
+
+    pub const fn ctrl(ch: char) -> Self {
+        Self::new(KeyCode::Char(ch), KeyModifiers::CONTROL)
+    }
+
+    pub const fn alt(ch: char) -> Self {
+        Self::new(KeyCode::Char(ch), KeyModifiers::ALT)
+    }
+
+    /// Returns the character to be inserted if this is a printable key.
+    pub fn printable(&self) -> Option<char> {
+        match (self.code, self.modifiers) {
+            (KeyCode::Char(ch), KeyModifiers::NONE) => Some(ch),
+            _ => None,
+        }
+    }
+}
+
+impl From<&KeyEvent> for Key {
+    fn from(ev: &KeyEvent) -> Self {
+        let mut modifiers = ev.modifiers;
+        // Upper-case letters and symbols typed with Shift arrive with the
+        // SHIFT modifier set, but the character already reflects it.
+        if let KeyCode::Char(_) = ev.code {
+            modifiers.remove(KeyModifiers::SHIFT);
+        }
+        Self::new(ev.code, modifiers)
+    }
+}
+
+/// An editing command which can be bound to a key sequence.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum EditCommand {
+    AcceptLine,
+    Interrupt,
+    Exit,
+    ClearScreen,
+    BackwardChar,
+    ForwardChar,
+    BeginningOfLine,
+    EndOfLine,
+    BackwardWord,
+    ForwardWord,
+    PreviousHistory,
+    NextHistory,
+    HistorySearchBackward,
+    HistorySearchForward,
+    ReverseSearchHistory,
+    Complete,
+    DeleteChar,
+    DeleteCharOrEof,
+    BackwardDeleteChar,
+    UnixWordRubout,
+    UnixLineDiscard,
+    KillLine,
+    Yank,
+    YankPop,
+    Undo,
+    Redo,
+    TransposeChars,
+    TransposeWords,
+    UpcaseWord,
+    DowncaseWord,
+    CapitalizeWord,
+    DigitArgument,
+    QuotedInsert,
+    EditAndExecute,
+    ViCommandMode,
+    ViInsertMode,
+    ViAppendMode,
+    ViInsertBeginningOfLine,
+    ViAppendEndOfLine,
+    ViForwardWord,
+    ViBackwardWord,
+    ViEndWord,
+    ViDelete,
+    ViChange,
+    ViChangeToEnd,
+    ViSubstituteChar,
+    ViPut,
+}
+
+/// The readline-style names of the editing commands, used by the `bind`
+/// builtin.
+const COMMAND_NAMES: &[(EditCommand, &str)] = &[
+    (EditCommand::AcceptLine, "accept-line"),
+    (EditCommand::Interrupt, "interrupt"),
+    (EditCommand::Exit, "exit-shell"),
+    (EditCommand::ClearScreen, "clear-screen"),
+    (EditCommand::BackwardChar, "backward-char"),
+    (EditCommand::ForwardChar, "forward-char"),
+    (EditCommand::BeginningOfLine, "beginning-of-line"),
+    (EditCommand::EndOfLine, "end-of-line"),
+    (EditCommand::BackwardWord, "backward-word"),
+    (EditCommand::ForwardWord, "forward-word"),
+    (EditCommand::PreviousHistory, "previous-history"),
+    (EditCommand::NextHistory, "next-history"),
+    (
+        EditCommand::HistorySearchBackward,
+        "history-search-backward",
+    ),
+    (EditCommand::HistorySearchForward, "history-search-forward"),
+    (EditCommand::ReverseSearchHistory, "reverse-search-history"),
+    (EditCommand::Complete, "complete"),
+    (EditCommand::DeleteChar, "delete-char"),
+    (EditCommand::DeleteCharOrEof, "delete-char-or-eof"),
+    (EditCommand::BackwardDeleteChar, "backward-delete-char"),
+    (EditCommand::UnixWordRubout, "unix-word-rubout"),
+    (EditCommand::UnixLineDiscard, "unix-line-discard"),
+    (EditCommand::KillLine, "kill-line"),
+    (EditCommand::Yank, "yank"),
+    (EditCommand::YankPop, "yank-pop"),
+    (EditCommand::Undo, "undo"),
+    (EditCommand::Redo, "redo"),
+    (EditCommand::TransposeChars, "transpose-chars"),
+    (EditCommand::TransposeWords, "transpose-words"),
+    (EditCommand::UpcaseWord, "upcase-word"),
+    (EditCommand::DowncaseWord, "downcase-word"),
+    (EditCommand::CapitalizeWord, "capitalize-word"),
+    (EditCommand::DigitArgument, "digit-argument"),
+    (EditCommand::QuotedInsert, "quoted-insert"),
+    (EditCommand::EditAndExecute, "edit-and-execute-command"),
+    (EditCommand::ViCommandMode, "vi-movement-mode"),
+    (EditCommand::ViInsertMode, "vi-insertion-mode"),
+    (EditCommand::ViAppendMode, "vi-append-mode"),
+    (EditCommand::ViInsertBeginningOfLine, "vi-insert-beg"),
+    (EditCommand::ViAppendEndOfLine, "vi-append-eol"),
+    (EditCommand::ViForwardWord, "vi-next-word"),
+    (EditCommand::ViBackwardWord, "vi-prev-word"),
+    (EditCommand::ViEndWord, "vi-end-word"),
+    (EditCommand::ViDelete, "vi-delete-to"),
+    (EditCommand::ViChange, "vi-change-to"),
+    (EditCommand::ViChangeToEnd, "vi-change-eol"),
+    (EditCommand::ViSubstituteChar, "vi-subst"),
+    (EditCommand::ViPut, "vi-put"),
+];
+
+impl EditCommand {
+    pub fn name(&self) -> &'static str {
+        COMMAND_NAMES
+            .iter()
+            .find(|(command, _)| command == self)
+            .map(|(_, name)| *name)
+            .unwrap()
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        COMMAND_NAMES
+            .iter()
+            .find(|(_, n)| *n == name)
+            .map(|(command, _)| *command)
+    }
+
+    /// Returns all the commands in the order they are documented.
+    pub fn all() -> impl Iterator<Item = EditCommand> {
+        COMMAND_NAMES.iter().map(|(command, _)| *command)
+    }
+
+    /// Whether a numeric argument makes the command run that many times.
+    /// Commands which finish the line or change modes run only once.
+    pub fn is_repeatable(&self) -> bool {
+        !matches!(
+            self,
+            EditCommand::AcceptLine
+                | EditCommand::Interrupt
+                | EditCommand::Exit
+                | EditCommand::ClearScreen
+                | EditCommand::Complete
+                | EditCommand::DigitArgument
+                | EditCommand::QuotedInsert
+                | EditCommand::EditAndExecute
+                | EditCommand::ViCommandMode
+                | EditCommand::ViInsertMode
+                | EditCommand::ViAppendMode
+                | EditCommand::ViInsertBeginningOfLine
+                | EditCommand::ViAppendEndOfLine
+                | EditCommand::ViDelete
+                | EditCommand::ViChange
+        )
+    }
+
+    /// Whether the command only moves the cursor, which makes it usable as
+    /// the target of a vi operator such as `d` or `c`.
+    pub fn is_motion(&self) -> bool {
+        matches!(
+            self,
+            EditCommand::BackwardChar
+                | EditCommand::ForwardChar
+                | EditCommand::BeginningOfLine
+                | EditCommand::EndOfLine
+                | EditCommand::BackwardWord
+                | EditCommand::ForwardWord
+                | EditCommand::ViForwardWord
+                | EditCommand::ViBackwardWord
+                | EditCommand::ViEndWord
+        )
+    }
+}
+
+/// Which keymap the line editor uses.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum EditingMode {
+    Emacs,
+    Vi,
+}
+
+/// The result of looking up a key sequence.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Lookup {
+    Found(EditCommand),
+    /// The keys are a prefix of some longer binding.
+    Prefix,
+    NotFound,
+}
+
+/// A table from key sequences to editing commands.
+#[derive(Clone, Debug, Default)]
+pub struct Keymap {
+    bindings: HashMap<Vec<Key>, EditCommand>,
+}
+
+impl Keymap {
+    pub fn bind(&mut self, keys: &[Key], command: EditCommand) {
+        self.bindings.insert(keys.to_vec(), command);
+    }
+
+    pub fn unbind(&mut self, keys: &[Key]) -> bool {
+        self.bindings.remove(keys).is_some()
+    }
+
+    /// Returns the bindings sorted by command name.
+    pub fn bindings(&self) -> Vec<(&[Key], EditCommand)> {
+        let mut bindings: Vec<_> = self
+            .bindings
+            .iter()
+            .map(|(keys, command)| (keys.as_slice(), *command))
+            .collect();
+        bindings.sort_by_key(|(keys, command)| (command.name(), format_key_sequence(keys)));
+        bindings
+    }
+
+    pub fn lookup(&self, keys: &[Key]) -> Lookup {
+        if let Some(command) = self.bindings.get(keys) {
+            return Lookup::Found(*command);
+        }
+
+        let is_prefix = self
+            .bindings
+            .keys()
+            .any(|bound| bound.len() > keys.len() && bound.starts_with(keys));
+        if is_prefix {
+            Lookup::Prefix
+        } else {
+            Lookup::NotFound
+        }
+    }
+
+    /// Bindings shared by the emacs keymap and vi insert mode.
+    fn common() -> Self {
+        let mut keymap = Keymap::default();
+        keymap.bind(
+            &[Key::new(KeyCode::Enter, KeyModifiers::NONE)],
+            EditCommand::AcceptLine,
+        );
+        keymap.bind(&[Key::ctrl('c')], EditCommand::Interrupt);
+        keymap.bind(&[Key::ctrl('d')], EditCommand::DeleteCharOrEof);
+        keymap.bind(&[Key::ctrl('l')], EditCommand::ClearScreen);
+        keymap.bind(
+            &[Key::new(KeyCode::Left, KeyModifiers::NONE)],
+            EditCommand::BackwardChar,
+        );
+        keymap.bind(
+            &[Key::new(KeyCode::Right, KeyModifiers::NONE)],
+            EditCommand::ForwardChar,
+        );
+        keymap.bind(
+            &[Key::new(KeyCode::Home, KeyModifiers::NONE)],
+            EditCommand::BeginningOfLine,
+        );
+        keymap.bind(
+            &[Key::new(KeyCode::End, KeyModifiers::NONE)],
+            EditCommand::EndOfLine,
+        );
+        keymap.bind(
+            &[Key::new(KeyCode::Left, KeyModifiers::CONTROL)],
+            EditCommand::BackwardWord,
+        );
+        keymap.bind(
+            &[Key::new(KeyCode::Right, KeyModifiers::CONTROL)],
+            EditCommand::ForwardWord,
+        );
+        // Like zsh, Up and Down only go through the entries which start with
+        // what has been typed.
+        keymap.bind(
+            &[Key::new(KeyCode::Up, KeyModifiers::NONE)],
+            EditCommand::HistorySearchBackward,
+        );
+        keymap.bind(
+            &[Key::new(KeyCode::Down, KeyModifiers::NONE)],
+            EditCommand::HistorySearchForward,
+        );
+        keymap.bind(
+            &[Key::new(KeyCode::PageUp, KeyModifiers::NONE)],
+            EditCommand::HistorySearchBackward,
+        );
+        keymap.bind(
+            &[Key::new(KeyCode::PageDown, KeyModifiers::NONE)],
+            EditCommand::HistorySearchForward,
+        );
+        keymap.bind(
+            &[Key::new(KeyCode::Backspace, KeyModifiers::NONE)],
+            EditCommand::BackwardDeleteChar,
+        );
+        keymap.bind(
+            &[Key::new(KeyCode::Delete, KeyModifiers::NONE)],
+            EditCommand::DeleteChar,
+        );
+        keymap.bind(&[Key::ctrl('r')], EditCommand::ReverseSearchHistory);
+        keymap.bind(
+            &[Key::new(KeyCode::Tab, KeyModifiers::NONE)],
+            EditCommand::Complete,
+        );
+        keymap.bind(&[Key::ctrl('w')], EditCommand::UnixWordRubout);
+        keymap.bind(&[Key::ctrl('u')], EditCommand::UnixLineDiscard);
+        keymap.bind(&[Key::ctrl('v')], EditCommand::QuotedInsert);
+        keymap
+    }
+
+    pub fn emacs() -> Self {
+        let mut keymap = Keymap::common();
+        keymap.bind(
+            &[Key::new(KeyCode::Esc, KeyModifiers::NONE)],
+            EditCommand::Exit,
+        );
+        keymap.bind(&[Key::ctrl('b')], EditCommand::BackwardChar);
+        keymap.bind(&[Key::ctrl('f')], EditCommand::ForwardChar);
+        keymap.bind(&[Key::ctrl('a')], EditCommand::BeginningOfLine);
+        keymap.bind(&[Key::ctrl('e')], EditCommand::EndOfLine);
+        keymap.bind(&[Key::alt('b')], EditCommand::BackwardWord);
+        keymap.bind(&[Key::alt('f')], EditCommand::ForwardWord);
+        keymap.bind(&[Key::ctrl('p')], EditCommand::PreviousHistory);
+        keymap.bind(&[Key::ctrl('n')], EditCommand::NextHistory);
+        keymap.bind(&[Key::ctrl('k')], EditCommand::KillLine);
+        keymap.bind(&[Key::ctrl('y')], EditCommand::Yank);
+        keymap.bind(&[Key::alt('y')], EditCommand::YankPop);
+        // Ctrl-_ is reported as Ctrl-7 by the terminal.
+        keymap.bind(&[Key::ctrl('7')], EditCommand::Undo);
+        keymap.bind(&[Key::ctrl('x'), Key::ctrl('u')], EditCommand::Undo);
+        keymap.bind(
+            &[Key::ctrl('x'), Key::ctrl('e')],
+            EditCommand::EditAndExecute,
+        );
+        keymap.bind(&[Key::alt('_')], EditCommand::Redo);
+        keymap.bind(&[Key::ctrl('t')], EditCommand::TransposeChars);
+        keymap.bind(&[Key::alt('t')], EditCommand::TransposeWords);
+        keymap.bind(&[Key::alt('u')], EditCommand::UpcaseWord);
+        keymap.bind(&[Key::alt('l')], EditCommand::DowncaseWord);
+        keymap.bind(&[Key::alt('c')], EditCommand::CapitalizeWord);
+        for digit in '0'..='9' {
+            keymap.bind(&[Key::alt(digit)], EditCommand::DigitArgument);
+        }
+        keymap
+    }
+
+    pub fn vi_insert() -> Self {
+        let mut keymap = Keymap::common();
+        keymap.bind(
+            &[Key::new(KeyCode::Esc, KeyModifiers::NONE)],
+            EditCommand::ViCommandMode,
+        );
+        keymap
+    }
+
+    pub fn vi_command() -> Self {
+        let mut keymap = Keymap::common();
+        let bindings = [
+            ('h', EditCommand::BackwardChar),
+            ('l', EditCommand::ForwardChar),
+            (' ', EditCommand::ForwardChar),
+            ('0', EditCommand::BeginningOfLine),
+            ('^', EditCommand::BeginningOfLine),
+            ('$', EditCommand::EndOfLine),
+            ('w', EditCommand::ViForwardWord),
+            ('b', EditCommand::ViBackwardWord),
+            ('e', EditCommand::ViEndWord),
+            ('k', EditCommand::PreviousHistory),
+            ('-', EditCommand::PreviousHistory),
+            ('j', EditCommand::NextHistory),
+            ('+', EditCommand::NextHistory),
+            ('x', EditCommand::DeleteChar),
+            ('X', EditCommand::BackwardDeleteChar),
+            ('i', EditCommand::ViInsertMode),
+            ('a', EditCommand::ViAppendMode),
+            ('I', EditCommand::ViInsertBeginningOfLine),
+            ('A', EditCommand::ViAppendEndOfLine),
+            ('d', EditCommand::ViDelete),
+            ('c', EditCommand::ViChange),
+            ('D', EditCommand::KillLine),
+            ('C', EditCommand::ViChangeToEnd),
+            ('s', EditCommand::ViSubstituteChar),
+            ('p', EditCommand::ViPut),
+            ('P', EditCommand::Yank),
+            ('u', EditCommand::Undo),
+            ('v', EditCommand::EditAndExecute),
+        ];
+        for (ch, command) in bindings {
+            keymap.bind(&[Key::plain(ch)], command);
+        }
+        keymap.bind(&[Key::ctrl('r')], EditCommand::Redo);
+        // `0` moves to the beginning of the line unless it continues a count.
+        for digit in '1'..='9' {
+            keymap.bind(&[Key::plain(digit)], EditCommand::DigitArgument);
+        }
+        keymap
+    }
+}
+
+/// The set of keymaps used by the line editor.
+#[derive(Clone, Debug)]
+pub struct Keymaps {
+    pub mode: EditingMode,
+    pub emacs: Keymap,
+    pub vi_insert: Keymap,
+    pub vi_command: Keymap,
+}
+
+impl Keymaps {
+    /// Returns the keymap called `name` as in readline's `keymap` variable.
+    pub fn by_name_mut(&mut self, name: &str) -> Option<&mut Keymap> {
+        match name {
+            "emacs" | "emacs-standard" => Some(&mut self.emacs),
+            "vi-insert" => Some(&mut self.vi_insert),
+            "vi" | "vi-command" | "vi-move" => Some(&mut self.vi_command),
+            _ => None,
+        }
+    }
+
+    /// The keymap `bind` modifies when no `-m` option is given.
+    pub fn current_mut(&mut self) -> &mut Keymap {
+        match self.mode {
+            EditingMode::Emacs => &mut self.emacs,
+            EditingMode::Vi => &mut self.vi_insert,
+        }
+    }
+
+    pub fn new() -> Self {
+        Self {
+            mode: EditingMode::Emacs,
+            emacs: Keymap::emacs(),
+            vi_insert: Keymap::vi_insert(),
+            vi_command: Keymap::vi_command(),
+        }
+    }
+}
+
+/// Names of the keys without a printable representation, as written inside
+/// `<...>` in a key sequence.
+const SPECIAL_KEYS: &[(KeyCode, &str)] = &[
+    (KeyCode::Enter, "Enter"),
+    (KeyCode::Esc, "Esc"),
+    (KeyCode::Tab, "Tab"),
+    (KeyCode::BackTab, "BackTab"),
+    (KeyCode::Backspace, "Backspace"),
+    (KeyCode::Delete, "Delete"),
+    (KeyCode::Insert, "Insert"),
+    (KeyCode::Left, "Left"),
+    (KeyCode::Right, "Right"),
+    (KeyCode::Up, "Up"),
+    (KeyCode::Down, "Down"),
+    (KeyCode::Home, "Home"),
+    (KeyCode::End, "End"),
+    (KeyCode::PageUp, "PageUp"),
+    (KeyCode::PageDown, "PageDown"),
+];
+
+/// Parses a key sequence written like readline's: `\C-x` is Ctrl-x, `\M-x` and
+/// `\ex` are Alt-x, and `\\` and `\"` are a backslash and a double quote.
+/// Keys without a character are written by name, e.g. `<Home>` or `\C-<Left>`.
+pub fn parse_key_sequence(s: &str) -> Result<Vec<Key>, String> {
+    let mut keys = Vec::new();
+    let mut rest = s;
+    while !rest.is_empty() {
+        let mut modifiers = KeyModifiers::NONE;
+        loop {
+            if let Some(r) = rest.strip_prefix("\\C-") {
+                modifiers |= KeyModifiers::CONTROL;
+                rest = r;
+            } else if let Some(r) = rest.strip_prefix("\\M-") {
+                modifiers |= KeyModifiers::ALT;
+                rest = r;
+            } else if rest.len() > 2 && rest.starts_with("\\e") {
+                modifiers |= KeyModifiers::ALT;
+                rest = &rest[2..];
+            } else {
+                break;
+            }
+        }
+
+        let code = if let Some(r) = rest.strip_prefix('<') {
+            let end = r
+                .find('>')
+                .ok_or_else(|| format!("{}: unterminated key name", s))?;
+            let name = &r[..end];
+            rest = &r[end + 1..];
+            SPECIAL_KEYS
+                .iter()
+                .find(|(_, n)| n.eq_ignore_ascii_case(name))
+                .map(|(code, _)| *code)
+                .ok_or_else(|| format!("{}: unknown key name", name))?
+        } else {
+            let mut chars = rest.chars();
+            let ch = match chars.next() {
+                Some('\\') => match chars.next() {
+                    Some(escaped @ ('e' | 't')) => {
+                        rest = chars.as_str();
+                        let code = if escaped == 'e' {
+                            KeyCode::Esc
+                        } else {
+                            KeyCode::Tab
+                        };
+                        keys.push(Key::new(code, modifiers));
+                        continue;
+                    }
+                    Some(ch) => ch,
+                    None => return Err(format!("{}: trailing backslash", s)),
+                },
+                Some(ch) => ch,
+                None => return Err(format!("{}: missing key after modifier", s)),
+            };
+            rest = chars.as_str();
+            if modifiers.contains(KeyModifiers::CONTROL) {
+                KeyCode::Char(ch.to_ascii_lowercase())
+            } else {
+                KeyCode::Char(ch)
+            }
+        };
+
+        keys.push(Key::new(code, modifiers));
+    }
+
+    Ok(keys)
+}
+
+/// Formats a key sequence in the syntax `parse_key_sequence` accepts.
+pub fn format_key_sequence(keys: &[Key]) -> String {
+    let mut s = String::new();
+    for key in keys {
+        if key.modifiers.contains(KeyModifiers::CONTROL) {
+            s.push_str("\\C-");
+        }
+        if key.modifiers.contains(KeyModifiers::ALT) {
+            s.push_str("\\M-");
+        }
+        match key.code {
+            KeyCode::Char('\\') => s.push_str("\\\\"),
+            KeyCode::Char('"') => s.push_str("\\\""),
+            KeyCode::Char('<') => s.push_str("\\<"),
+            KeyCode::Char(ch) => s.push(ch),
+            code => {
+                let name = SPECIAL_KEYS
+                    .iter()
+                    .find(|(c, _)| *c == code)
+                    .map_or("?", |(_, name)| name);
+                s.push('<');
+                s.push_str(name);
+                s.push('>');
+            }
+        }
+    }
+    s
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn lookup_key_sequences() {
+        let keymap = Keymap::emacs();
+        assert_eq!(
+            keymap.lookup(&[Key::ctrl('a')]),
+            Lookup::Found(EditCommand::BeginningOfLine)
+        );
+        assert_eq!(keymap.lookup(&[Key::ctrl('x')]), Lookup::Prefix);
+        assert_eq!(
+            keymap.lookup(&[Key::ctrl('x'), Key::ctrl('u')]),
+            Lookup::Found(EditCommand::Undo)
+        );
+        assert_eq!(
+            keymap.lookup(&[Key::ctrl('x'), Key::ctrl('z')]),
+            Lookup::NotFound
+        );
+        assert_eq!(
+            Keymap::vi_insert().lookup(&[Key::plain('w')]),
+            Lookup::NotFound
+        );
+        assert_eq!(
+            Keymap::vi_command().lookup(&[Key::plain('w')]),
+            Lookup::Found(EditCommand::ViForwardWord)
+        );
+    }
+
+    #[test]
+    fn digit_arguments() {
+        let emacs = Keymap::emacs();
+        let vi_command = Keymap::vi_command();
+        assert_eq!(
+            emacs.lookup(&[Key::alt('0')]),
+            Lookup::Found(EditCommand::DigitArgument)
+        );
+        assert_eq!(
+            vi_command.lookup(&[Key::plain('3')]),
+            Lookup::Found(EditCommand::DigitArgument)
+        );
+        assert_eq!(
+            vi_command.lookup(&[Key::plain('0')]),
+            Lookup::Found(EditCommand::BeginningOfLine)
+        );
+        assert!(EditCommand::BackwardWord.is_repeatable());
+        assert!(!EditCommand::AcceptLine.is_repeatable());
+    }
+
+    #[test]
+    fn keys_from_events() {
+        let ev = KeyEvent::new(KeyCode::Char('A'), KeyModifiers::SHIFT);
+        assert_eq!(Key::from(&ev), Key::plain('A'));
+        assert_eq!(Key::from(&ev).printable(), Some('A'));
+        let ev = KeyEvent::new(KeyCode::Tab, KeyModifiers::SHIFT);
+        assert_eq!(Key::from(&ev), Key::new(KeyCode::Tab, KeyModifiers::SHIFT));
+        assert_eq!(Key::ctrl('a').printable(), None);
+    }
+
+    #[test]
+    fn key_sequences_round_trip() {
+        for (seq, formatted) in [
+            ("\\C-x\\C-e", "\\C-x\\C-e"),
+            ("\\C-X", "\\C-x"),
+            ("\\M-y", "\\M-y"),
+            ("\\ey", "\\M-y"),
+            ("\\e", "<Esc>"),
+            ("\\t", "<Tab>"),
+            ("<Home>", "<Home>"),
+            ("\\C-<left>", "\\C-<Left>"),
+            ("\\\\", "\\\\"),
+            ("\\\"", "\\\""),
+            ("\\<", "\\<"),
+            ("ab", "ab"),
+        ] {
+            let keys = parse_key_sequence(seq).unwrap();
+            assert_eq!(format_key_sequence(&keys), formatted, "{}", seq);
+            assert_eq!(parse_key_sequence(formatted), Ok(keys), "{}", seq);
+        }
+        assert_eq!(
+            parse_key_sequence("\\C-x\\e"),
+            Ok(vec![
+                Key::ctrl('x'),
+                Key::new(KeyCode::Esc, KeyModifiers::NONE)
+            ])
+        );
+    }
+
+    #[test]
+    fn invalid_key_sequences() {
+        assert_eq!(
+            parse_key_sequence("<Home"),
+            Err("<Home: unterminated key name".to_owned())
+        );
+        assert_eq!(
+            parse_key_sequence("<Nope>"),
+            Err("Nope: unknown key name".to_owned())
+        );
+        assert_eq!(
+            parse_key_sequence("a\\"),
+            Err("a\\: trailing backslash".to_owned())
+        );
+        assert_eq!(
+            parse_key_sequence("\\C-"),
+            Err("\\C-: missing key after modifier".to_owned())
+        );
+    }
+}
@@ -0,0 +1,213 @@
+//! Colors of file names as `ls` shows them, configured with `$LS_COLORS`.
+
+use std::{
+    collections::HashMap,
+    os::unix::fs::{FileTypeExt, PermissionsExt},
+    path::Path,
+};
+
+use crossterm::style::{Attribute, Color, ContentStyle};
+
+/// What `ls` uses when `$LS_COLORS` is not set.
+const DEFAULT_LS_COLORS: &str =
+    "di=01;34:ln=01;36:pi=40;33:so=01;35:bd=40;33;01:cd=40;33;01:or=40;31;01:ex=01;32";
+
+/// The styles for each kind of file, e.g. `di` for directories, and for file
+/// name suffixes such as `*.tar`.
+#[derive(Clone, Debug, Default)]
+pub struct LsColors {
+    kinds: HashMap<String, ContentStyle>,
+    suffixes: Vec<(String, ContentStyle)>,
+    /// `ln=target` colors symbolic links as the files they point to.
+    link_as_target: bool,
+}
+
+impl LsColors {
+    pub fn from_env() -> Self {
+        match std::env::var("LS_COLORS") {
+            Ok(colors) => Self::parse(&colors),
+            Err(_) => Self::parse(DEFAULT_LS_COLORS),
+        }
+    }
+
+    /// Parses the `KIND=CODES` entries of `$LS_COLORS`, where `CODES` are
+    /// SGR parameters such as `01;34`.
+    fn parse(colors: &str) -> Self {
+        let mut ls_colors = Self::default();
+        for entry in colors.split(':') {
+            let (key, codes) = match entry.split_once('=') {
+                Some(entry) => entry,
+                None => continue,
+            };
+            if key == "ln" && codes == "target" {
+                ls_colors.link_as_target = true;
+            } else if let Some(suffix) = key.strip_prefix('*') {
+                ls_colors
+                    .suffixes
+                    .push((suffix.to_owned(), parse_sgr(codes)));
+            } else {
+                ls_colors.kinds.insert(key.to_owned(), parse_sgr(codes));
+            }
+        }
+        ls_colors
+    }
+
+    /// Returns the style to show the file at `path` in.
+    pub fn style(&self, path: &Path) -> ContentStyle {
+        let metadata = match std::fs::symlink_metadata(path) {
+            Ok(metadata) => metadata,
+            Err(_) => return self.kind("mi"),
+        };
+        let file_type = metadata.file_type();
+        if file_type.is_symlink() {
+            return match std::fs::metadata(path) {
+                Ok(_) if self.link_as_target => self.target_style(path),
+                Ok(_) => self.kind("ln"),
+                Err(_) => self.kind("or"),
+            };
+        }
+        self.target_style(path)
+    }
+
+    /// Returns the style of the file at `path`, following symbolic links.
+    fn target_style(&self, path: &Path) -> ContentStyle {
+        let metadata = match std::fs::metadata(path) {
+            Ok(metadata) => metadata,
+            Err(_) => return self.kind("or"),
+        };
+        let file_type = metadata.file_type();
+        let mode = metadata.permissions().mode();
+        let kind = if file_type.is_dir() {
+            match (mode & 0o1000 != 0, mode & 0o002 != 0) {
+                (true, true) => "tw",
+                (false, true) => "ow",
+                (true, false) => "st",
+                (false, false) => "di",
+            }
+        } else if file_type.is_fifo() {
+            "pi"
+        } else if file_type.is_socket() {
+            "so"
+        } else if file_type.is_block_device() {
+            "bd"
+        } else if file_type.is_char_device() {
+            "cd"
+        } else if mode & 0o4000 != 0 {
+            "su"
+        } else if mode & 0o2000 != 0 {
+            "sg"
+        } else if mode & 0o111 != 0 {
+            "ex"
+        } else {
+            "fi"
+        };
+
+        // Like `ls`, fall back to the plainer kinds if the specific one has
+        // no color, and to the suffix for regular files.
+        let fallback = match kind {
+            "tw" | "ow" | "st" => "di",
+            "su" | "sg" | "ex" | "fi" => "fi",
+            kind => kind,
+        };
+        if let Some(style) = self.kinds.get(kind).filter(|_| kind != "fi") {
+            return *style;
+        }
+        if fallback == "fi" {
+            let name = path.file_name().map(|name| name.to_string_lossy());
+            let suffix = self
+                .suffixes
+                .iter()
+                .find(|(suffix, _)| name.as_ref().is_some_and(|name| name.ends_with(suffix)));
+            if let Some((_, style)) = suffix {
+                return *style;
+            }
+        }
+        self.kind(fallback)
+    }
+
+    fn kind(&self, kind: &str) -> ContentStyle {
+        self.kinds.get(kind).copied().unwrap_or_default()
+    }
+}
+
+/// Converts SGR parameters such as `01;38;5;208` into a style.
+fn parse_sgr(codes: &str) -> ContentStyle {
+    let mut style = ContentStyle::new();
+    let mut codes = codes.split(';').map(|code| code.parse::<u8>().ok());
+    while let Some(code) = codes.next() {
+        match code {
+            Some(1) => style.attributes.set(Attribute::Bold),
+            Some(2) => style.attributes.set(Attribute::Dim),
+            Some(3) => style.attributes.set(Attribute::Italic),
+            Some(4) => style.attributes.set(Attribute::Underlined),
+            Some(5) => style.attributes.set(Attribute::SlowBlink),
+            Some(7) => style.attributes.set(Attribute::Reverse),
+            Some(code @ 30..=37) => style.foreground_color = Some(Color::AnsiValue(code - 30)),
+            Some(code @ 90..=97) => style.foreground_color = Some(Color::AnsiValue(code - 82)),
+            Some(code @ 40..=47) => style.background_color = Some(Color::AnsiValue(code - 40)),
+            Some(code @ 100..=107) => style.background_color = Some(Color::AnsiValue(code - 92)),
+            Some(code @ (38 | 48)) => {
+                let color = match codes.next().flatten() {
+                    Some(5) => codes.next().flatten().map(Color::AnsiValue),
+                    Some(2) => match (codes.next(), codes.next(), codes.next()) {
+                        (Some(Some(r)), Some(Some(g)), Some(Some(b))) => {
+                            Some(Color::Rgb { r, g, b })
+                        }
+                        _ => None,
+                    },
+                    _ => None,
+                };
+                if code == 38 {
+                    style.foreground_color = color;
+                } else {
+                    style.background_color = color;
+                }
+            }
+            _ => (),
+        }
+    }
+    style
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_sgr_codes() {
+        let style = parse_sgr("01;34");
+        assert!(style.attributes.has(Attribute::Bold));
+        assert_eq!(style.foreground_color, Some(Color::AnsiValue(4)));
+        assert_eq!(parse_sgr("91").foreground_color, Some(Color::AnsiValue(9)));
+        assert_eq!(
+            parse_sgr("40;33").background_color,
+            Some(Color::AnsiValue(0))
+        );
+        assert_eq!(
+            parse_sgr("38;5;208").foreground_color,
+            Some(Color::AnsiValue(208))
+        );
+        assert_eq!(
+            parse_sgr("48;2;1;2;3").background_color,
+            Some(Color::Rgb { r: 1, g: 2, b: 3 })
+        );
+        assert_eq!(parse_sgr("38;2;1").foreground_color, None);
+        assert_eq!(parse_sgr(""), ContentStyle::new());
+    }
+
+    #[test]
+    fn styles_by_kind_and_suffix() {
+        let dir = std::env::temp_dir().join(format!("toysh-ls-colors-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(dir.join("sub")).unwrap();
+        std::fs::write(dir.join("a.tar"), "").unwrap();
+        std::fs::write(dir.join("plain"), "").unwrap();
+
+        let colors = LsColors::parse("di=34:*.tar=31:bogus");
+        let color = |name: &str| colors.style(&dir.join(name)).foreground_color;
+        assert_eq!(color("sub"), Some(Color::AnsiValue(4)));
+        assert_eq!(color("a.tar"), Some(Color::AnsiValue(1)));
+        assert_eq!(color("plain"), None);
+        assert_eq!(color("missing"), None);
+    }
+}
@@ -1,11 +1,29 @@
 use event::ShellState;
+use process::ExitStatus;
 use shell::Shell;
 use tracing_subscriber::{self, fmt, prelude::*, EnvFilter};
 
+mod arith;
+mod builtins;
+mod command_index;
+mod command_paths;
+mod completion;
+mod diagnostic;
 mod event;
+mod expand;
+mod glob;
+mod history;
+mod job;
+mod keymap;
+mod ls_colors;
 mod parser;
 mod process;
+mod regex;
+mod render;
 mod shell;
+mod signal;
+mod users;
+mod variables;
 
 fn main() {
     tracing_subscriber::registry()
@@ -13,5 +31,46 @@ fn main() {
         .with(EnvFilter::from_default_env())
         .init();
 
-    ShellState::new(Shell).run();
+    let mut args = std::env::args().skip(1);
+    if args.next().as_deref() == Some("--dump-ast") {
+        std::process::exit(dump_ast(args));
+    }
+
+    signal::install();
+    process::enable_job_control();
+    let mut shell = Shell::new();
+    shell.load_history();
+    shell.load_config();
+    ShellState::new(shell).run();
+}
+
+/// `toysh --dump-ast [--json] [file]` prints the syntax tree of the file, or
+/// of the standard input, without running it.
+fn dump_ast(mut args: impl Iterator<Item = String>) -> i32 {
+    let mut json = false;
+    let mut path = None;
+    for arg in args.by_ref() {
+        match arg.as_str() {
+            "--json" => json = true,
+            _ if path.is_none() => path = Some(arg),
+            _ => {
+                eprintln!("toysh: {}: unexpected argument", arg);
+                return 2;
+            }
+        }
+    }
+    let script = match &path {
+        Some(path) => std::fs::read_to_string(path),
+        None => std::io::read_to_string(std::io::stdin()),
+    };
+    match script {
+        Ok(script) => {
+            let ExitStatus::ExitedWith(code) = builtins::dump_ast(&script, json);
+            code
+        }
+        Err(err) => {
+            eprintln!("toysh: {}: {}", path.as_deref().unwrap_or("stdin"), err);
+            1
+        }
+    }
 }
@@ -0,0 +1,255 @@
+//! The syntax tree of scripts.
+
+use std::rc::Rc;
+
+use serde::Serialize;
+
+#[derive(Debug, PartialEq, Eq, Clone, Serialize)]
+pub struct Ast {
+    pub terms: Vec<Term>,
+}
+
+#[derive(Debug, PartialEq, Eq, Clone, Serialize)]
+pub struct Term {
+    pub code: String,
+    pub first: Pipeline,
+    /// The pipelines after `&&` or `||`, which are run depending on the
+    /// status of the one before.
+    pub rest: Vec<(AndOr, Pipeline)>,
+    /// Whether the term ends with `&` to run it without waiting for it.
+    pub background: bool,
+}
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Serialize)]
+pub enum AndOr {
+    And,
+    Or,
+}
+
+#[derive(Debug, PartialEq, Eq, Clone, Serialize)]
+pub struct Pipeline {
+    pub commands: Vec<Command>,
+}
+
+#[derive(Debug, PartialEq, Eq, Clone, Serialize)]
+pub enum Command {
+    Simple {
+        assignments: Vec<Assignment>,
+        argv: Vec<Word>,
+        redirects: Vec<Redirect>,
+    },
+    /// `( ... )`, which runs the terms in a child process so that they don't
+    /// change the shell.
+    Subshell {
+        terms: Vec<Term>,
+        redirects: Vec<Redirect>,
+    },
+    /// `{ ...; }`, which runs the terms in the shell itself, e.g. to
+    /// redirect the output of all of them.
+    Group {
+        terms: Vec<Term>,
+        redirects: Vec<Redirect>,
+    },
+    /// `if ...; then ...; elif ...; then ...; else ...; fi`, which runs the
+    /// body of the first branch whose condition succeeds, or `otherwise` if
+    /// none does.
+    If {
+        branches: Vec<(Vec<Term>, Vec<Term>)>,
+        otherwise: Option<Vec<Term>>,
+        redirects: Vec<Redirect>,
+    },
+    /// `while ...; do ...; done`, or `until` which loops while the condition
+    /// fails.
+    While {
+        condition: Vec<Term>,
+        body: Vec<Term>,
+        until: bool,
+        redirects: Vec<Redirect>,
+    },
+    /// `for name in words; do ...; done`, which runs the body with `name`
+    /// set to each of the fields of `words`, or of the positional
+    /// parameters if there is no `in`.
+    For {
+        name: String,
+        words: Option<Vec<Word>>,
+        body: Vec<Term>,
+        redirects: Vec<Redirect>,
+    },
+    /// `for ((init; condition; step)); do ...; done` with arithmetic
+    /// expressions. An empty condition is true.
+    ArithFor {
+        init: String,
+        condition: String,
+        step: String,
+        body: Vec<Term>,
+        redirects: Vec<Redirect>,
+    },
+    /// `case word in pattern | pattern) ...;; esac`, which runs the body of
+    /// the first item with a pattern matching `word`.
+    Case {
+        word: Word,
+        items: Vec<CaseItem>,
+        redirects: Vec<Redirect>,
+    },
+    /// `[[ expr ]]`, which succeeds if the conditional expression is true.
+    Cond {
+        expr: CondExpr,
+        redirects: Vec<Redirect>,
+    },
+    /// `name() command`, which defines the function `name`.
+    Function { name: String, body: Rc<Command> },
+    /// `return N`, which leaves the function with the status `N`, or that
+    /// of the last command.
+    Return(Option<Word>),
+    /// `break N`, which leaves the `N` innermost loops, 1 by default.
+    Break(Option<Word>),
+    /// `continue N`, which goes on to the next iteration of the `N`th loop.
+    Continue(Option<Word>),
+}
+
+#[derive(Debug, PartialEq, Eq, Clone, Serialize)]
+pub struct CaseItem {
+    pub patterns: Vec<Word>,
+    pub body: Vec<Term>,
+    pub terminator: CaseTerminator,
+}
+
+/// What happens after the body of a `case` item is run.
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Serialize)]
+pub enum CaseTerminator {
+    /// `;;`, which ends the `case`.
+    Break,
+    /// `;&`, which runs the body of the next item as well.
+    FallThrough,
+    /// `;;&`, which goes on matching the next items.
+    Continue,
+}
+
+/// An expression in `[[ ]]`, whose words are neither split nor globbed.
+#[derive(Debug, PartialEq, Eq, Clone, Serialize)]
+pub enum CondExpr {
+    And(Box<CondExpr>, Box<CondExpr>),
+    Or(Box<CondExpr>, Box<CondExpr>),
+    Not(Box<CondExpr>),
+    /// `-f word` and the like.
+    Unary {
+        op: String,
+        operand: Word,
+    },
+    /// `word == pattern`, `word =~ regex`, `word -eq word` and the like.
+    Binary {
+        op: String,
+        left: Word,
+        right: Word,
+    },
+    /// `word`, which is true unless it is empty.
+    Word(Word),
+}
+
+/// `name=value`, which sets the variable `name`.
+#[derive(Debug, PartialEq, Eq, Clone, Serialize)]
+pub struct Assignment {
+    pub name: String,
+    pub value: Word,
+}
+
+/// A redirection such as `2>> log` of the descriptor `fd` to `target`.
+#[derive(Debug, PartialEq, Eq, Clone, Serialize)]
+pub struct Redirect {
+    pub fd: i32,
+    pub kind: RedirectKind,
+    pub target: RedirectTarget,
+}
+
+#[derive(Debug, PartialEq, Eq, Clone, Serialize)]
+pub enum RedirectTarget {
+    File(Word),
+    /// `&N`, which makes the descriptor a copy of `N`.
+    Fd(i32),
+    /// The body of a here document, or the word of a here string with a
+    /// newline.
+    Text(Word),
+}
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Serialize)]
+pub enum RedirectKind {
+    /// `<`
+    Read,
+    /// `>`, which truncates the file.
+    Write,
+    /// `>>`
+    Append,
+}
+
+#[derive(Debug, PartialEq, Eq, Clone, Serialize)]
+pub struct Word(pub Vec<Span>);
+
+#[derive(Debug, PartialEq, Eq, Clone, Serialize)]
+pub enum Span {
+    Literal(String),
+    /// Text in single or double quotes, which is not split into words or
+    /// used as a glob pattern.
+    Quoted(String),
+    /// `~` or `~user` at the beginning of a word, with the user name.
+    Tilde(String),
+    /// `$(script)` or `` `script` ``, replaced with what `script` prints.
+    /// Its output is split into words unless it is `quoted`.
+    Command {
+        script: String,
+        quoted: bool,
+    },
+    /// `<(script)` or `>(script)`, replaced with a path from which what
+    /// `script` prints is read, or to which what it reads is written.
+    Process {
+        script: String,
+        output: bool,
+    },
+    /// `$((expr))`, replaced with the value of the arithmetic expression.
+    Arithmetic(String),
+    /// `{a,b}` or `{1..3}`, which makes a word for each of the
+    /// `alternatives`. It is left as `text` where words are not split, e.g.
+    /// in assignments.
+    Brace {
+        text: String,
+        alternatives: Vec<Word>,
+    },
+    /// `$name` or `${name}`, replaced with the value of the variable or the
+    /// special parameter, or `${name<op>...}` which modifies it. The result
+    /// is split into words unless it is `quoted`. `index` is what `[index]`
+    /// after the name in `${name[index]}` has, e.g. `@` or `1`.
+    Parameter {
+        name: String,
+        index: Option<String>,
+        op: Option<ParameterOp>,
+        quoted: bool,
+    },
+}
+
+/// What `${name<op>...}` does with the value of `name`. `colon` is whether
+/// the operator starts with `:`, which treats an empty value as unset.
+#[derive(Debug, PartialEq, Eq, Clone, Serialize)]
+pub enum ParameterOp {
+    /// `${#name}`, the number of characters in the value.
+    Length,
+    /// `${name:-word}`, `word` if the value is unset.
+    Default { word: Word, colon: bool },
+    /// `${name:=word}`, which also assigns `word` if the value is unset.
+    Assign { word: Word, colon: bool },
+    /// `${name:?word}`, an error with the message `word` if the value is
+    /// unset.
+    Error { word: Word, colon: bool },
+    /// `${name:+word}`, `word` if the value is set, and nothing otherwise.
+    Alternative { word: Word, colon: bool },
+    /// `${name#pattern}` or `${name##pattern}`, which removes the shortest
+    /// or the `longest` prefix matching `pattern`.
+    RemovePrefix { pattern: Word, longest: bool },
+    /// `${name%pattern}` or `${name%%pattern}` for a suffix.
+    RemoveSuffix { pattern: Word, longest: bool },
+    /// `${name/pattern/string}`, which replaces the longest match of
+    /// `pattern` with `string`, or every match with `//`.
+    Replace {
+        pattern: Word,
+        string: Word,
+        all: bool,
+    },
+}
@@ -0,0 +1,1444 @@
+//! Splits scripts into tokens. The quotes, escapes and expansions in words
+//! are turned into the spans of the words here, and the bodies of here
+//! documents are read, so the parser only deals with words and operators.
+
+use super::{ast::*, unexpected, Parser};
+use crate::diagnostic::Diagnostic;
+
+pub type Result<T> = std::result::Result<T, Diagnostic>;
+
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct Token {
+    pub kind: TokenKind,
+    /// The byte offsets of the token in the script.
+    pub start: usize,
+    pub end: usize,
+}
+
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub enum TokenKind {
+    Word(Word),
+    /// A control operator such as `|`, `&&` or `;;`, or one of `(`, `)`,
+    /// `{` and `}`.
+    Operator(&'static str),
+    /// A redirection operator such as `>>`, with the descriptor right before
+    /// it as in `2>`.
+    Redirect {
+        fd: Option<i32>,
+        op: &'static str,
+    },
+    /// `<<` or `<<-` with its delimiter, and the body after the line.
+    Heredoc {
+        fd: Option<i32>,
+        body: Word,
+    },
+    Newline,
+    Eof,
+}
+
+/// The operators, longest first so that e.g. `;;` is not read as `;`.
+const OPERATORS: &[&str] = &[
+    ";;&", ";;", ";&", ";", "&&", "&", "||", "|", "(", ")", "{", "}",
+];
+
+/// The redirection operators, which are looked for before the other ones so
+/// that `&>` is not read as `&`.
+const REDIRECTS: &[&str] = &["&>>", "&>", "<<<", "<<-", "<<", "<&", "<", ">>", ">&", ">"];
+
+/// The most words a brace range such as `{1..100}` expands to. Larger ones
+/// are left as they are rather than filling up the memory.
+const MAX_BRACE_RANGE_LEN: u64 = 1 << 16;
+
+/// The operators in `${name<op>word}`, longest first.
+const PARAMETER_OPS: &[&str] = &[
+    ":-", ":=", ":?", ":+", "-", "=", "?", "+", "##", "#", "%%", "%",
+];
+
+/// Where a word is, which decides where it ends.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Context {
+    Word,
+    /// An alternative in `{a,b}`, which ends at `,` as well.
+    Brace,
+    /// The regex on the right of `=~` in `[[ ]]`, where `(`, `)` and `|`
+    /// are parts of the word.
+    Regex,
+}
+
+#[derive(Debug, Clone)]
+pub struct Lexer<'a> {
+    src: &'a str,
+    pos: usize,
+    /// Where the bodies of the here documents on the current line end. The
+    /// lexer skips to there at the end of the line.
+    heredocs_end: Option<usize>,
+    /// Whether the lexer is only looking for the end of the line, in which
+    /// case the bodies of here documents are not read.
+    lookahead: bool,
+}
+
+impl<'a> Lexer<'a> {
+    pub fn new(src: &'a str) -> Self {
+        Self::at(src, 0)
+    }
+
+    /// Starts lexing `src` at the byte offset `pos`, e.g. in `$(...)`.
+    pub fn at(src: &'a str, pos: usize) -> Self {
+        Self {
+            src,
+            pos,
+            heredocs_end: None,
+            lookahead: false,
+        }
+    }
+
+    pub fn src(&self) -> &'a str {
+        self.src
+    }
+
+    pub fn pos(&self) -> usize {
+        self.pos
+    }
+
+    fn rest(&self) -> &'a str {
+        &self.src[self.pos..]
+    }
+
+    fn peek(&self) -> Option<char> {
+        self.rest().chars().next()
+    }
+
+    fn bump(&mut self) -> Option<char> {
+        let c = self.peek()?;
+        self.pos += c.len_utf8();
+        Some(c)
+    }
+
+    fn eat(&mut self, s: &str) -> bool {
+        if self.rest().starts_with(s) {
+            self.pos += s.len();
+            true
+        } else {
+            false
+        }
+    }
+
+    /// The error for something which starts at `start` and doesn't end
+    /// before the end of the script.
+    fn unclosed(&self, start: usize, hint: impl Into<String>) -> Diagnostic {
+        Diagnostic::new("unexpected end of input", self.src.len()).with_hint(start, hint)
+    }
+
+    /// Skips blanks, backslashes before newlines, and comments.
+    pub fn skip_blanks(&mut self) {
+        loop {
+            match self.peek() {
+                Some(' ' | '\t' | '\r') => self.pos += 1,
+                Some('\\') if self.rest().starts_with("\\\n") => self.pos += 2,
+                Some('#') => self.pos += self.rest().find('\n').unwrap_or(self.rest().len()),
+                _ => break,
+            }
+        }
+    }
+
+    pub fn next_token(&mut self) -> Result<Token> {
+        self.skip_blanks();
+        let start = self.pos;
+        let kind = match self.peek() {
+            None => TokenKind::Eof,
+            Some('\n') => {
+                self.pos += 1;
+                let token = Token {
+                    kind: TokenKind::Newline,
+                    start,
+                    end: self.pos,
+                };
+                if let Some(end) = self.heredocs_end.take() {
+                    self.pos = end;
+                }
+                return Ok(token);
+            }
+            Some(_) => self.operator_or_word()?,
+        };
+        Ok(Token {
+            kind,
+            start,
+            end: self.pos,
+        })
+    }
+
+    fn operator_or_word(&mut self) -> Result<TokenKind> {
+        let start = self.pos;
+        let rest = self.rest();
+        let is_process_substitution = rest.starts_with("<(") || rest.starts_with(">(");
+        if !is_process_substitution {
+            // The descriptor has to be right before the operator: `echo 2 > x`
+            // writes `2` to `x`.
+            let digits = rest
+                .find(|c: char| !c.is_ascii_digit())
+                .unwrap_or(rest.len());
+            let after = &rest[digits..];
+            let op = REDIRECTS
+                .iter()
+                .find(|op| after.starts_with(**op) && (digits == 0 || !op.starts_with('&')));
+            if let Some(op) = op.filter(|_| !after.starts_with("<(") && !after.starts_with(">(")) {
+                let fd = match &rest[..digits] {
+                    "" => None,
+                    fd => Some(fd.parse().map_err(|_| {
+                        Diagnostic::new(format!("{}: bad file descriptor", fd), start)
+                    })?),
+                };
+                self.pos += digits + op.len();
+                if op.starts_with("<<") && *op != "<<<" {
+                    return self.heredoc(start, fd, *op == "<<-");
+                }
+                return Ok(TokenKind::Redirect { fd, op });
+            }
+        }
+
+        if let Some(word) = self.word(Context::Word)? {
+            return Ok(TokenKind::Word(word));
+        }
+        match OPERATORS.iter().find(|op| rest.starts_with(**op)) {
+            Some(op) => {
+                self.pos += op.len();
+                Ok(TokenKind::Operator(op))
+            }
+            None => Err(unexpected(self.src, start)),
+        }
+    }
+
+    /// Lexes a word, or returns `None` if there isn't one at the position.
+    fn word(&mut self, context: Context) -> Result<Option<Word>> {
+        let start = self.pos;
+        let mut spans = Vec::new();
+        if context == Context::Word {
+            self.tilde(&mut spans);
+        }
+        while let Some(c) = self.peek() {
+            match c {
+                '\'' => spans.push(self.single_quoted()?),
+                '"' => spans.extend(self.double_quoted()?),
+                '`' => spans.push(self.backquoted(false)?),
+                '$' => match self.dollar(false)? {
+                    Some(span) => spans.push(span),
+                    None => push_literal(&mut spans, "$", false),
+                },
+                '\\' => self.escaped(&mut spans)?,
+                '{' => match self.brace()? {
+                    Some(span) => spans.push(span),
+                    None => break,
+                },
+                '<' | '>' if context != Context::Brace && self.rest()[1..].starts_with('(') => {
+                    let output = c == '>';
+                    let script = self.substitution(if output { ">(" } else { "<(" })?;
+                    spans.push(Span::Process { script, output });
+                }
+                '(' | ')' | '|' if context == Context::Regex => {
+                    self.pos += 1;
+                    push_literal(&mut spans, &c.to_string(), false);
+                }
+                ',' if context == Context::Brace => break,
+                c if c == '*' || c == '?' || is_word_char(c) => {
+                    self.pos += c.len_utf8();
+                    push_literal(&mut spans, &c.to_string(), false);
+                }
+                _ => break,
+            }
+        }
+        Ok((self.pos > start).then_some(Word(spans)))
+    }
+
+    /// Lexes the regex on the right of `=~`.
+    pub fn regex(&mut self) -> Result<Option<Token>> {
+        self.skip_blanks();
+        let start = self.pos;
+        Ok(self.word(Context::Regex)?.map(|word| Token {
+            kind: TokenKind::Word(word),
+            start,
+            end: self.pos,
+        }))
+    }
+
+    /// Lexes `~` or `~user` at the beginning of a word, or after `name=` as
+    /// in `--prefix=~/usr`.
+    fn tilde(&mut self, spans: &mut Vec<Span>) {
+        let rest = self.rest();
+        let prefix = rest
+            .find(|c: char| c != '-' && c != '_' && !c.is_ascii_alphanumeric())
+            .unwrap_or(rest.len());
+        let prefix = if prefix > 0 && rest[prefix..].starts_with("=~") {
+            prefix + 1
+        } else {
+            0
+        };
+        if !rest[prefix..].starts_with('~') {
+            return;
+        }
+        if prefix > 0 {
+            spans.push(Span::Literal(rest[..prefix].to_owned()));
+        }
+        let user = &rest[prefix + 1..];
+        let user = &user[..user
+            .find(|c: char| c == '/' || !is_word_char(c))
+            .unwrap_or(user.len())];
+        spans.push(Span::Tilde(user.to_owned()));
+        self.pos += prefix + 1 + user.len();
+    }
+
+    /// Lexes `'...'`, in which nothing is special, not even a backslash.
+    fn single_quoted(&mut self) -> Result<Span> {
+        let start = self.pos;
+        let len = self.rest()[1..]
+            .find('\'')
+            .ok_or_else(|| self.unclosed(start, "unterminated single quote started here"))?;
+        self.pos += len + 2;
+        Ok(Span::Quoted(
+            self.src[start + 1..start + 1 + len].to_owned(),
+        ))
+    }
+
+    /// Lexes `"..."`. `""` still makes an (empty) span.
+    fn double_quoted(&mut self) -> Result<Vec<Span>> {
+        let start = self.pos;
+        self.pos += 1;
+        let spans = self.quoted_text(Some(start))?;
+        if spans.is_empty() {
+            return Ok(vec![Span::Quoted(String::new())]);
+        }
+        Ok(spans)
+    }
+
+    /// Lexes the text in double quotes which start at `quote`, or the body
+    /// of a here document if there are none, where `"` is not special. A
+    /// backslash only escapes `$`, `` ` ``, `\` and `"` in double quotes, and
+    /// joins lines before a newline.
+    fn quoted_text(&mut self, quote: Option<usize>) -> Result<Vec<Span>> {
+        let escapable = if quote.is_some() { "$`\"\\" } else { "$`\\" };
+        let mut spans = Vec::new();
+        let mut text = String::new();
+        loop {
+            let span = match self.peek() {
+                None => match quote {
+                    Some(start) => {
+                        return Err(self.unclosed(start, "unterminated double quote started here"))
+                    }
+                    None => break,
+                },
+                Some('"') if quote.is_some() => {
+                    self.pos += 1;
+                    break;
+                }
+                Some('$') => match self.dollar(true)? {
+                    Some(span) => span,
+                    None => {
+                        text.push('$');
+                        continue;
+                    }
+                },
+                Some('`') => self.backquoted(true)?,
+                Some('\\') => {
+                    self.pos += 1;
+                    match self.bump() {
+                        Some('\n') => (),
+                        Some(c) if escapable.contains(c) => text.push(c),
+                        Some(c) => {
+                            text.push('\\');
+                            text.push(c);
+                        }
+                        None => text.push('\\'),
+                    }
+                    continue;
+                }
+                Some(c) => {
+                    self.pos += c.len_utf8();
+                    text.push(c);
+                    continue;
+                }
+            };
+            if !text.is_empty() {
+                spans.push(Span::Quoted(std::mem::take(&mut text)));
+            }
+            spans.push(span);
+        }
+        if !text.is_empty() {
+            spans.push(Span::Quoted(text));
+        }
+        Ok(spans)
+    }
+
+    /// Lexes `` `script` ``, in which a backslash escapes `$`, `` ` `` and
+    /// `\`.
+    fn backquoted(&mut self, quoted: bool) -> Result<Span> {
+        let start = self.pos;
+        self.pos += 1;
+        loop {
+            match self.bump() {
+                None => return Err(self.unclosed(start, "unterminated backquote started here")),
+                Some('\\') => {
+                    self.bump();
+                }
+                Some('`') => break,
+                Some(_) => (),
+            }
+        }
+        let script = unescape(&self.src[start + 1..self.pos - 1], "$`\\");
+        Ok(Span::Command { script, quoted })
+    }
+
+    /// Lexes a character escaped with a backslash, which is taken as if it
+    /// were quoted. A backslash before a newline joins the lines.
+    fn escaped(&mut self, spans: &mut Vec<Span>) -> Result<()> {
+        self.pos += 1;
+        match self.bump() {
+            Some('\n') => (),
+            Some(c) => push_literal(spans, &c.to_string(), true),
+            // A backslash has to escape something.
+            None => return Err(unexpected(self.src, self.pos)),
+        }
+        Ok(())
+    }
+
+    /// Lexes an expansion starting with `$`, or returns `None` if the `$`
+    /// doesn't start one and is taken as it is.
+    fn dollar(&mut self, quoted: bool) -> Result<Option<Span>> {
+        let rest = &self.rest()[1..];
+        if rest.starts_with("((") {
+            if let Some(span) = self.arithmetic()? {
+                return Ok(Some(span));
+            }
+        }
+        if rest.starts_with('(') {
+            let script = self.substitution("$(")?;
+            return Ok(Some(Span::Command { script, quoted }));
+        }
+        if rest.starts_with('{') {
+            return self.braced_parameter(quoted).map(Some);
+        }
+        self.pos += 1;
+        match self.parameter_name() {
+            Some(name) => Ok(Some(Span::Parameter {
+                name,
+                index: None,
+                op: None,
+                quoted,
+            })),
+            None => Ok(None),
+        }
+    }
+
+    /// Lexes `$((expr))`. The expression is evaluated as it is, so only the
+    /// parentheses in it have to be matched. Returns `None` if a `)` closes
+    /// the outer parentheses alone, as in `$((cd dir); ls)`.
+    fn arithmetic(&mut self) -> Result<Option<Span>> {
+        let start = self.pos;
+        let body = start + 3;
+        let mut depth = 0;
+        for (i, c) in self.src[body..].char_indices() {
+            match c {
+                '(' => depth += 1,
+                ')' if depth > 0 => depth -= 1,
+                ')' if self.src[body + i..].starts_with("))") => {
+                    self.pos = body + i + 2;
+                    return Ok(Some(Span::Arithmetic(self.src[body..body + i].to_owned())));
+                }
+                ')' => return Ok(None),
+                _ => (),
+            }
+        }
+        Err(self.unclosed(start, "`$((` started here is not closed with `))`"))
+    }
+
+    /// Lexes `$(script)`, `<(script)` or `>(script)`, whose script is parsed
+    /// to find the `)` which closes it, and returns the script.
+    fn substitution(&mut self, opener: &str) -> Result<String> {
+        let start = self.pos;
+        let body = start + opener.len();
+        let mut parser = Parser::new(Lexer::at(self.src, body));
+        parser.compound_list()?;
+        let token = parser.next()?;
+        match token.kind {
+            TokenKind::Operator(")") => {
+                self.pos = token.end;
+                Ok(self.src[body..token.start].to_owned())
+            }
+            TokenKind::Eof => Err(self.unclosed(
+                start,
+                format!("`{}` started here is not closed with `)`", opener),
+            )),
+            _ => Err(parser.unexpected(&token)),
+        }
+    }
+
+    /// Lexes the name in `$name` or `${name}`, which is a variable or a
+    /// special parameter.
+    fn parameter_name(&mut self) -> Option<String> {
+        let rest = self.rest();
+        let len = match rest.find(|c: char| c != '_' && !c.is_ascii_alphanumeric()) {
+            Some(0) if rest.starts_with(['?', '$', '!', '*', '@', '#', '-']) => 1,
+            Some(0) => return None,
+            Some(len) => len,
+            None if rest.is_empty() => return None,
+            None => rest.len(),
+        };
+        self.pos += len;
+        Some(rest[..len].to_owned())
+    }
+
+    /// Lexes `${...}`, where the name may have an index such as in
+    /// `${name[1]}`.
+    fn braced_parameter(&mut self, quoted: bool) -> Result<Span> {
+        let start = self.pos;
+        self.pos += 2;
+        // `${#name}`, or `${#}` for the number of positional parameters.
+        if self.peek() == Some('#') {
+            let hash = self.pos;
+            self.pos += 1;
+            if let Some(name) = self.parameter_name() {
+                let index = self.index();
+                if self.eat("}") {
+                    return Ok(Self::parameter(
+                        name,
+                        index,
+                        Some(ParameterOp::Length),
+                        quoted,
+                    ));
+                }
+            }
+            self.pos = hash;
+        }
+        let name = self.parameter_name();
+        let index = self.index();
+        let op = match name {
+            Some(_) => self.parameter_op()?,
+            None => None,
+        };
+        match (name, self.eat("}")) {
+            (Some(name), true) => Ok(Self::parameter(name, index, op, quoted)),
+            _ if self.pos >= self.src.len() => {
+                Err(self.unclosed(start, "`${` started here is not closed with `}`"))
+            }
+            _ => Err(Diagnostic::new("bad substitution", start)),
+        }
+    }
+
+    fn parameter(
+        name: String,
+        index: Option<String>,
+        op: Option<ParameterOp>,
+        quoted: bool,
+    ) -> Span {
+        Span::Parameter {
+            name,
+            index,
+            op,
+            quoted,
+        }
+    }
+
+    /// Lexes `[index]` after the name of a parameter if there is one. An
+    /// empty index is left for the caller to report as a bad substitution.
+    fn index(&mut self) -> Option<String> {
+        if !self.rest().starts_with('[') {
+            return None;
+        }
+        let end = self.rest().find(']')?;
+        let index = self.rest()[1..end].trim().to_owned();
+        if index.is_empty() {
+            return None;
+        }
+        self.pos += end + 1;
+        Some(index)
+    }
+
+    /// Lexes the operator after the name in `${name<op>...}` and the words
+    /// after it.
+    fn parameter_op(&mut self) -> Result<Option<ParameterOp>> {
+        if self.rest().starts_with('/') {
+            let all = self.eat("//") || !self.eat("/");
+            let pattern = self.parameter_word(true)?;
+            let string = if self.eat("/") {
+                self.parameter_word(false)?
+            } else {
+                Word(Vec::new())
+            };
+            return Ok(Some(ParameterOp::Replace {
+                pattern,
+                string,
+                all,
+            }));
+        }
+        let op = match PARAMETER_OPS
+            .iter()
+            .find(|op| self.rest().starts_with(**op))
+        {
+            Some(op) => *op,
+            None => return Ok(None),
+        };
+        self.pos += op.len();
+        let word = self.parameter_word(false)?;
+        let colon = op.starts_with(':');
+        Ok(Some(match op {
+            ":-" | "-" => ParameterOp::Default { word, colon },
+            ":=" | "=" => ParameterOp::Assign { word, colon },
+            ":?" | "?" => ParameterOp::Error { word, colon },
+            ":+" | "+" => ParameterOp::Alternative { word, colon },
+            "#" | "##" => ParameterOp::RemovePrefix {
+                pattern: word,
+                longest: op == "##",
+            },
+            _ => ParameterOp::RemoveSuffix {
+                pattern: word,
+                longest: op == "%%",
+            },
+        }))
+    }
+
+    /// Lexes the word in `${name:-word}`, which can have whitespace in it, or
+    /// the `pattern` in `${name/pattern/string}`, which ends at `/`. Braces
+    /// in the word are literal, but they nest so that `${x:-{}}` ends at the
+    /// last `}`.
+    fn parameter_word(&mut self, pattern: bool) -> Result<Word> {
+        let mut spans = Vec::new();
+        let mut depth = 0;
+        while let Some(c) = self.peek() {
+            match c {
+                '}' if depth == 0 => break,
+                '/' if pattern => break,
+                '\'' => spans.push(self.single_quoted()?),
+                '"' => spans.extend(self.double_quoted()?),
+                '`' => spans.push(self.backquoted(false)?),
+                '$' => match self.dollar(false)? {
+                    Some(span) => spans.push(span),
+                    None => push_literal(&mut spans, "$", false),
+                },
+                '\\' => self.escaped(&mut spans)?,
+                c => {
+                    match c {
+                        '{' => depth += 1,
+                        '}' => depth -= 1,
+                        _ => (),
+                    }
+                    self.pos += c.len_utf8();
+                    push_literal(&mut spans, &c.to_string(), false);
+                }
+            }
+        }
+        Ok(Word(spans))
+    }
+
+    /// Lexes `{a,b}` or `{1..3}`. Braces without a `,` or a range, such as
+    /// `{a}`, are kept. Returns `None` if the braces don't make a word, e.g.
+    /// in `{ echo; }`.
+    fn brace(&mut self) -> Result<Option<Span>> {
+        let start = self.pos;
+        self.pos += 1;
+        if let Some(bounds) = self.brace_range() {
+            if self.eat("}") {
+                let text = self.src[start..self.pos].to_owned();
+                return Ok(Some(match brace_range(&bounds) {
+                    Some(alternatives) => Span::Brace { text, alternatives },
+                    None => Span::Literal(text),
+                }));
+            }
+        }
+
+        self.pos = start + 1;
+        let mut alternatives = vec![self.brace_word()?];
+        while self.eat(",") {
+            alternatives.push(self.brace_word()?);
+        }
+        if !self.eat("}") {
+            self.pos = start;
+            return Ok(None);
+        }
+        if alternatives.len() == 1 {
+            let Word(mut spans) = alternatives.pop().unwrap();
+            spans.insert(0, Span::Literal("{".to_owned()));
+            spans.push(Span::Literal("}".to_owned()));
+            alternatives.push(Word(spans));
+        }
+        let text = self.src[start..self.pos].to_owned();
+        Ok(Some(Span::Brace { text, alternatives }))
+    }
+
+    fn brace_word(&mut self) -> Result<Word> {
+        Ok(self.word(Context::Brace)?.unwrap_or(Word(Vec::new())))
+    }
+
+    /// Lexes `x..y` or `x..y..step` in braces, where `x` and `y` are both
+    /// integers or both letters.
+    fn brace_range(&mut self) -> Option<Vec<&'a str>> {
+        let rest = self.rest();
+        let integer = |s: &str| {
+            let sign = usize::from(s.starts_with(['-', '+']));
+            let digits = s[sign..]
+                .find(|c: char| !c.is_ascii_digit())
+                .unwrap_or(s.len() - sign);
+            (digits > 0).then_some(sign + digits)
+        };
+        let letter = |s: &str| {
+            s.starts_with(|c: char| c.is_ascii_alphabetic())
+                .then_some(1)
+        };
+
+        let bound: &dyn Fn(&str) -> Option<usize> = if integer(rest).is_some() {
+            &integer
+        } else {
+            &letter
+        };
+        let mut bounds = Vec::new();
+        let mut len = 0;
+        for i in 0..3 {
+            if i > 0 {
+                if !rest[len..].starts_with("..") {
+                    break;
+                }
+                len += 2;
+            }
+            let bound_len = if i == 2 {
+                integer(&rest[len..])
+            } else {
+                bound(&rest[len..])
+            }?;
+            bounds.push(&rest[len..len + bound_len]);
+            len += bound_len;
+        }
+        if bounds.len() < 2 {
+            return None;
+        }
+        self.pos += len;
+        Some(bounds)
+    }
+
+    /// Lexes the delimiter after `<<` and reads the body of the here
+    /// document, which starts on the next line.
+    fn heredoc(&mut self, start: usize, fd: Option<i32>, strip_tabs: bool) -> Result<TokenKind> {
+        while matches!(self.peek(), Some(' ' | '\t')) {
+            self.pos += 1;
+        }
+        let word = self.pos;
+        if self.word(Context::Word)?.is_none() {
+            return Err(unexpected(self.src, self.pos));
+        }
+        let (delimiter, quoted) = heredoc_delimiter(&self.src[word..self.pos]);
+        if self.lookahead {
+            return Ok(TokenKind::Heredoc {
+                fd,
+                body: Word(Vec::new()),
+            });
+        }
+
+        // The bodies of the here documents on a line follow each other.
+        let body_start = match self.heredocs_end {
+            Some(end) => end,
+            None => self.line_end()?,
+        };
+        let mut body = String::new();
+        let mut end = None;
+        let mut offset = body_start;
+        for line in self.src[body_start..].split_inclusive('\n') {
+            offset += line.len();
+            let line = if strip_tabs {
+                line.trim_start_matches('\t')
+            } else {
+                line
+            };
+            if line.strip_suffix('\n').unwrap_or(line) == delimiter {
+                end = Some(offset);
+                break;
+            }
+            body.push_str(line);
+        }
+        let end = end.ok_or_else(|| {
+            self.unclosed(
+                start,
+                format!(
+                    "here document started here is not terminated by `{}`",
+                    delimiter
+                ),
+            )
+        })?;
+        self.heredocs_end = Some(end);
+
+        let spans = if quoted {
+            vec![Span::Quoted(body)]
+        } else {
+            heredoc_body(&body)
+        };
+        Ok(TokenKind::Heredoc {
+            fd,
+            body: Word(spans),
+        })
+    }
+
+    /// Finds where the current line ends, which is not always at the next
+    /// `\n`, e.g. if it is in quotes.
+    fn line_end(&self) -> Result<usize> {
+        let mut ahead = Lexer {
+            heredocs_end: None,
+            lookahead: true,
+            ..self.clone()
+        };
+        loop {
+            let token = ahead.next_token()?;
+            match token.kind {
+                TokenKind::Newline => return Ok(token.end),
+                TokenKind::Eof => return Ok(self.src.len()),
+                _ => (),
+            }
+        }
+    }
+
+    /// Lexes `((init; condition; step))` after `for`, or returns `None` if
+    /// there isn't one. The expressions are evaluated as they are, like in
+    /// `$((...))`.
+    pub fn arith_for(&mut self) -> Result<Option<[String; 3]>> {
+        self.skip_blanks();
+        let start = self.pos;
+        if !self.eat("((") {
+            return Ok(None);
+        }
+        let mut exprs: [String; 3] = Default::default();
+        for (i, expr) in exprs.iter_mut().enumerate() {
+            let expr_start = self.pos;
+            let mut depth = 0;
+            loop {
+                let end = self.pos;
+                match self.bump() {
+                    None => {
+                        return Err(
+                            self.unclosed(start, "`((` started here is not closed with `))`")
+                        )
+                    }
+                    Some('(') => depth += 1,
+                    Some(')') if depth > 0 => depth -= 1,
+                    Some(')') if i == 2 && self.eat(")") => {
+                        *expr = self.src[expr_start..end].to_owned();
+                        break;
+                    }
+                    Some(';') if depth == 0 && i < 2 => {
+                        *expr = self.src[expr_start..end].to_owned();
+                        break;
+                    }
+                    Some(')' | ';') => return Err(unexpected(self.src, end)),
+                    Some(_) => (),
+                }
+            }
+        }
+        Ok(Some(exprs))
+    }
+}
+
+/// Splits the body of a here document into the expansions in it. Like in
+/// double quotes, a backslash escapes `$`, `` ` `` and `\`.
+fn heredoc_body(body: &str) -> Vec<Span> {
+    Lexer::new(body)
+        .quoted_text(None)
+        // E.g. an unterminated `$(`, which is left as it is.
+        .unwrap_or_else(|_| vec![Span::Quoted(unescape(body, "$`\\"))])
+}
+
+/// Returns the delimiter of a here document without the quotes in `word`,
+/// and whether there were any, which leave the body as it is.
+fn heredoc_delimiter(word: &str) -> (String, bool) {
+    let mut delimiter = String::new();
+    let mut quoted = false;
+    let mut chars = word.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '\'' | '"' => {
+                quoted = true;
+                for inner in chars.by_ref() {
+                    if inner == c {
+                        break;
+                    }
+                    delimiter.push(inner);
+                }
+            }
+            '\\' => {
+                quoted = true;
+                delimiter.extend(chars.next());
+            }
+            c => delimiter.push(c),
+        }
+    }
+    (delimiter, quoted)
+}
+
+/// Makes the words of `{x..y}` or `{x..y..step}`. Integers are padded with
+/// zeros to the same width if either of them starts with `0`, e.g.
+/// `{01..10}`. Returns `None` if they are too large or there would be too
+/// many words.
+fn brace_range(bounds: &[&str]) -> Option<Vec<Word>> {
+    let step = match bounds.get(2) {
+        Some(step) => step.parse::<i64>().ok()?.unsigned_abs().max(1),
+        None => 1,
+    };
+    let (start, end) = (bounds[0], bounds[1]);
+    let (values, width): (Vec<String>, usize) = match (start.parse::<i64>(), end.parse::<i64>()) {
+        (Ok(from), Ok(to)) => {
+            let padded = [start, end].iter().any(|s| {
+                let digits = s.trim_start_matches(['-', '+']);
+                digits.len() > 1 && digits.starts_with('0')
+            });
+            let width = if padded {
+                start.len().max(end.len())
+            } else {
+                0
+            };
+            (
+                range(from, to, step)?.map(|n| n.to_string()).collect(),
+                width,
+            )
+        }
+        (Err(_), Err(_))
+            if !start.starts_with(|c: char| c.is_ascii_digit() || c == '-' || c == '+') =>
+        {
+            let (from, to) = (start.as_bytes()[0] as i64, end.as_bytes()[0] as i64);
+            let values = range(from, to, step)?
+                .map(|c| (c as u8 as char).to_string())
+                .collect();
+            (values, 0)
+        }
+        _ => return None,
+    };
+    Some(
+        values
+            .into_iter()
+            .map(|value| {
+                let value = match value.strip_prefix('-') {
+                    Some(digits) => format!("-{:0>1$}", digits, width.saturating_sub(1)),
+                    None => format!("{:0>1$}", value, width),
+                };
+                Word(vec![Span::Literal(value)])
+            })
+            .collect(),
+    )
+}
+
+/// The integers from `from` to `to`, either way, every `step`, or `None` if
+/// there are more than `MAX_BRACE_RANGE_LEN` of them.
+fn range(from: i64, to: i64, step: u64) -> Option<impl Iterator<Item = i64>> {
+    let len = (from.abs_diff(to) / step).checked_add(1)?;
+    if len > MAX_BRACE_RANGE_LEN {
+        return None;
+    }
+    // The values are between `from` and `to`, but the offsets from `from`
+    // may not fit in an `i64`.
+    let step = if from <= to {
+        step as i128
+    } else {
+        -(step as i128)
+    };
+    Some((0..len).map(move |i| (from as i128 + i as i128 * step) as i64))
+}
+
+/// Whether `c` can be in a word without quotes.
+fn is_word_char(c: char) -> bool {
+    !"|&;<>(){}$\"'`*?\\ \t\r\n".contains(c)
+}
+
+/// Pushes the text of a literal, as a quoted one if it was escaped with a
+/// backslash, joining it with the span before if it is the same kind.
+fn push_literal(spans: &mut Vec<Span>, text: &str, quoted: bool) {
+    match (spans.last_mut(), quoted) {
+        (Some(Span::Quoted(s)), true) | (Some(Span::Literal(s)), false) => s.push_str(text),
+        (_, true) => spans.push(Span::Quoted(text.to_owned())),
+        (_, false) => spans.push(Span::Literal(text.to_owned())),
+    }
+}
+
+/// Removes the backslashes before the characters in `escapable` and before
+/// newlines, which join the lines. Other backslashes are left as they are.
+fn unescape(text: &str, escapable: &str) -> String {
+    let mut unescaped = String::new();
+    let mut chars = text.chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            unescaped.push(c);
+            continue;
+        }
+        match chars.next() {
+            Some('\n') => (),
+            Some(c) if escapable.contains(c) => unescaped.push(c),
+            Some(c) => {
+                unescaped.push('\\');
+                unescaped.push(c);
+            }
+            None => unescaped.push('\\'),
+        }
+    }
+    unescaped
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tokens(src: &str) -> Vec<TokenKind> {
+        let mut lexer = Lexer::new(src);
+        let mut tokens = Vec::new();
+        loop {
+            let token = lexer.next_token().unwrap();
+            if token.kind == TokenKind::Eof {
+                return tokens;
+            }
+            tokens.push(token.kind);
+        }
+    }
+
+    /// Lexes `src`, which has to be a word.
+    fn spans(src: &str) -> Vec<Span> {
+        match Lexer::new(src).next_token().unwrap().kind {
+            TokenKind::Word(Word(spans)) => spans,
+            kind => panic!("{:?} is not a word", kind),
+        }
+    }
+
+    fn literal(text: &str) -> Span {
+        Span::Literal(text.to_owned())
+    }
+
+    fn quoted(text: &str) -> Span {
+        Span::Quoted(text.to_owned())
+    }
+
+    fn parameter(name: &str, op: Option<ParameterOp>, quoted: bool) -> Span {
+        Span::Parameter {
+            name: name.to_owned(),
+            index: None,
+            op,
+            quoted,
+        }
+    }
+
+    fn word(text: &str) -> TokenKind {
+        TokenKind::Word(Word(vec![literal(text)]))
+    }
+
+    #[test]
+    fn operators_are_the_longest_matches() {
+        assert_eq!(
+            tokens("a;;b;&c;;&d&&e||f|g&h;"),
+            vec![
+                word("a"),
+                TokenKind::Operator(";;"),
+                word("b"),
+                TokenKind::Operator(";&"),
+                word("c"),
+                TokenKind::Operator(";;&"),
+                word("d"),
+                TokenKind::Operator("&&"),
+                word("e"),
+                TokenKind::Operator("||"),
+                word("f"),
+                TokenKind::Operator("|"),
+                word("g"),
+                TokenKind::Operator("&"),
+                word("h"),
+                TokenKind::Operator(";"),
+            ]
+        );
+    }
+
+    #[test]
+    fn descriptors_have_to_be_right_before_redirections() {
+        assert_eq!(
+            tokens("2>x 2 >>x &>y 3<&0"),
+            vec![
+                TokenKind::Redirect {
+                    fd: Some(2),
+                    op: ">"
+                },
+                word("x"),
+                word("2"),
+                TokenKind::Redirect { fd: None, op: ">>" },
+                word("x"),
+                TokenKind::Redirect { fd: None, op: "&>" },
+                word("y"),
+                TokenKind::Redirect {
+                    fd: Some(3),
+                    op: "<&"
+                },
+                word("0"),
+            ]
+        );
+    }
+
+    #[test]
+    fn blanks_and_comments_separate_tokens() {
+        assert_eq!(
+            tokens("a \t\\\nb # c\nd#e"),
+            vec![word("a"), word("b"), TokenKind::Newline, word("d#e")]
+        );
+    }
+
+    #[test]
+    fn braces_are_operators_unless_they_make_words() {
+        assert_eq!(
+            tokens("{ a; }"),
+            vec![
+                TokenKind::Operator("{"),
+                word("a"),
+                TokenKind::Operator(";"),
+                TokenKind::Operator("}"),
+            ]
+        );
+    }
+
+    #[test]
+    fn escaped_characters_are_quoted() {
+        assert_eq!(
+            spans("a\\ b\\\nc\\*"),
+            vec![literal("a"), quoted(" "), literal("bc"), quoted("*")]
+        );
+    }
+
+    #[test]
+    fn quotes_are_removed() {
+        assert_eq!(
+            spans(r#"'a $b'"c \$d \e""#),
+            vec![quoted("a $b"), quoted("c $d \\e")]
+        );
+        assert_eq!(spans("''"), vec![quoted("")]);
+        assert_eq!(spans(r#""""#), vec![quoted("")]);
+    }
+
+    #[test]
+    fn expansions_in_double_quotes_are_quoted() {
+        assert_eq!(
+            spans(r#""a $b $(c) `d` $""#),
+            vec![
+                quoted("a "),
+                parameter("b", None, true),
+                quoted(" "),
+                Span::Command {
+                    script: "c".to_owned(),
+                    quoted: true
+                },
+                quoted(" "),
+                Span::Command {
+                    script: "d".to_owned(),
+                    quoted: true
+                },
+                quoted(" $"),
+            ]
+        );
+    }
+
+    #[test]
+    fn tildes_start_words_or_follow_assignments() {
+        assert_eq!(
+            spans("~user/bin"),
+            vec![Span::Tilde("user".to_owned()), literal("/bin")]
+        );
+        assert_eq!(
+            spans("--prefix=~"),
+            vec![literal("--prefix="), Span::Tilde(String::new())]
+        );
+        assert_eq!(spans("a~"), vec![literal("a~")]);
+    }
+
+    #[test]
+    fn parameters() {
+        assert_eq!(
+            spans("$a$1$?${b}$"),
+            vec![
+                parameter("a", None, false),
+                parameter("1", None, false),
+                parameter("?", None, false),
+                parameter("b", None, false),
+                literal("$"),
+            ]
+        );
+    }
+
+    #[test]
+    fn parameter_indices() {
+        let indexed = |name: &str, index: &str, op| Span::Parameter {
+            name: name.to_owned(),
+            index: Some(index.to_owned()),
+            op,
+            quoted: false,
+        };
+        assert_eq!(
+            spans("${PIPESTATUS[1]}${a[@]}${#a[*]}"),
+            vec![
+                indexed("PIPESTATUS", "1", None),
+                indexed("a", "@", None),
+                indexed("a", "*", Some(ParameterOp::Length)),
+            ]
+        );
+        for src in ["${a[]}", "${a[1}"] {
+            let err = Lexer::new(src).next_token().unwrap_err();
+            assert_eq!(err.message, "bad substitution", "{}", src);
+        }
+    }
+
+    #[test]
+    fn parameter_operators() {
+        let word = |text: &str| Word(vec![literal(text)]);
+        assert_eq!(
+            spans("${#a}${a:-b c}${a+}${a##*/}${a%x}${a//x/y}"),
+            vec![
+                parameter("a", Some(ParameterOp::Length), false),
+                parameter(
+                    "a",
+                    Some(ParameterOp::Default {
+                        word: word("b c"),
+                        colon: true
+                    }),
+                    false
+                ),
+                parameter(
+                    "a",
+                    Some(ParameterOp::Alternative {
+                        word: Word(Vec::new()),
+                        colon: false
+                    }),
+                    false
+                ),
+                parameter(
+                    "a",
+                    Some(ParameterOp::RemovePrefix {
+                        pattern: word("*/"),
+                        longest: true
+                    }),
+                    false
+                ),
+                parameter(
+                    "a",
+                    Some(ParameterOp::RemoveSuffix {
+                        pattern: word("x"),
+                        longest: false
+                    }),
+                    false
+                ),
+                parameter(
+                    "a",
+                    Some(ParameterOp::Replace {
+                        pattern: word("x"),
+                        string: word("y"),
+                        all: true
+                    }),
+                    false
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn braces_in_parameter_words() {
+        let default = |text: &str| {
+            parameter(
+                "x",
+                Some(ParameterOp::Default {
+                    word: Word(vec![literal(text)]),
+                    colon: true,
+                }),
+                false,
+            )
+        };
+        assert_eq!(spans("${x:-{}}"), [default("{}")]);
+        assert_eq!(spans("${x:-{a,b}}c"), [default("{a,b}"), literal("c")]);
+        assert_eq!(spans("${x:-{{}}}"), [default("{{}}")]);
+        // A quoted `}` doesn't end the word.
+        let quoted = parameter(
+            "x",
+            Some(ParameterOp::Default {
+                word: Word(vec![Span::Quoted("}".to_owned())]),
+                colon: true,
+            }),
+            false,
+        );
+        assert_eq!(spans("${x:-\\}}"), std::slice::from_ref(&quoted));
+        assert_eq!(spans("${x:-'}'}"), [quoted]);
+    }
+
+    #[test]
+    fn bad_substitutions() {
+        let err = Lexer::new("${a:0:1}").next_token().unwrap_err();
+        assert_eq!(err, Diagnostic::new("bad substitution", 0));
+        let err = Lexer::new("x${a").next_token().unwrap_err();
+        assert_eq!(
+            err,
+            Diagnostic::new("unexpected end of input", 4)
+                .with_hint(1, "`${` started here is not closed with `}`")
+        );
+    }
+
+    #[test]
+    fn substitutions_end_where_their_scripts_do() {
+        assert_eq!(
+            spans("$(case x in x) echo ')';; esac)`a \\` b`"),
+            vec![
+                Span::Command {
+                    script: "case x in x) echo ')';; esac".to_owned(),
+                    quoted: false
+                },
+                Span::Command {
+                    script: "a ` b".to_owned(),
+                    quoted: false
+                },
+            ]
+        );
+        assert_eq!(
+            spans("<(a)>(b)"),
+            vec![
+                Span::Process {
+                    script: "a".to_owned(),
+                    output: false
+                },
+                Span::Process {
+                    script: "b".to_owned(),
+                    output: true
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn arithmetic_needs_matching_parentheses() {
+        assert_eq!(
+            spans("$((1 + (2)))"),
+            vec![Span::Arithmetic("1 + (2)".to_owned())]
+        );
+        assert_eq!(
+            spans("$((a); b)"),
+            vec![Span::Command {
+                script: "(a); b".to_owned(),
+                quoted: false
+            }]
+        );
+    }
+
+    #[test]
+    fn brace_lists() {
+        let words = |texts: &[&str]| texts.iter().map(|text| Word(vec![literal(text)])).collect();
+        assert_eq!(
+            spans("a{b,c}"),
+            vec![
+                literal("a"),
+                Span::Brace {
+                    text: "{b,c}".to_owned(),
+                    alternatives: words(&["b", "c"])
+                }
+            ]
+        );
+        assert_eq!(
+            spans("{a}"),
+            vec![Span::Brace {
+                text: "{a}".to_owned(),
+                alternatives: vec![Word(vec![literal("{"), literal("a"), literal("}")])]
+            }]
+        );
+    }
+
+    #[test]
+    fn brace_ranges() {
+        let alternatives = |src: &str| match spans(src).pop() {
+            Some(Span::Brace { alternatives, .. }) => alternatives
+                .into_iter()
+                .map(|Word(spans)| match &spans[..] {
+                    [Span::Literal(text)] => text.clone(),
+                    spans => panic!("{:?}", spans),
+                })
+                .collect::<Vec<_>>(),
+            span => panic!("{:?}", span),
+        };
+        assert_eq!(alternatives("{1..3}"), ["1", "2", "3"]);
+        assert_eq!(alternatives("{c..a}"), ["c", "b", "a"]);
+        assert_eq!(alternatives("{08..10}"), ["08", "09", "10"]);
+        assert_eq!(alternatives("{-1..5..3}"), ["-1", "2", "5"]);
+        assert_eq!(
+            spans("{1..a}")[0],
+            Span::Brace {
+                text: "{1..a}".to_owned(),
+                alternatives: vec![Word(vec![literal("{"), literal("1..a"), literal("}")])]
+            }
+        );
+        assert_eq!(
+            alternatives("{-9223372036854775808..9223372036854775807..4611686018427387904}"),
+            [
+                "-9223372036854775808",
+                "-4611686018427387904",
+                "0",
+                "4611686018427387904"
+            ]
+        );
+        for huge in [
+            "{-9223372036854775808..9223372036854775807}",
+            "{0..9223372036854775807}",
+            "{1..1000000}",
+        ] {
+            assert_eq!(spans(huge), [literal(huge)]);
+        }
+    }
+
+    #[test]
+    fn heredocs_are_read_after_the_line() {
+        assert_eq!(
+            tokens("cat <<A <<-'B'; x\n$a\nA\n\t$b\n\tB\ny"),
+            vec![
+                word("cat"),
+                TokenKind::Heredoc {
+                    fd: None,
+                    body: Word(vec![parameter("a", None, true), quoted("\n")])
+                },
+                TokenKind::Heredoc {
+                    fd: None,
+                    body: Word(vec![quoted("$b\n")])
+                },
+                TokenKind::Operator(";"),
+                word("x"),
+                TokenKind::Newline,
+                word("y"),
+            ]
+        );
+    }
+
+    #[test]
+    fn unterminated_heredocs() {
+        let mut lexer = Lexer::new("cat <<EOF\nx\n");
+        assert_eq!(lexer.next_token().unwrap().kind, word("cat"));
+        assert_eq!(
+            lexer.next_token().unwrap_err(),
+            Diagnostic::new("unexpected end of input", 12)
+                .with_hint(4, "here document started here is not terminated by `EOF`")
+        );
+    }
+
+    #[test]
+    fn unterminated_quotes() {
+        assert_eq!(
+            Lexer::new("'b").next_token(),
+            Err(Diagnostic::new("unexpected end of input", 2)
+                .with_hint(0, "unterminated single quote started here"))
+        );
+        assert_eq!(
+            Lexer::new("\"b").next_token(),
+            Err(Diagnostic::new("unexpected end of input", 2)
+                .with_hint(0, "unterminated double quote started here"))
+        );
+    }
+
+    #[test]
+    fn regexes_take_parentheses_and_bars() {
+        let mut lexer = Lexer::new(" ^(a|b)$ ]]");
+        assert_eq!(
+            lexer.regex().unwrap().map(|token| token.kind),
+            Some(word("^(a|b)$"))
+        );
+    }
+
+    #[test]
+    fn arith_for_expressions() {
+        let mut lexer = Lexer::new(" ((i = 0; i < (3); i++)) ; do");
+        assert_eq!(
+            lexer.arith_for().unwrap(),
+            Some(["i = 0".to_owned(), " i < (3)".to_owned(), " i++".to_owned()])
+        );
+        assert_eq!(lexer.next_token().unwrap().kind, TokenKind::Operator(";"));
+    }
+}
@@ -0,0 +1,1447 @@
+//! Parses scripts into syntax trees. The lexer splits a script into words
+//! and operators, and the parser builds the tree from them by recursive
+//! descent, with a method for each production.
+
+mod ast;
+mod lexer;
+
+use std::collections::VecDeque;
+use std::rc::Rc;
+
+pub use ast::*;
+use lexer::{Lexer, Result, Token, TokenKind};
+
+use crate::diagnostic::Diagnostic;
+
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub enum ParseError {
+    Fatal(Diagnostic),
+    /// The script ends in the middle of something, e.g. a quote, an `if`
+    /// without `fi` or a here document, so more lines may complete it.
+    Incomplete(Diagnostic),
+    Empty,
+}
+
+/// The words which start or end compound commands, and can't be the names
+/// of simple commands.
+const RESERVED_WORDS: &[&str] = &[
+    "break", "case", "continue", "done", "do", "elif", "else", "esac", "fi", "for", "function",
+    "if", "in", "local", "return", "then", "until", "while", "[[",
+];
+
+/// The reserved words which end compound lists, e.g. `then` after the
+/// condition of `if`.
+const CLOSING_WORDS: &[&str] = &["do", "done", "elif", "else", "esac", "fi", "in", "then"];
+
+const COND_UNARY_OPS: &[&str] = &[
+    "-e", "-f", "-d", "-s", "-r", "-w", "-x", "-L", "-h", "-z", "-n",
+];
+
+const COND_BINARY_OPS: &[&str] = &["==", "!=", "=", "-eq", "-ne", "-lt", "-le", "-gt", "-ge"];
+
+/// Parses `script`. The positions of errors are byte offsets in `script`.
+pub fn parse(script: &str) -> std::result::Result<Ast, ParseError> {
+    let mut parser = Parser::new(Lexer::new(script));
+    let terms = parser.script().map_err(|diagnostic| {
+        // The parser only gets to the end of the script when it runs out of
+        // input, e.g. after a backslash or `|`.
+        if diagnostic.position == script.len() {
+            ParseError::Incomplete(diagnostic)
+        } else {
+            ParseError::Fatal(diagnostic)
+        }
+    })?;
+    if terms.is_empty() {
+        return Err(ParseError::Empty);
+    }
+    Ok(Ast { terms })
+}
+
+/// Makes the error for the token at `position` in `script`, which the parser
+/// doesn't expect.
+fn unexpected(script: &str, position: usize) -> Diagnostic {
+    let rest = &script[position..];
+    let len = match rest.chars().next() {
+        None => return Diagnostic::new("unexpected end of input", position),
+        Some('\n') => return Diagnostic::new("unexpected newline", position),
+        Some('(' | ')') => 1,
+        Some(ch) if "|&;<>".contains(ch) => rest
+            .find(|ch| !"|&;<>".contains(ch))
+            .unwrap_or(rest.len())
+            .min(3),
+        Some(_) => rest
+            .find(|ch: char| ch.is_whitespace() || "|&;<>()".contains(ch))
+            .unwrap_or(rest.len()),
+    };
+    Diagnostic::new(format!("unexpected `{}`", &rest[..len]), position)
+}
+
+#[derive(Clone)]
+struct Parser<'a> {
+    lexer: Lexer<'a>,
+    /// The tokens which have been looked at but not taken yet.
+    peeked: VecDeque<Token>,
+    /// Where the last token taken ends, which ends the code of a term.
+    last_end: usize,
+}
+
+impl<'a> Parser<'a> {
+    fn new(lexer: Lexer<'a>) -> Self {
+        let last_end = lexer.pos();
+        Self {
+            lexer,
+            peeked: VecDeque::new(),
+            last_end,
+        }
+    }
+
+    fn peek_nth(&mut self, n: usize) -> Result<&Token> {
+        while self.peeked.len() <= n {
+            let token = self.lexer.next_token()?;
+            self.peeked.push_back(token);
+        }
+        Ok(&self.peeked[n])
+    }
+
+    fn peek(&mut self) -> Result<&Token> {
+        self.peek_nth(0)
+    }
+
+    fn next(&mut self) -> Result<Token> {
+        self.peek()?;
+        let token = self.peeked.pop_front().unwrap();
+        if token.kind != TokenKind::Eof {
+            self.last_end = token.end;
+        }
+        Ok(token)
+    }
+
+    fn text(&self, token: &Token) -> &'a str {
+        &self.lexer.src()[token.start..token.end]
+    }
+
+    /// The text of the `n`th token from here if it is a word.
+    fn peek_word_nth(&mut self, n: usize) -> Result<Option<&'a str>> {
+        let src = self.lexer.src();
+        let token = self.peek_nth(n)?;
+        Ok(match token.kind {
+            TokenKind::Word(_) => Some(&src[token.start..token.end]),
+            _ => None,
+        })
+    }
+
+    fn peek_word(&mut self) -> Result<Option<&'a str>> {
+        self.peek_word_nth(0)
+    }
+
+    fn peek_operator_nth(&mut self, n: usize) -> Result<Option<&'static str>> {
+        Ok(match self.peek_nth(n)?.kind {
+            TokenKind::Operator(op) => Some(op),
+            _ => None,
+        })
+    }
+
+    fn peek_operator(&mut self) -> Result<Option<&'static str>> {
+        self.peek_operator_nth(0)
+    }
+
+    fn unexpected(&self, token: &Token) -> Diagnostic {
+        match token.kind {
+            TokenKind::Eof | TokenKind::Newline => unexpected(self.lexer.src(), token.start),
+            _ => Diagnostic::new(format!("unexpected `{}`", self.text(token)), token.start),
+        }
+    }
+
+    /// Takes the word or operator `expected`. If the script ends before it,
+    /// the error hints at `opener`, which `expected` closes, e.g. `if` for
+    /// `fi`.
+    fn expect(&mut self, expected: &str, opener: Option<&Token>) -> Result<Token> {
+        let token = self.next()?;
+        match token.kind {
+            TokenKind::Word(_) | TokenKind::Operator(_) if self.text(&token) == expected => {
+                Ok(token)
+            }
+            TokenKind::Eof => {
+                let diagnostic = self.unexpected(&token);
+                Err(match opener {
+                    Some(opener) => diagnostic.with_hint(
+                        opener.start,
+                        format!(
+                            "`{}` started here is not closed with `{}`",
+                            self.text(opener),
+                            expected
+                        ),
+                    ),
+                    None => diagnostic,
+                })
+            }
+            _ => Err(self.unexpected(&token)),
+        }
+    }
+
+    /// Takes a word, which can be a reserved word as well.
+    fn word(&mut self) -> Result<Word> {
+        let token = self.next()?;
+        match token.kind {
+            TokenKind::Word(word) => Ok(word),
+            _ => Err(self.unexpected(&token)),
+        }
+    }
+
+    fn skip_newlines(&mut self) -> Result<()> {
+        while self.peek()?.kind == TokenKind::Newline {
+            self.next()?;
+        }
+        Ok(())
+    }
+
+    /// script = compound_list EOF
+    fn script(&mut self) -> Result<Vec<Term>> {
+        let terms = self.compound_list()?;
+        let token = self.next()?;
+        if token.kind != TokenKind::Eof {
+            return Err(self.unexpected(&token));
+        }
+        Ok(terms)
+    }
+
+    /// compound_list = (and_or_list (`;` | `&` | newline))* and_or_list?
+    ///
+    /// The list ends where a command can't start, e.g. at `)` or `fi`, which
+    /// the caller takes. It can be empty, but a separator has to follow a
+    /// command: `; cmd` and `cmd; ; cmd` are errors as in other shells.
+    fn compound_list(&mut self) -> Result<Vec<Term>> {
+        let mut terms = Vec::new();
+        loop {
+            self.skip_newlines()?;
+            if !self.starts_command()? {
+                return Ok(terms);
+            }
+            let mut term = self.and_or_list()?;
+            let separated = match self.peek()?.kind {
+                TokenKind::Operator(";") | TokenKind::Newline => true,
+                TokenKind::Operator("&") => {
+                    term.background = true;
+                    true
+                }
+                _ => false,
+            };
+            terms.push(term);
+            if !separated {
+                return Ok(terms);
+            }
+            self.next()?;
+        }
+    }
+
+    fn starts_command(&mut self) -> Result<bool> {
+        if let Some(word) = self.peek_word()? {
+            return Ok(!CLOSING_WORDS.contains(&word));
+        }
+        Ok(matches!(self.peek_operator()?, Some("(" | "{")))
+    }
+
+    /// and_or_list = pipeline ((`&&` | `||`) newline* pipeline)*
+    fn and_or_list(&mut self) -> Result<Term> {
+        let start = self.peek()?.start;
+        let first = self.pipeline()?;
+        let mut rest = Vec::new();
+        loop {
+            let op = match self.peek_operator()? {
+                Some("&&") => AndOr::And,
+                Some("||") => AndOr::Or,
+                _ => break,
+            };
+            self.next()?;
+            self.skip_newlines()?;
+            rest.push((op, self.pipeline()?));
+        }
+        Ok(Term {
+            code: self.lexer.src()[start..self.last_end].trim().to_owned(),
+            first,
+            rest,
+            background: false,
+        })
+    }
+
+    /// pipeline = command (`|` newline* command)*
+    fn pipeline(&mut self) -> Result<Pipeline> {
+        let mut commands = vec![self.command()?];
+        while self.peek_operator()? == Some("|") {
+            self.next()?;
+            self.skip_newlines()?;
+            commands.push(self.command()?);
+        }
+        Ok(Pipeline { commands })
+    }
+
+    fn command(&mut self) -> Result<Command> {
+        match self.peek_operator()? {
+            Some("(") => return self.subshell(),
+            Some("{") => return self.group(),
+            _ => (),
+        }
+        match self.peek_word()? {
+            Some("if") => self.if_command(),
+            Some("case") => self.case_command(),
+            Some("while" | "until") => self.while_command(),
+            Some("for") => self.for_command(),
+            Some("break" | "continue") => self.break_command(),
+            Some("return") => self.return_command(),
+            Some("local") => Err(Diagnostic::new(
+                "local is not supported yet",
+                self.peek()?.start,
+            )),
+            Some("function") => self.function_definition(),
+            Some("[[") => self.cond_command(),
+            Some(name) if is_function_name(name) && self.peek_operator_nth(1)? == Some("(") => {
+                self.function_definition()
+            }
+            _ => self.simple_command(),
+        }
+    }
+
+    /// subshell = `(` compound_list `)` redirect_list
+    fn subshell(&mut self) -> Result<Command> {
+        let opener = self.next()?;
+        let terms = self.compound_list()?;
+        self.expect(")", Some(&opener))?;
+        let redirects = self.redirect_list()?;
+        Ok(Command::Subshell { terms, redirects })
+    }
+
+    /// group = `{` compound_list `}` redirect_list
+    fn group(&mut self) -> Result<Command> {
+        let opener = self.next()?;
+        let terms = self.compound_list()?;
+        self.expect("}", Some(&opener))?;
+        let redirects = self.redirect_list()?;
+        Ok(Command::Group { terms, redirects })
+    }
+
+    /// if_command = `if` compound_list `then` compound_list
+    ///     (`elif` compound_list `then` compound_list)*
+    ///     (`else` compound_list)? `fi` redirect_list
+    fn if_command(&mut self) -> Result<Command> {
+        let opener = self.next()?;
+        let mut branches = Vec::new();
+        let mut otherwise = None;
+        loop {
+            let condition = self.compound_list()?;
+            self.expect("then", None)?;
+            branches.push((condition, self.compound_list()?));
+            match self.peek_word()? {
+                Some("elif") => {
+                    self.next()?;
+                }
+                Some("else") => {
+                    self.next()?;
+                    otherwise = Some(self.compound_list()?);
+                    break;
+                }
+                _ => break,
+            }
+        }
+        self.expect("fi", Some(&opener))?;
+        let redirects = self.redirect_list()?;
+        Ok(Command::If {
+            branches,
+            otherwise,
+            redirects,
+        })
+    }
+
+    /// case_command = `case` word newline* `in` (newline | case_item)*
+    ///     `esac` redirect_list
+    /// case_item = `(`? word (`|` word)* `)` compound_list
+    ///     (`;;` | `;&` | `;;&` | &`esac`)
+    fn case_command(&mut self) -> Result<Command> {
+        let opener = self.next()?;
+        let word = self.word()?;
+        self.skip_newlines()?;
+        self.expect("in", None)?;
+        let mut items = Vec::new();
+        loop {
+            self.skip_newlines()?;
+            if self.peek_word()? == Some("esac") && !self.esac_is_pattern()? {
+                break;
+            }
+            if self.peek()?.kind == TokenKind::Eof {
+                self.expect("esac", Some(&opener))?;
+            }
+            let (item, terminated) = self.case_item()?;
+            items.push(item);
+            if !terminated {
+                break;
+            }
+        }
+        self.expect("esac", Some(&opener))?;
+        let redirects = self.redirect_list()?;
+        Ok(Command::Case {
+            word,
+            items,
+            redirects,
+        })
+    }
+
+    /// Parses a case item, and returns whether it has a terminator.
+    fn case_item(&mut self) -> Result<(CaseItem, bool)> {
+        if self.peek_operator()? == Some("(") {
+            self.next()?;
+        }
+        let mut patterns = vec![self.word()?];
+        while self.peek_operator()? == Some("|") {
+            self.next()?;
+            patterns.push(self.word()?);
+        }
+        self.expect(")", None)?;
+        let body = self.compound_list()?;
+        let terminator = match self.peek_operator()? {
+            Some(";;") => Some(CaseTerminator::Break),
+            Some(";&") => Some(CaseTerminator::FallThrough),
+            Some(";;&") => Some(CaseTerminator::Continue),
+            _ => None,
+        };
+        match terminator {
+            Some(_) => {
+                self.next()?;
+            }
+            // The last item doesn't need a terminator.
+            None if self.peek_word()? == Some("esac") => (),
+            None => {
+                let token = self.next()?;
+                return Err(self.unexpected(&token));
+            }
+        }
+        let item = CaseItem {
+            patterns,
+            body,
+            terminator: terminator.unwrap_or(CaseTerminator::Break),
+        };
+        Ok((item, terminator.is_some()))
+    }
+
+    /// Whether the `esac` ahead is a pattern, as in `esac)` or `esac|x)`,
+    /// rather than the end of e.g. `$(case x in *) ;; esac)`.
+    fn esac_is_pattern(&mut self) -> Result<bool> {
+        let end = self.peek()?.end;
+        let next = self.peek_nth(1)?;
+        if next.start != end || !matches!(next.kind, TokenKind::Operator(")" | "|")) {
+            return Ok(false);
+        }
+        let mut item = self.clone();
+        Ok(item.case_item().is_ok())
+    }
+
+    /// while_command = (`while` | `until`) compound_list `do` compound_list
+    ///     `done` redirect_list
+    fn while_command(&mut self) -> Result<Command> {
+        let opener = self.next()?;
+        let until = self.text(&opener) == "until";
+        let condition = self.compound_list()?;
+        let body = self.do_group(&opener)?;
+        let redirects = self.redirect_list()?;
+        Ok(Command::While {
+            condition,
+            body,
+            until,
+            redirects,
+        })
+    }
+
+    /// do_group = `do` compound_list `done`
+    fn do_group(&mut self, opener: &Token) -> Result<Vec<Term>> {
+        self.expect("do", None)?;
+        let body = self.compound_list()?;
+        self.expect("done", Some(opener))?;
+        Ok(body)
+    }
+
+    /// for_command = `for` name newline* (`in` word* separator+ | separator*)
+    ///     do_group redirect_list
+    /// arith_for_command = `for` `((` expr `;` expr `;` expr `))` separator*
+    ///     do_group redirect_list
+    ///
+    /// A separator is `;` or a newline.
+    fn for_command(&mut self) -> Result<Command> {
+        let opener = self.next()?;
+        if let Some([init, condition, step]) = self.lexer.arith_for()? {
+            self.last_end = self.lexer.pos();
+            self.skip_separators()?;
+            let body = self.do_group(&opener)?;
+            let redirects = self.redirect_list()?;
+            return Ok(Command::ArithFor {
+                init,
+                condition,
+                step,
+                body,
+                redirects,
+            });
+        }
+
+        let token = self.next()?;
+        let name = match token.kind {
+            TokenKind::Word(_) if is_var_name(self.text(&token)) => self.text(&token).to_owned(),
+            _ => return Err(self.unexpected(&token)),
+        };
+        self.skip_newlines()?;
+        let words = if self.peek_word()? == Some("in") {
+            self.next()?;
+            let mut words = Vec::new();
+            while let Some(word) = self.peek_word()? {
+                if RESERVED_WORDS.contains(&word) {
+                    break;
+                }
+                words.push(self.word()?);
+            }
+            if !self.skip_separators()? {
+                let token = self.next()?;
+                return Err(self.unexpected(&token));
+            }
+            Some(words)
+        } else {
+            self.skip_separators()?;
+            None
+        };
+        let body = self.do_group(&opener)?;
+        let redirects = self.redirect_list()?;
+        Ok(Command::For {
+            name,
+            words,
+            body,
+            redirects,
+        })
+    }
+
+    /// Skips `;`s and newlines, and returns whether there were any.
+    fn skip_separators(&mut self) -> Result<bool> {
+        let mut skipped = false;
+        while matches!(
+            self.peek()?.kind,
+            TokenKind::Operator(";") | TokenKind::Newline
+        ) {
+            self.next()?;
+            skipped = true;
+        }
+        Ok(skipped)
+    }
+
+    /// break_command = (`break` | `continue`) count?
+    fn break_command(&mut self) -> Result<Command> {
+        let keyword = self.next()?;
+        let count = self.count()?;
+        if self.text(&keyword) == "break" {
+            Ok(Command::Break(count))
+        } else {
+            Ok(Command::Continue(count))
+        }
+    }
+
+    /// return_command = `return` status?
+    fn return_command(&mut self) -> Result<Command> {
+        self.next()?;
+        let status = self.count()?;
+        Ok(Command::Return(status))
+    }
+
+    /// Takes the word after `break`, `continue` or `return` if there is one.
+    /// It is only a number once it is expanded, e.g. `$1`.
+    fn count(&mut self) -> Result<Option<Word>> {
+        match self.peek_word()? {
+            Some(word) if !RESERVED_WORDS.contains(&word) => Ok(Some(self.word()?)),
+            _ => Ok(None),
+        }
+    }
+
+    /// function_definition = (`function` name (`(` `)`)? | name `(` `)`)
+    ///     newline* command
+    fn function_definition(&mut self) -> Result<Command> {
+        let keyword = self.peek_word()? == Some("function");
+        if keyword {
+            self.next()?;
+        }
+        let token = self.next()?;
+        let name = match token.kind {
+            TokenKind::Word(_) if is_function_name(self.text(&token)) => {
+                self.text(&token).to_owned()
+            }
+            _ => return Err(self.unexpected(&token)),
+        };
+        if !keyword || self.peek_operator()? == Some("(") {
+            self.expect("(", None)?;
+            self.expect(")", None)?;
+        }
+        self.skip_newlines()?;
+        let body = self.command()?;
+        Ok(Command::Function {
+            name,
+            body: Rc::new(body),
+        })
+    }
+
+    /// cond_command = `[[` newline* cond_or newline* `]]` redirect_list
+    fn cond_command(&mut self) -> Result<Command> {
+        let opener = self.next()?;
+        self.skip_newlines()?;
+        let expr = self.cond_or()?;
+        self.skip_newlines()?;
+        self.expect("]]", Some(&opener))?;
+        let redirects = self.redirect_list()?;
+        Ok(Command::Cond { expr, redirects })
+    }
+
+    /// cond_or = cond_and (newline* `||` newline* cond_and)*
+    fn cond_or(&mut self) -> Result<CondExpr> {
+        let mut expr = self.cond_and()?;
+        loop {
+            self.skip_newlines()?;
+            if self.peek_operator()? != Some("||") {
+                return Ok(expr);
+            }
+            self.next()?;
+            self.skip_newlines()?;
+            expr = CondExpr::Or(Box::new(expr), Box::new(self.cond_and()?));
+        }
+    }
+
+    /// cond_and = cond_not (newline* `&&` newline* cond_not)*
+    fn cond_and(&mut self) -> Result<CondExpr> {
+        let mut expr = self.cond_not()?;
+        loop {
+            self.skip_newlines()?;
+            if self.peek_operator()? != Some("&&") {
+                return Ok(expr);
+            }
+            self.next()?;
+            self.skip_newlines()?;
+            expr = CondExpr::And(Box::new(expr), Box::new(self.cond_not()?));
+        }
+    }
+
+    /// cond_not = `!` cond_not | cond_primary
+    fn cond_not(&mut self) -> Result<CondExpr> {
+        if self.peek_word()? == Some("!") && self.peek_word_nth(1)? != Some("]]") {
+            self.next()?;
+            return Ok(CondExpr::Not(Box::new(self.cond_not()?)));
+        }
+        self.cond_primary()
+    }
+
+    /// cond_primary = `(` newline* cond_or newline* `)`
+    ///     | word `=~` regex
+    ///     | word binary_op word
+    ///     | unary_op word
+    ///     | word
+    fn cond_primary(&mut self) -> Result<CondExpr> {
+        if self.peek_operator()? == Some("(") {
+            let opener = self.next()?;
+            self.skip_newlines()?;
+            let expr = self.cond_or()?;
+            self.skip_newlines()?;
+            self.expect(")", Some(&opener))?;
+            return Ok(expr);
+        }
+
+        let first = self.cond_word()?;
+        let op = match self.peek_word()? {
+            Some(op) if op == "=~" || COND_BINARY_OPS.contains(&op) => Some(op),
+            Some(_) => None,
+            None => match &self.peek()?.kind {
+                TokenKind::Redirect { fd: None, op } if *op == "<" || *op == ">" => Some(*op),
+                _ => None,
+            },
+        };
+        if let Some(op) = op {
+            let op_token = self.next()?;
+            let right = if op == "=~" {
+                match self.lexer.regex()? {
+                    Some(token) => {
+                        self.last_end = token.end;
+                        token
+                    }
+                    None => {
+                        let token = self.next()?;
+                        return Err(self.unexpected(&token));
+                    }
+                }
+            } else {
+                self.cond_word_token()?
+            };
+            let TokenKind::Word(right) = right.kind else {
+                unreachable!()
+            };
+            return Ok(CondExpr::Binary {
+                op: self.text(&op_token).to_owned(),
+                left: first.1,
+                right,
+            });
+        }
+
+        let unary = COND_UNARY_OPS.contains(&first.0);
+        if unary && self.peek_word()?.is_some_and(|word| word != "]]") {
+            return Ok(CondExpr::Unary {
+                op: first.0.to_owned(),
+                operand: self.cond_word()?.1,
+            });
+        }
+        Ok(CondExpr::Word(first.1))
+    }
+
+    /// Takes a word in `[[ ]]` other than `]]`, with its text.
+    fn cond_word(&mut self) -> Result<(&'a str, Word)> {
+        let token = self.cond_word_token()?;
+        let text = self.text(&token);
+        let TokenKind::Word(word) = token.kind else {
+            unreachable!()
+        };
+        Ok((text, word))
+    }
+
+    fn cond_word_token(&mut self) -> Result<Token> {
+        let token = self.next()?;
+        match token.kind {
+            TokenKind::Word(_) if self.text(&token) != "]]" => Ok(token),
+            _ => Err(self.unexpected(&token)),
+        }
+    }
+
+    /// simple_command = assignment* (name (word | redirect)*)?
+    ///
+    /// It has to have an assignment or a name, which is not a reserved word.
+    fn simple_command(&mut self) -> Result<Command> {
+        let mut assignments = Vec::new();
+        while let Some(word) = self.peek_word()? {
+            if assignment_name(word).is_none() {
+                break;
+            }
+            assignments.push(self.assignment()?);
+        }
+
+        let mut argv = Vec::new();
+        let mut redirects = Vec::new();
+        match self.peek_word()? {
+            Some(name) if !RESERVED_WORDS.contains(&name) => argv.push(self.word()?),
+            _ if !assignments.is_empty() => {
+                return Ok(Command::Simple {
+                    assignments,
+                    argv,
+                    redirects,
+                })
+            }
+            _ => {
+                let token = self.next()?;
+                return Err(self.unexpected(&token));
+            }
+        }
+        loop {
+            match self.peek()?.kind {
+                TokenKind::Word(_) => argv.push(self.word()?),
+                TokenKind::Redirect { .. } | TokenKind::Heredoc { .. } => {
+                    self.redirect(&mut redirects)?
+                }
+                _ => break,
+            }
+        }
+        Ok(Command::Simple {
+            assignments,
+            argv,
+            redirects,
+        })
+    }
+
+    /// assignment = name `=` word
+    ///
+    /// Nothing can be between `=` and the value: `a= b` sets `a` to the empty
+    /// string.
+    fn assignment(&mut self) -> Result<Assignment> {
+        let token = self.next()?;
+        let text = self.text(&token);
+        let name = assignment_name(text).unwrap();
+        let TokenKind::Word(Word(mut spans)) = token.kind else {
+            unreachable!()
+        };
+        let array = text.len() == name.len() + 1
+            && self.peek_operator()? == Some("(")
+            && self.peek()?.start == token.end;
+        if text[name.len()..].starts_with('[') || array {
+            let text = match array {
+                true => {
+                    let rest = &self.lexer.src()[token.start..];
+                    rest.find(')').map_or(rest, |end| &rest[..=end])
+                }
+                false => text,
+            };
+            return Err(Diagnostic::new(
+                format!("{}: arrays are not supported yet", text),
+                token.start,
+            ));
+        }
+
+        // The name is in the first literal, since it is not quoted.
+        let Some(Span::Literal(first)) = spans.first_mut() else {
+            unreachable!()
+        };
+        first.drain(..name.len() + 1);
+        if first.is_empty() {
+            spans.remove(0);
+        }
+        Ok(Assignment {
+            name: name.to_owned(),
+            value: assignment_value(spans),
+        })
+    }
+
+    fn redirect_list(&mut self) -> Result<Vec<Redirect>> {
+        let mut redirects = Vec::new();
+        while matches!(
+            self.peek()?.kind,
+            TokenKind::Redirect { .. } | TokenKind::Heredoc { .. }
+        ) {
+            self.redirect(&mut redirects)?;
+        }
+        Ok(redirects)
+    }
+
+    /// redirect = fd? (`<` | `>` | `>>` | `&>` | `&>>`) word
+    ///     | fd? (`<&` | `>&`) fd
+    ///     | fd? `<<<` word
+    ///     | fd? (`<<` | `<<-`) word
+    ///
+    /// Pushes the redirections it stands for: `&> file` is the same as
+    /// `> file 2>&1`.
+    fn redirect(&mut self, redirects: &mut Vec<Redirect>) -> Result<()> {
+        let token = self.next()?;
+        let (fd, op) = match token.kind {
+            TokenKind::Redirect { fd, op } => (fd, op),
+            TokenKind::Heredoc { fd, body } => {
+                redirects.push(Redirect {
+                    fd: fd.unwrap_or(0),
+                    kind: RedirectKind::Read,
+                    target: RedirectTarget::Text(body),
+                });
+                return Ok(());
+            }
+            _ => return Err(self.unexpected(&token)),
+        };
+
+        let kind = match op {
+            "<" | "<&" | "<<<" => RedirectKind::Read,
+            ">>" | "&>>" => RedirectKind::Append,
+            _ => RedirectKind::Write,
+        };
+        let target_token = self.next()?;
+        let target_text = self.text(&target_token);
+        let TokenKind::Word(word) = target_token.kind else {
+            return Err(self.unexpected(&target_token));
+        };
+        let target = match op {
+            "<&" | ">&" => RedirectTarget::Fd(target_text.parse().map_err(|_| {
+                Diagnostic::new(
+                    format!("{}: bad file descriptor", target_text),
+                    target_token.start,
+                )
+            })?),
+            "<<<" => {
+                let Word(mut spans) = word;
+                spans.push(Span::Quoted("\n".to_owned()));
+                RedirectTarget::Text(Word(spans))
+            }
+            _ => RedirectTarget::File(word),
+        };
+        redirects.push(Redirect {
+            fd: fd.unwrap_or(if kind == RedirectKind::Read { 0 } else { 1 }),
+            kind,
+            target,
+        });
+        if op.starts_with('&') {
+            redirects.push(Redirect {
+                fd: 2,
+                kind: RedirectKind::Write,
+                target: RedirectTarget::Fd(1),
+            });
+        }
+        Ok(())
+    }
+}
+
+fn is_var_name(text: &str) -> bool {
+    !text.is_empty() && text.bytes().all(|b| b == b'_' || b.is_ascii_alphanumeric())
+}
+
+fn is_function_name(text: &str) -> bool {
+    !text.is_empty()
+        && text
+            .bytes()
+            .all(|b| b"_-.:".contains(&b) || b.is_ascii_alphanumeric())
+}
+
+/// Returns the name in `text` if the word is an assignment, i.e. starts with
+/// `name=` or `name[index]=`.
+fn assignment_name(text: &str) -> Option<&str> {
+    let len = text
+        .find(|c: char| c != '_' && !c.is_ascii_alphanumeric())
+        .unwrap_or(text.len());
+    let (name, rest) = text.split_at(len);
+    if name.is_empty() || name.starts_with(|c: char| c.is_ascii_digit()) {
+        return None;
+    }
+    let rest = match rest.strip_prefix('[') {
+        Some(index) => &index[index.find(']')? + 1..],
+        None => rest,
+    };
+    rest.starts_with('=').then_some(name)
+}
+
+/// Makes the value of an assignment, in which a tilde after a `:` is
+/// expanded as well, e.g. in `PATH=~/bin:~user/bin`.
+fn assignment_value(spans: Vec<Span>) -> Word {
+    let mut value = Vec::with_capacity(spans.len());
+    for span in spans {
+        let text = match span {
+            // `~` ends at `:`, e.g. in `PATH=~:/bin`.
+            Span::Tilde(user) if user.contains(':') => {
+                let (user, rest) = user.split_once(':').unwrap();
+                value.push(Span::Tilde(user.to_owned()));
+                format!(":{}", rest)
+            }
+            Span::Literal(text) => text,
+            span => {
+                value.push(span);
+                continue;
+            }
+        };
+        let mut parts = text.split(':');
+        value.push(Span::Literal(parts.next().unwrap().to_owned()));
+        for part in parts {
+            value.push(Span::Literal(":".to_owned()));
+            match part.strip_prefix('~') {
+                Some(tilde) => {
+                    let (user, rest) = tilde.split_at(tilde.find('/').unwrap_or(tilde.len()));
+                    value.push(Span::Tilde(user.to_owned()));
+                    value.push(Span::Literal(rest.to_owned()));
+                }
+                None => value.push(Span::Literal(part.to_owned())),
+            }
+        }
+    }
+    Word(value)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn terms(script: &str) -> Vec<Term> {
+        parse(script).unwrap().terms
+    }
+
+    /// Parses `script`, which has to be a single command.
+    fn command(script: &str) -> Command {
+        let mut terms = terms(script);
+        assert_eq!(terms.len(), 1);
+        let mut commands = terms.pop().unwrap().first.commands;
+        assert_eq!(commands.len(), 1);
+        commands.pop().unwrap()
+    }
+
+    fn error(script: &str) -> Diagnostic {
+        match parse(script) {
+            Err(ParseError::Fatal(diagnostic) | ParseError::Incomplete(diagnostic)) => diagnostic,
+            result => panic!("{:?} is not an error", result),
+        }
+    }
+
+    fn word(text: &str) -> Word {
+        Word(vec![Span::Literal(text.to_owned())])
+    }
+
+    fn simple(argv: &[&str]) -> Command {
+        Command::Simple {
+            assignments: Vec::new(),
+            argv: argv.iter().map(|text| word(text)).collect(),
+            redirects: Vec::new(),
+        }
+    }
+
+    fn term(code: &str, command: Command) -> Term {
+        Term {
+            code: code.to_owned(),
+            first: Pipeline {
+                commands: vec![command],
+            },
+            rest: Vec::new(),
+            background: false,
+        }
+    }
+
+    #[test]
+    fn empty_scripts() {
+        assert_eq!(parse(""), Err(ParseError::Empty));
+        assert_eq!(parse("\n  # comment\n"), Err(ParseError::Empty));
+    }
+
+    #[test]
+    fn compound_lists() {
+        let mut background = term("b", simple(&["b"]));
+        background.background = true;
+        assert_eq!(
+            terms("a;b &\n\nc"),
+            vec![
+                term("a", simple(&["a"])),
+                background,
+                term("c", simple(&["c"]))
+            ]
+        );
+        assert_eq!(error("; a"), Diagnostic::new("unexpected `;`", 0));
+        assert_eq!(error("a; ; b"), Diagnostic::new("unexpected `;`", 3));
+    }
+
+    #[test]
+    fn and_or_lists() {
+        let terms = terms("a && b ||\n c");
+        assert_eq!(terms[0].code, "a && b ||\n c");
+        assert_eq!(
+            terms[0].rest,
+            vec![
+                (
+                    AndOr::And,
+                    Pipeline {
+                        commands: vec![simple(&["b"])]
+                    }
+                ),
+                (
+                    AndOr::Or,
+                    Pipeline {
+                        commands: vec![simple(&["c"])]
+                    }
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn pipelines() {
+        assert_eq!(
+            terms("a | b |\n c")[0].first,
+            Pipeline {
+                commands: vec![simple(&["a"]), simple(&["b"]), simple(&["c"])]
+            }
+        );
+    }
+
+    #[test]
+    fn simple_commands() {
+        assert_eq!(
+            command("a=1 b= echo x >f 2>&1"),
+            Command::Simple {
+                assignments: vec![
+                    Assignment {
+                        name: "a".to_owned(),
+                        value: word("1"),
+                    },
+                    Assignment {
+                        name: "b".to_owned(),
+                        value: Word(Vec::new()),
+                    },
+                ],
+                argv: vec![word("echo"), word("x")],
+                redirects: vec![
+                    Redirect {
+                        fd: 1,
+                        kind: RedirectKind::Write,
+                        target: RedirectTarget::File(word("f")),
+                    },
+                    Redirect {
+                        fd: 2,
+                        kind: RedirectKind::Write,
+                        target: RedirectTarget::Fd(1),
+                    },
+                ],
+            }
+        );
+        // Reserved words are only special as the names of commands.
+        assert_eq!(command("echo if fi"), simple(&["echo", "if", "fi"]));
+        assert_eq!(error("fi"), Diagnostic::new("unexpected `fi`", 0));
+    }
+
+    #[test]
+    fn braces_in_parameter_words() {
+        let default = |name: &str, text: &str| {
+            Word(vec![Span::Parameter {
+                name: name.to_owned(),
+                index: None,
+                op: Some(ParameterOp::Default {
+                    word: word(text),
+                    colon: true,
+                }),
+                quoted: false,
+            }])
+        };
+        let Command::Simple { argv, .. } = command("echo ${data:-{}} ${y:-{a,b}}") else {
+            unreachable!()
+        };
+        assert_eq!(
+            argv,
+            [word("echo"), default("data", "{}"), default("y", "{a,b}")]
+        );
+        // The `}` after them still closes the group.
+        let Command::Group { terms, .. } = command("{ echo ${data:-{}}; }") else {
+            unreachable!()
+        };
+        assert_eq!(terms.len(), 1);
+    }
+
+    #[test]
+    fn assignments() {
+        assert_eq!(
+            command("PATH=~/bin:~u/bin"),
+            Command::Simple {
+                assignments: vec![Assignment {
+                    name: "PATH".to_owned(),
+                    value: Word(vec![
+                        Span::Tilde(String::new()),
+                        Span::Literal("/bin".to_owned()),
+                        Span::Literal(":".to_owned()),
+                        Span::Tilde("u".to_owned()),
+                        Span::Literal("/bin".to_owned()),
+                    ]),
+                }],
+                argv: Vec::new(),
+                redirects: Vec::new(),
+            }
+        );
+        assert_eq!(error("x a=(1 2)"), Diagnostic::new("unexpected `(`", 4));
+        assert_eq!(
+            error("a=(1 2)"),
+            Diagnostic::new("a=(1 2): arrays are not supported yet", 0)
+        );
+        assert_eq!(
+            error("a[0]=1"),
+            Diagnostic::new("a[0]=1: arrays are not supported yet", 0)
+        );
+    }
+
+    #[test]
+    fn redirections() {
+        let Command::Simple { redirects, .. } = command("a <x >>y &>z 3<<<w 4<&5") else {
+            unreachable!()
+        };
+        let redirect = |fd, kind, target| Redirect { fd, kind, target };
+        assert_eq!(
+            redirects,
+            vec![
+                redirect(0, RedirectKind::Read, RedirectTarget::File(word("x"))),
+                redirect(1, RedirectKind::Append, RedirectTarget::File(word("y"))),
+                redirect(1, RedirectKind::Write, RedirectTarget::File(word("z"))),
+                redirect(2, RedirectKind::Write, RedirectTarget::Fd(1)),
+                redirect(
+                    3,
+                    RedirectKind::Read,
+                    RedirectTarget::Text(Word(vec![
+                        Span::Literal("w".to_owned()),
+                        Span::Quoted("\n".to_owned()),
+                    ]))
+                ),
+                redirect(4, RedirectKind::Read, RedirectTarget::Fd(5)),
+            ]
+        );
+        assert_eq!(error("a >&b"), Diagnostic::new("b: bad file descriptor", 4));
+    }
+
+    #[test]
+    fn heredocs() {
+        let Command::Simple { redirects, .. } = command("cat <<EOF\nbody\nEOF") else {
+            unreachable!()
+        };
+        assert_eq!(
+            redirects,
+            vec![Redirect {
+                fd: 0,
+                kind: RedirectKind::Read,
+                target: RedirectTarget::Text(Word(vec![Span::Quoted("body\n".to_owned())])),
+            }]
+        );
+        assert_eq!(terms("cat <<EOF; b\nx\nEOF\nc").len(), 3);
+    }
+
+    #[test]
+    fn subshells_and_groups() {
+        assert_eq!(
+            command("( a ) >f"),
+            Command::Subshell {
+                terms: vec![term("a", simple(&["a"]))],
+                redirects: vec![Redirect {
+                    fd: 1,
+                    kind: RedirectKind::Write,
+                    target: RedirectTarget::File(word("f")),
+                }],
+            }
+        );
+        assert_eq!(
+            command("{ a; }"),
+            Command::Group {
+                terms: vec![term("a", simple(&["a"]))],
+                redirects: Vec::new(),
+            }
+        );
+    }
+
+    #[test]
+    fn if_commands() {
+        assert_eq!(
+            command("if a; then b; elif c\nthen d; else e; fi"),
+            Command::If {
+                branches: vec![
+                    (
+                        vec![term("a", simple(&["a"]))],
+                        vec![term("b", simple(&["b"]))]
+                    ),
+                    (
+                        vec![term("c", simple(&["c"]))],
+                        vec![term("d", simple(&["d"]))]
+                    ),
+                ],
+                otherwise: Some(vec![term("e", simple(&["e"]))]),
+                redirects: Vec::new(),
+            }
+        );
+    }
+
+    #[test]
+    fn while_commands() {
+        assert_eq!(
+            command("until a; do b; done"),
+            Command::While {
+                condition: vec![term("a", simple(&["a"]))],
+                body: vec![term("b", simple(&["b"]))],
+                until: true,
+                redirects: Vec::new(),
+            }
+        );
+    }
+
+    #[test]
+    fn for_commands() {
+        let for_command = |words| Command::For {
+            name: "x".to_owned(),
+            words,
+            body: vec![term("a", simple(&["a"]))],
+            redirects: Vec::new(),
+        };
+        assert_eq!(
+            command("for x in 1 2; do a; done"),
+            for_command(Some(vec![word("1"), word("2")]))
+        );
+        assert_eq!(
+            command("for x in\ndo a; done"),
+            for_command(Some(Vec::new()))
+        );
+        assert_eq!(command("for x\ndo a; done"), for_command(None));
+        assert_eq!(
+            error("for x in a do b; done"),
+            Diagnostic::new("unexpected `do`", 11)
+        );
+    }
+
+    #[test]
+    fn arith_for_commands() {
+        assert_eq!(
+            command("for ((i = 0; i < 3; i++))\ndo a; done"),
+            Command::ArithFor {
+                init: "i = 0".to_owned(),
+                condition: " i < 3".to_owned(),
+                step: " i++".to_owned(),
+                body: vec![term("a", simple(&["a"]))],
+                redirects: Vec::new(),
+            }
+        );
+    }
+
+    #[test]
+    fn case_commands() {
+        let item = |patterns: &[&str], body: Vec<Term>, terminator| CaseItem {
+            patterns: patterns.iter().map(|text| word(text)).collect(),
+            body,
+            terminator,
+        };
+        assert_eq!(
+            command("case x in\n(a|b) c;;\nd) ;&\nesac) ;;&\n*) e\nesac"),
+            Command::Case {
+                word: word("x"),
+                items: vec![
+                    item(
+                        &["a", "b"],
+                        vec![term("c", simple(&["c"]))],
+                        CaseTerminator::Break
+                    ),
+                    item(&["d"], Vec::new(), CaseTerminator::FallThrough),
+                    item(&["esac"], Vec::new(), CaseTerminator::Continue),
+                    item(
+                        &["*"],
+                        vec![term("e", simple(&["e"]))],
+                        CaseTerminator::Break
+                    ),
+                ],
+                redirects: Vec::new(),
+            }
+        );
+        assert_eq!(
+            command("echo $(case x in x) ;; esac)"),
+            Command::Simple {
+                assignments: Vec::new(),
+                argv: vec![
+                    word("echo"),
+                    Word(vec![Span::Command {
+                        script: "case x in x) ;; esac".to_owned(),
+                        quoted: false,
+                    }]),
+                ],
+                redirects: Vec::new(),
+            }
+        );
+    }
+
+    #[test]
+    fn function_definitions() {
+        let function = |name: &str, body| Command::Function {
+            name: name.to_owned(),
+            body: Rc::new(body),
+        };
+        let group = Command::Group {
+            terms: vec![term("a", simple(&["a"]))],
+            redirects: Vec::new(),
+        };
+        assert_eq!(command("f() { a; }"), function("f", group.clone()));
+        assert_eq!(command("function g\n{ a; }"), function("g", group.clone()));
+        assert_eq!(command("function h() { a; }"), function("h", group));
+        assert_eq!(
+            command("a.b-c() ( a )"),
+            function(
+                "a.b-c",
+                Command::Subshell {
+                    terms: vec![term("a", simple(&["a"]))],
+                    redirects: Vec::new(),
+                }
+            )
+        );
+    }
+
+    #[test]
+    fn loop_controls_and_returns() {
+        assert_eq!(command("break"), Command::Break(None));
+        assert_eq!(command("continue 2"), Command::Continue(Some(word("2"))));
+        assert_eq!(command("return"), Command::Return(None));
+        // The status is only taken modulo 256 when it runs.
+        assert_eq!(command("return 257"), Command::Return(Some(word("257"))));
+        assert_eq!(
+            command("return $1"),
+            Command::Return(Some(Word(vec![Span::Parameter {
+                name: "1".to_owned(),
+                index: None,
+                op: None,
+                quoted: false,
+            }])))
+        );
+        assert_eq!(command("break 0"), Command::Break(Some(word("0"))));
+        assert_eq!(error("return 1 2"), Diagnostic::new("unexpected `2`", 9));
+    }
+
+    #[test]
+    fn conditional_expressions() {
+        let Command::Cond { expr, .. } = command("[[ ! -f a && b == c* || ( d < e ) ]]") else {
+            unreachable!()
+        };
+        assert_eq!(
+            expr,
+            CondExpr::Or(
+                Box::new(CondExpr::And(
+                    Box::new(CondExpr::Not(Box::new(CondExpr::Unary {
+                        op: "-f".to_owned(),
+                        operand: word("a"),
+                    }))),
+                    Box::new(CondExpr::Binary {
+                        op: "==".to_owned(),
+                        left: word("b"),
+                        right: Word(vec![Span::Literal("c*".to_owned())]),
+                    }),
+                )),
+                Box::new(CondExpr::Binary {
+                    op: "<".to_owned(),
+                    left: word("d"),
+                    right: word("e"),
+                }),
+            )
+        );
+        // Operators without operands are words.
+        assert_eq!(
+            command("[[ -n ]]"),
+            Command::Cond {
+                expr: CondExpr::Word(word("-n")),
+                redirects: Vec::new(),
+            }
+        );
+    }
+
+    #[test]
+    fn regexes_in_conditional_expressions() {
+        assert_eq!(
+            command("[[ a =~ ^(b|c)$ ]]"),
+            Command::Cond {
+                expr: CondExpr::Binary {
+                    op: "=~".to_owned(),
+                    left: word("a"),
+                    right: word("^(b|c)$"),
+                },
+                redirects: Vec::new(),
+            }
+        );
+    }
+
+    #[test]
+    fn unclosed_commands_hint_at_where_they_start() {
+        let unclosed = |position, hint: &str| {
+            Diagnostic::new("unexpected end of input", position).with_hint(0, hint)
+        };
+        assert_eq!(
+            error("if a; then b"),
+            unclosed(12, "`if` started here is not closed with `fi`")
+        );
+        assert_eq!(
+            error("while a; do\n"),
+            unclosed(12, "`while` started here is not closed with `done`")
+        );
+        assert_eq!(
+            error("( a"),
+            unclosed(3, "`(` started here is not closed with `)`")
+        );
+        assert_eq!(
+            error("$(a"),
+            unclosed(3, "`$(` started here is not closed with `)`")
+        );
+    }
+
+    #[test]
+    fn incomplete_scripts() {
+        for script in [
+            "echo 'a",
+            "echo a |",
+            "if a; then",
+            "cat <<EOF\nbody",
+            "echo a\\",
+            "f() {",
+        ] {
+            assert!(
+                matches!(parse(script), Err(ParseError::Incomplete(_))),
+                "{:?}",
+                script
+            );
+        }
+        for script in ["echo a", "fi", "echo )", ""] {
+            assert!(
+                !matches!(parse(script), Err(ParseError::Incomplete(_))),
+                "{:?}",
+                script
+            );
+        }
+    }
+}
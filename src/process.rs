@@ -1,4 +1,686 @@
+use std::{
+    ffi::CString,
+    io::{Read, Seek, SeekFrom, Write},
+    os::unix::{
+        ffi::OsStrExt,
+        fs::PermissionsExt,
+        io::{FromRawFd, IntoRawFd, RawFd},
+    },
+    path::{Path, PathBuf},
+    sync::atomic::{AtomicBool, AtomicUsize, Ordering},
+};
+
+use crate::{job::JobState, signal};
+
+/// The editor used when none of the variables naming one is set.
+const DEFAULT_EDITOR: &str = "vi";
+
+/// Whether the shell runs jobs in process groups of their own, which it
+/// gives the terminal to while they run in the foreground. Only the shell
+/// itself does, not the child processes it forks.
+static JOB_CONTROL: AtomicBool = AtomicBool::new(false);
+
 #[derive(Debug, Copy, Clone, Eq, PartialEq)]
 pub enum ExitStatus {
     ExitedWith(i32),
-}
\ No newline at end of file
+}
+
+/// Returns the editor named by the first of `vars` which is set.
+pub fn editor_from_env(vars: &[&str]) -> String {
+    vars.iter()
+        .find_map(|var| std::env::var(var).ok())
+        .unwrap_or_else(|| DEFAULT_EDITOR.to_owned())
+}
+
+/// Lets the user edit `text` with `editor` and returns the result. `editor`
+/// may contain arguments, e.g. `code --wait`.
+pub fn edit_text(editor: &str, text: &str) -> Result<String, String> {
+    let path = std::env::temp_dir().join(format!("toysh-edit-{}.sh", std::process::id()));
+    std::fs::write(&path, text).map_err(|err| format!("{}: {}", path.display(), err))?;
+    let result = run_editor(editor, &path);
+    std::fs::remove_file(&path).ok();
+    result
+}
+
+fn run_editor(editor: &str, path: &Path) -> Result<String, String> {
+    let mut editor_argv = editor.split_whitespace();
+    let program = editor_argv.next().ok_or("no editor is set")?;
+    let status = std::process::Command::new(program)
+        .args(editor_argv)
+        .arg(path)
+        .status()
+        .map_err(|err| format!("{}: {}", program, err))?;
+    if !status.success() {
+        return Err(format!("{}: exited with {}", program, status));
+    }
+    std::fs::read_to_string(path).map_err(|err| format!("{}: {}", path.display(), err))
+}
+
+/// Starts the `len` stages of a pipeline, each in a child process with its
+/// standard output connected to the standard input of the next one, and
+/// returns their pids. `run_stage` is called in the child to run the stage
+/// with the given index, and the child exits with the status it returns. If
+/// not all of them can be started, waits for the ones which have been. The
+/// children are a job in the foreground.
+pub fn start_pipeline(
+    len: usize,
+    mut run_stage: impl FnMut(usize) -> ExitStatus,
+) -> Result<Vec<libc::pid_t>, String> {
+    // Don't let the children inherit output which hasn't been written yet.
+    std::io::stdout().flush().ok();
+    std::io::stderr().flush().ok();
+
+    let mut pids = Vec::with_capacity(len);
+    let mut stdin: Option<RawFd> = None;
+    let mut error = None;
+    for i in 0..len {
+        let next = if i + 1 < len {
+            match pipe() {
+                Ok(fds) => Some(fds),
+                Err(err) => {
+                    error = Some(format!("pipe: {}", err));
+                    break;
+                }
+            }
+        } else {
+            None
+        };
+
+        let child = spawn_job(pids.first().copied(), true, || {
+            // SAFETY: the descriptors are the ones created above and owned
+            // by this process.
+            unsafe {
+                if let Some(read) = stdin {
+                    libc::dup2(read, 0);
+                    libc::close(read);
+                }
+                if let Some((read, write)) = next {
+                    libc::close(read);
+                    libc::dup2(write, 1);
+                    libc::close(write);
+                }
+            }
+            run_stage(i)
+        });
+
+        // SAFETY: closes the descriptors the child has taken over.
+        unsafe {
+            if let Some(read) = stdin.take() {
+                libc::close(read);
+            }
+            if let Some((_, write)) = next {
+                libc::close(write);
+            }
+        }
+        stdin = next.map(|(read, _)| read);
+        match child {
+            Ok(pid) => pids.push(pid),
+            Err(err) => {
+                error = Some(format!("fork: {}", err));
+                break;
+            }
+        }
+    }
+    if let Some(read) = stdin {
+        // SAFETY: the read end of a pipe no child was started for.
+        unsafe { libc::close(read) };
+    }
+
+    match error {
+        Some(err) => {
+            for pid in pids {
+                wait(pid);
+            }
+            Err(err)
+        }
+        None => Ok(pids),
+    }
+}
+
+/// Runs `run` in a child process, which exits with the status it returns,
+/// and returns the pid of the child without waiting for it.
+pub fn spawn(run: impl FnOnce() -> ExitStatus) -> std::io::Result<libc::pid_t> {
+    fork(|| (), run)
+}
+
+/// Runs `run` in a child process like [`spawn`] as (a part of) a job. With
+/// job control, the child is put in the process group `pgid`, or in a new
+/// one which it leads if it is `None`, and the group is given the terminal
+/// if `foreground` is set.
+pub fn spawn_job(
+    pgid: Option<libc::pid_t>,
+    foreground: bool,
+    run: impl FnOnce() -> ExitStatus,
+) -> std::io::Result<libc::pid_t> {
+    if !JOB_CONTROL.load(Ordering::Relaxed) {
+        return spawn(run);
+    }
+    let pid = fork(
+        || {
+            // SAFETY: `getpid` has no preconditions.
+            join_group(
+                0,
+                pgid.unwrap_or_else(|| unsafe { libc::getpid() }),
+                foreground,
+            );
+        },
+        run,
+    )?;
+    // Both the parent and the child do this so that the group is in place
+    // whichever runs first.
+    join_group(pid, pgid.unwrap_or(pid), foreground);
+    Ok(pid)
+}
+
+/// Puts the process `pid`, or the calling one if it is 0, in the process
+/// group `pgid`, and gives the group the terminal if `foreground` is set.
+fn join_group(pid: libc::pid_t, pgid: libc::pid_t, foreground: bool) {
+    // SAFETY: these have no preconditions and only fail if the process
+    // has already joined the group or exited.
+    unsafe {
+        libc::setpgid(pid, pgid);
+        if foreground {
+            libc::tcsetpgrp(0, pgid);
+        }
+    }
+}
+
+/// Starts job control if the standard input is a terminal: the shell leads
+/// a process group of its own which owns the terminal, and runs each job in
+/// another one.
+pub fn enable_job_control() {
+    // SAFETY: these have no preconditions.
+    unsafe {
+        if libc::isatty(0) == 0 {
+            return;
+        }
+        // This fails if the shell already leads a session, and so a group.
+        libc::setpgid(0, 0);
+        libc::tcsetpgrp(0, libc::getpgrp());
+    }
+    JOB_CONTROL.store(true, Ordering::Relaxed);
+}
+
+/// Gives the terminal to the process group `pgid` of a job which continues
+/// in the foreground.
+pub fn give_terminal(pgid: libc::pid_t) {
+    if JOB_CONTROL.load(Ordering::Relaxed) {
+        // SAFETY: `tcsetpgrp` has no preconditions.
+        unsafe { libc::tcsetpgrp(0, pgid) };
+    }
+}
+
+/// Takes the terminal back from the job in the foreground once it is done
+/// or stopped.
+pub fn reclaim_terminal() {
+    if JOB_CONTROL.load(Ordering::Relaxed) {
+        // SAFETY: these have no preconditions.
+        unsafe { libc::tcsetpgrp(0, libc::getpgrp()) };
+    }
+}
+
+/// Forks a child process which runs `setup` and then `run`, and exits with
+/// the status `run` returns.
+fn fork(setup: impl FnOnce(), run: impl FnOnce() -> ExitStatus) -> std::io::Result<libc::pid_t> {
+    // Don't let the child inherit output which hasn't been written yet.
+    std::io::stdout().flush().ok();
+    std::io::stderr().flush().ok();
+
+    // SAFETY: the child only runs `run` before exiting.
+    let pid = unsafe { libc::fork() };
+    if pid < 0 {
+        return Err(std::io::Error::last_os_error());
+    }
+    if pid == 0 {
+        // Only the shell itself controls jobs, and the commands a child runs
+        // stay in its group.
+        JOB_CONTROL.store(false, Ordering::Relaxed);
+        // SIGTTOU is still ignored here, so that `setup` can take the
+        // terminal.
+        setup();
+        signal::restore_defaults();
+        let ExitStatus::ExitedWith(code) = run();
+        std::io::stdout().flush().ok();
+        std::io::stderr().flush().ok();
+        // SAFETY: exits without running the parent's destructors and
+        // `atexit` handlers a second time.
+        unsafe { libc::_exit(code) };
+    }
+    Ok(pid)
+}
+
+/// Runs `run` in a child process with its standard output connected to a
+/// pipe, and returns what it writes there once it exits.
+pub fn capture(run: impl FnOnce() -> ExitStatus) -> Result<Vec<u8>, String> {
+    let (read, write) = pipe().map_err(|err| format!("pipe: {}", err))?;
+    let child = spawn(|| {
+        // SAFETY: the descriptors are the ones created above and owned by
+        // this process.
+        unsafe {
+            libc::close(read);
+            libc::dup2(write, 1);
+            libc::close(write);
+        }
+        run()
+    });
+    // SAFETY: the child has taken over the write end, and the read end is
+    // owned by the file from here on.
+    let mut output = unsafe {
+        libc::close(write);
+        std::fs::File::from_raw_fd(read)
+    };
+    let pid = child.map_err(|err| format!("fork: {}", err))?;
+
+    let mut captured = Vec::new();
+    let result = output.read_to_end(&mut captured);
+    drop(output);
+    wait(pid);
+    result.map_err(|err| format!("read: {}", err))?;
+    Ok(captured)
+}
+
+/// Runs `run` in a child process connected to a pipe for `<(...)`, or for
+/// `>(...)` if `output` is set. Returns the descriptor of the other end,
+/// which is left open in the shell for commands to use as `/dev/fd/N`, and
+/// the pid of the child.
+pub fn substitute(
+    run: impl FnOnce() -> ExitStatus,
+    output: bool,
+) -> Result<(RawFd, libc::pid_t), String> {
+    let (read, write) = pipe().map_err(|err| format!("pipe: {}", err))?;
+    // The child writes what commands read from `<(...)`, and reads what they
+    // write to `>(...)`.
+    let (end, child_end, child_fd) = if output {
+        (write, read, 0)
+    } else {
+        (read, write, 1)
+    };
+    let child = spawn(|| {
+        // SAFETY: the descriptors are the ones created above and owned by
+        // this process.
+        unsafe {
+            libc::close(end);
+            libc::dup2(child_end, child_fd);
+            libc::close(child_end);
+        }
+        run()
+    });
+    // SAFETY: the child has taken over its end.
+    unsafe { libc::close(child_end) };
+    match child {
+        Ok(pid) => Ok((end, pid)),
+        Err(err) => {
+            // SAFETY: nobody is going to use the other end.
+            unsafe { libc::close(end) };
+            Err(format!("fork: {}", err))
+        }
+    }
+}
+
+/// Closes the descriptor of a process substitution once the command using
+/// it has finished, and waits for the child.
+pub fn finish_substitution(fd: RawFd, pid: libc::pid_t) {
+    // SAFETY: the descriptor is owned by the shell and not used afterwards.
+    unsafe { libc::close(fd) };
+    wait(pid);
+}
+
+/// Returns the path of the command `name`: `name` itself if it contains a
+/// slash, or otherwise the first executable named so in the directories on
+/// `$PATH`, where an empty one is the current directory.
+pub fn find_command(name: &str) -> Option<PathBuf> {
+    if name.contains('/') {
+        return Some(PathBuf::from(name));
+    }
+    search_path(name, is_executable)
+}
+
+/// Returns whether there is a file named `name` on `$PATH` which can't be
+/// run because it isn't executable.
+pub fn is_on_path_but_not_executable(name: &str) -> bool {
+    search_path(name, |path| path.is_file()).is_some()
+}
+
+fn search_path(name: &str, accept: impl Fn(&Path) -> bool) -> Option<PathBuf> {
+    let path = std::env::var_os("PATH")?;
+    std::env::split_paths(&path)
+        .map(|dir| {
+            if dir.as_os_str().is_empty() {
+                PathBuf::from(".").join(name)
+            } else {
+                dir.join(name)
+            }
+        })
+        .find(|candidate| accept(candidate))
+}
+
+/// Describes `err` as `strerror` does, e.g. `No such process`, without the
+/// `(os error 3)` which Rust adds.
+pub fn error_message(err: &std::io::Error) -> String {
+    match err.raw_os_error() {
+        // SAFETY: `strerror` returns a string which stays valid until it is
+        // called again, and it is copied right away.
+        Some(code) => unsafe { std::ffi::CStr::from_ptr(libc::strerror(code)) }
+            .to_string_lossy()
+            .into_owned(),
+        None => err.to_string(),
+    }
+}
+
+pub fn is_executable(path: &Path) -> bool {
+    std::fs::metadata(path)
+        .is_ok_and(|metadata| metadata.is_file() && metadata.permissions().mode() & 0o111 != 0)
+}
+
+/// Replaces the process with the command `argv` at `path`. Only returns if
+/// it can't be run, with the status to exit with.
+pub fn exec(path: &Path, argv: &[String]) -> ExitStatus {
+    let path = CString::new(path.as_os_str().as_bytes());
+    let args: Result<Vec<CString>, _> = argv.iter().map(|arg| CString::new(arg.as_str())).collect();
+    let (path, args) = match (path, args) {
+        (Ok(path), Ok(args)) => (path, args),
+        _ => {
+            eprintln!("toysh: {}: argument contains a null byte", argv[0]);
+            return ExitStatus::ExitedWith(126);
+        }
+    };
+    let mut arg_ptrs: Vec<*const libc::c_char> = args.iter().map(|arg| arg.as_ptr()).collect();
+    arg_ptrs.push(std::ptr::null());
+
+    // SAFETY: `path` is a string and `arg_ptrs` is a null-terminated array
+    // of pointers to strings which are alive during the call.
+    unsafe { libc::execv(path.as_ptr(), arg_ptrs.as_ptr()) };
+    let err = std::io::Error::last_os_error();
+    match err.raw_os_error() {
+        Some(libc::ENOENT) => {
+            eprintln!("toysh: {}: No such file or directory", argv[0]);
+            ExitStatus::ExitedWith(127)
+        }
+        Some(libc::EACCES) => {
+            eprintln!("toysh: {}: Permission denied", argv[0]);
+            ExitStatus::ExitedWith(126)
+        }
+        _ => {
+            eprintln!("toysh: {}: {}", argv[0], err);
+            ExitStatus::ExitedWith(126)
+        }
+    }
+}
+
+/// A redirection of the descriptor `fd`, with its target expanded.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Redirection {
+    /// `fd< path`
+    Read { fd: RawFd, path: String },
+    /// `fd> path`, or `fd>> path` if `append` is set.
+    Write {
+        fd: RawFd,
+        path: String,
+        append: bool,
+    },
+    /// `fd>&from` or `fd<&from`, which make `fd` a copy of `from`.
+    Duplicate { fd: RawFd, from: RawFd },
+    /// A here document or a here string, which lets `fd` read `text`.
+    Text { fd: RawFd, text: String },
+}
+
+/// The descriptors replaced by redirections, which [`SavedFds::restore`]
+/// puts back.
+#[derive(Debug)]
+pub struct SavedFds(Vec<(RawFd, Option<RawFd>)>);
+
+impl SavedFds {
+    /// Keeps the redirected descriptors instead of putting back the ones they
+    /// have replaced, e.g. for `exec 3< file`.
+    pub fn keep(self) {
+        for (_, saved) in self.0 {
+            if let Some(saved) = saved {
+                // SAFETY: `saved` is owned by this struct and not used
+                // afterwards.
+                unsafe { libc::close(saved) };
+            }
+        }
+    }
+
+    pub fn restore(self) {
+        std::io::stdout().flush().ok();
+        std::io::stderr().flush().ok();
+        // Put back the first redirected descriptors last in case one was
+        // redirected more than once.
+        for (fd, saved) in self.0.into_iter().rev() {
+            // SAFETY: `saved` is a copy of `fd` made by `redirect` and owned
+            // by this struct.
+            unsafe {
+                match saved {
+                    Some(saved) => {
+                        libc::dup2(saved, fd);
+                        libc::close(saved);
+                    }
+                    None => {
+                        libc::close(fd);
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// Applies `redirections` from left to right to the descriptors of this
+/// process. The ones they replace are kept so that the shell can restore
+/// them after running a builtin, and are closed on exec. If one of them
+/// fails, the ones before it are restored.
+pub fn redirect(redirections: &[Redirection]) -> Result<SavedFds, String> {
+    std::io::stdout().flush().ok();
+    std::io::stderr().flush().ok();
+
+    let mut saved = SavedFds(Vec::new());
+    for redirection in redirections {
+        // The descriptor to copy to `fd`, and whether it was opened here and
+        // has to be closed afterwards.
+        let (fd, source) = match redirection {
+            Redirection::Read { fd, path } => {
+                let file = std::fs::File::open(path).map_err(|err| format!("{}: {}", path, err));
+                (*fd, file.map(|file| (file.into_raw_fd(), true)))
+            }
+            Redirection::Write { fd, path, append } => {
+                let file = std::fs::OpenOptions::new()
+                    .write(true)
+                    .create(true)
+                    .append(*append)
+                    .truncate(!*append)
+                    .open(path)
+                    .map_err(|err| format!("{}: {}", path, err));
+                (*fd, file.map(|file| (file.into_raw_fd(), true)))
+            }
+            Redirection::Text { fd, text } => (*fd, text_file(text).map(|fd| (fd, true))),
+            Redirection::Duplicate { fd, from } => {
+                // SAFETY: only checks whether `from` is open.
+                let source = if unsafe { libc::fcntl(*from, libc::F_GETFD) } < 0 {
+                    Err(format!("{}: {}", from, std::io::Error::last_os_error()))
+                } else {
+                    Ok((*from, false))
+                };
+                (*fd, source)
+            }
+        };
+        let (source, owned) = match source {
+            Ok(source) => source,
+            Err(err) => {
+                saved.restore();
+                return Err(err);
+            }
+        };
+
+        // SAFETY: `fd` is only duplicated and replaced, and `source` is
+        // closed only if it is owned here. A file may have been opened as
+        // `fd` itself if it was closed.
+        unsafe {
+            let copy = libc::fcntl(fd, libc::F_DUPFD_CLOEXEC, 10);
+            saved.0.push((fd, (copy >= 0).then_some(copy)));
+            if source != fd {
+                let result = libc::dup2(source, fd);
+                let err = std::io::Error::last_os_error();
+                if owned {
+                    libc::close(source);
+                }
+                if result < 0 {
+                    saved.restore();
+                    return Err(format!("{}: {}", fd, err));
+                }
+            }
+        }
+    }
+    Ok(saved)
+}
+
+/// Returns a descriptor to read `text` from. It is written to a file which
+/// is removed right away so that nothing is left behind. Unlike with a pipe,
+/// the text can be larger than the pipe's buffer without another process
+/// writing it.
+fn text_file(text: &str) -> Result<RawFd, String> {
+    static COUNT: AtomicUsize = AtomicUsize::new(0);
+    let path = std::env::temp_dir().join(format!(
+        "toysh-text-{}-{}",
+        std::process::id(),
+        COUNT.fetch_add(1, Ordering::Relaxed)
+    ));
+    let write = || -> std::io::Result<std::fs::File> {
+        let mut file = std::fs::OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create_new(true)
+            .open(&path)?;
+        std::fs::remove_file(&path)?;
+        file.write_all(text.as_bytes())?;
+        file.seek(SeekFrom::Start(0))?;
+        Ok(file)
+    };
+    write()
+        .map(IntoRawFd::into_raw_fd)
+        .map_err(|err| format!("{}: {}", path.display(), err))
+}
+
+fn pipe() -> std::io::Result<(RawFd, RawFd)> {
+    let mut fds = [0; 2];
+    // SAFETY: `fds` has room for the two descriptors.
+    if unsafe { libc::pipe(fds.as_mut_ptr()) } < 0 {
+        return Err(std::io::Error::last_os_error());
+    }
+    Ok((fds[0], fds[1]))
+}
+
+/// Waits for the child `pid` to exit. A child killed by a signal is
+/// reported as exiting with 128 plus the signal number, as other shells do.
+fn wait(pid: libc::pid_t) -> ExitStatus {
+    match waitpid(pid, 0, false) {
+        Some(state) => state.status().unwrap_or(ExitStatus::ExitedWith(1)),
+        None => ExitStatus::ExitedWith(1),
+    }
+}
+
+/// Waits for the child `pid` to exit or to be stopped. Returns `None` if
+/// Ctrl-Z is pressed meanwhile, as it is up to the shell to stop the
+/// processes run in the foreground.
+pub fn wait_for_change(pid: libc::pid_t) -> Option<JobState> {
+    match waitpid(pid, libc::WUNTRACED, true) {
+        Some(state) => Some(state),
+        None if signal::take_stop_request() => None,
+        None => Some(JobState::Done(ExitStatus::ExitedWith(1))),
+    }
+}
+
+/// Waits for the child `pid` of a job run in the background to exit or to be
+/// stopped, e.g. for `wait`. Returns `None` if it can't be waited for.
+pub fn wait_for_job(pid: libc::pid_t) -> Option<JobState> {
+    waitpid(pid, libc::WUNTRACED, false)
+}
+
+/// Stops the processes `pids`, e.g. for Ctrl-Z.
+pub fn stop(pids: &[libc::pid_t]) {
+    for pid in pids {
+        // SAFETY: sending a signal has no preconditions.
+        unsafe { libc::kill(*pid, libc::SIGSTOP) };
+    }
+}
+
+/// The modes of the terminal on the standard input, if it is one.
+pub fn terminal_modes() -> Option<libc::termios> {
+    // SAFETY: `modes` is only read if `tcgetattr` has filled it.
+    unsafe {
+        let mut modes: libc::termios = std::mem::zeroed();
+        (libc::tcgetattr(0, &mut modes) == 0).then_some(modes)
+    }
+}
+
+pub fn set_terminal_modes(modes: &libc::termios) {
+    // SAFETY: `modes` is a valid `termios`.
+    unsafe { libc::tcsetattr(0, libc::TCSADRAIN, modes) };
+}
+
+/// Sends `signal` to the processes `pids` of a job in the process group
+/// `pgid`, e.g. SIGCONT to let it continue.
+pub fn signal_job(
+    pgid: libc::pid_t,
+    pids: &[libc::pid_t],
+    signal: libc::c_int,
+) -> std::io::Result<()> {
+    // With job control, the group also has the processes the job has
+    // started itself.
+    let targets = if JOB_CONTROL.load(Ordering::Relaxed) {
+        vec![-pgid]
+    } else {
+        pids.to_vec()
+    };
+    for target in targets {
+        // SAFETY: sending a signal has no preconditions.
+        if unsafe { libc::kill(target, signal) } < 0 {
+            return Err(std::io::Error::last_os_error());
+        }
+    }
+    Ok(())
+}
+
+/// Returns what has become of the child `pid` of a job without waiting for
+/// it, or `None` if nothing has.
+pub fn poll(pid: libc::pid_t) -> Option<JobState> {
+    waitpid(
+        pid,
+        libc::WNOHANG | libc::WUNTRACED | libc::WCONTINUED,
+        false,
+    )
+}
+
+/// Waits for the child `pid` as `waitpid` does with `options`, and returns
+/// what has become of it. Returns `None` if it can't be waited for, if
+/// nothing has become of it with `WNOHANG`, or if `foreground` is set and
+/// Ctrl-Z is pressed meanwhile. A child run in the `foreground` which Ctrl-C
+/// kills interrupts the shell as well, which doesn't get SIGINT itself when
+/// the child is in a process group of its own.
+fn waitpid(pid: libc::pid_t, options: libc::c_int, foreground: bool) -> Option<JobState> {
+    let mut status = 0;
+    loop {
+        // SAFETY: `status` is a valid place to store the status in.
+        match unsafe { libc::waitpid(pid, &mut status, options) } {
+            0 => return None,
+            waited if waited > 0 => break,
+            _ => (),
+        }
+        if std::io::Error::last_os_error().raw_os_error() != Some(libc::EINTR)
+            || (foreground && signal::stop_requested())
+        {
+            return None;
+        }
+    }
+    Some(if libc::WIFSTOPPED(status) {
+        JobState::Stopped
+    } else if libc::WIFCONTINUED(status) {
+        JobState::Running
+    } else if libc::WIFSIGNALED(status) {
+        if foreground && libc::WTERMSIG(status) == libc::SIGINT {
+            signal::interrupt();
+        }
+        JobState::Killed(libc::WTERMSIG(status))
+    } else {
+        JobState::Done(ExitStatus::ExitedWith(libc::WEXITSTATUS(status)))
+    })
+}
@@ -0,0 +1,49 @@
+//! Matching of POSIX extended regular expressions as in `[[ s =~ regex ]]`,
+//! with the regex functions of libc.
+
+use std::ffi::{CStr, CString};
+
+/// Returns whether `regex` matches a part of `text`.
+pub fn is_match(regex: &str, text: &str) -> Result<bool, String> {
+    let invalid = |_: std::ffi::NulError| format!("{}: invalid regular expression", regex);
+    let c_regex = CString::new(regex).map_err(invalid)?;
+    // Shell strings can't contain NUL anyway.
+    let c_text = match CString::new(text) {
+        Ok(text) => text,
+        Err(_) => return Ok(false),
+    };
+
+    // SAFETY: `compiled` is only used after `regcomp` has initialized it,
+    // and freed once.
+    unsafe {
+        let mut compiled: libc::regex_t = std::mem::zeroed();
+        let err = libc::regcomp(
+            &mut compiled,
+            c_regex.as_ptr(),
+            libc::REG_EXTENDED | libc::REG_NOSUB,
+        );
+        if err != 0 {
+            let mut message = [0u8; 128];
+            libc::regerror(err, &compiled, message.as_mut_ptr().cast(), message.len());
+            let message = CStr::from_bytes_until_nul(&message)
+                .map_or("invalid regular expression".into(), CStr::to_string_lossy);
+            return Err(format!("{}: {}", regex, message));
+        }
+        let status = libc::regexec(&compiled, c_text.as_ptr(), 0, std::ptr::null_mut(), 0);
+        libc::regfree(&mut compiled);
+        Ok(status == 0)
+    }
+}
+
+/// Escapes the characters of `text` which are special in regexes, so that
+/// they only match themselves.
+pub fn escape(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for ch in text.chars() {
+        if r"\^$.|?*+()[]{}".contains(ch) {
+            escaped.push('\\');
+        }
+        escaped.push(ch);
+    }
+    escaped
+}
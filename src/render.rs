@@ -0,0 +1,484 @@
+//! Redisplay of the prompt and the input.
+//!
+//! The editor describes what the screen should look like as a [`Frame`] and
+//! the [`Renderer`] compares it with the previously drawn one to emit only the
+//! terminal updates needed to get there.
+
+use std::io::Write;
+
+use crossterm::{
+    cursor, queue,
+    style::{ContentStyle, Print, PrintStyledContent, StyledContent},
+    terminal::{Clear, ClearType},
+};
+use unicode_segmentation::UnicodeSegmentation;
+use unicode_width::UnicodeWidthStr;
+
+/// A grapheme cluster occupying one or two columns.
+#[derive(Clone, Debug, PartialEq, Eq)]
+struct Cell {
+    text: String,
+    width: usize,
+    style: ContentStyle,
+}
+
+/// The contents of the rows used by the prompt and the input, and where the
+/// cursor is placed in them. Rows are counted from the one the prompt starts
+/// at.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Frame {
+    rows: Vec<Vec<Cell>>,
+    cursor: (usize, usize),
+}
+
+impl Frame {
+    pub fn height(&self) -> usize {
+        self.rows.len()
+    }
+}
+
+/// Lays text out into a [`Frame`], wrapping at the terminal width.
+pub struct FrameBuilder {
+    columns: usize,
+    frame: Frame,
+    col: usize,
+}
+
+impl FrameBuilder {
+    pub fn new(columns: usize) -> Self {
+        Self {
+            columns: columns.max(1),
+            frame: Frame {
+                rows: vec![Vec::new()],
+                cursor: (0, 0),
+            },
+            col: 0,
+        }
+    }
+
+    fn new_row(&mut self) {
+        self.frame.rows.push(Vec::new());
+        self.col = 0;
+    }
+
+    pub fn push_str(&mut self, s: &str) {
+        self.push_styled(s, ContentStyle::new());
+    }
+
+    pub fn push_styled(&mut self, s: &str, style: ContentStyle) {
+        for grapheme in s.graphemes(true) {
+            if grapheme == "\n" {
+                self.new_row();
+                continue;
+            }
+
+            let width = grapheme.width();
+            // A wide character which doesn't fit in the rest of the row goes
+            // to the next one as the terminal does.
+            if self.col + width > self.columns {
+                self.new_row();
+            }
+            self.frame.rows.last_mut().unwrap().push(Cell {
+                text: grapheme.to_owned(),
+                width,
+                style,
+            });
+            self.col += width;
+        }
+    }
+
+    /// The number of rows used so far.
+    pub fn height(&self) -> usize {
+        self.frame.rows.len()
+    }
+
+    /// Places the cursor at the current position.
+    pub fn set_cursor(&mut self) {
+        if self.col >= self.columns {
+            self.new_row();
+        }
+        self.frame.cursor = (self.frame.rows.len() - 1, self.col);
+    }
+
+    pub fn build(self) -> Frame {
+        self.frame
+    }
+}
+
+/// A primitive terminal update.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Op {
+    MoveUp(usize),
+    MoveDown(usize),
+    MoveToColumn(usize),
+    /// Moves to the beginning of the next row, scrolling if needed.
+    NewLine,
+    Print(String),
+    PrintStyled(String, ContentStyle),
+    ClearUntilNewLine,
+    ClearFromCursorDown,
+}
+
+/// Keeps track of what is on the screen and computes the updates to draw a
+/// new frame.
+#[derive(Debug, Default)]
+pub struct Renderer {
+    previous: Frame,
+    /// The row the terminal cursor is on, relative to the first row.
+    row: usize,
+    /// The number of rows which exist on the screen below the first one
+    /// (plus one), i.e. the rows which can be reached without scrolling.
+    rows_on_screen: usize,
+}
+
+impl Renderer {
+    pub fn new() -> Self {
+        Self {
+            previous: Frame::default(),
+            row: 0,
+            rows_on_screen: 1,
+        }
+    }
+
+    /// Forgets the previous frame. Call this when the cursor has been moved
+    /// to the beginning of a fresh row, e.g. before drawing a new prompt.
+    pub fn reset(&mut self) {
+        *self = Renderer::new();
+    }
+
+    /// The number of rows the last frame used.
+    pub fn height(&self) -> usize {
+        self.previous.height()
+    }
+
+    /// Clears everything from the first row and forgets the previous frame
+    /// so that the next one is drawn from scratch.
+    pub fn invalidate(&mut self) {
+        let mut stdout = std::io::stdout();
+        let mut ops = Vec::new();
+        if self.row > 0 {
+            ops.push(Op::MoveUp(self.row));
+        }
+        ops.push(Op::MoveToColumn(0));
+        ops.push(Op::ClearFromCursorDown);
+        write_ops(&mut stdout, ops);
+        self.reset();
+    }
+
+    /// Moves the cursor to `row`, creating rows as needed.
+    fn move_to_row(&self, ops: &mut Vec<Op>, current: &mut usize, row: usize, rows: &mut usize) {
+        if row < *current {
+            ops.push(Op::MoveUp(*current - row));
+            *current = row;
+        }
+        let reachable = row.min(*rows - 1);
+        if reachable > *current {
+            ops.push(Op::MoveDown(reachable - *current));
+            *current = reachable;
+        }
+        while *current < row {
+            ops.push(Op::NewLine);
+            *current += 1;
+            *rows = (*rows).max(*current + 1);
+        }
+    }
+
+    /// Returns the updates to turn the previous frame into `frame` and
+    /// assumes they are applied.
+    pub fn diff(&mut self, frame: &Frame) -> Vec<Op> {
+        let mut ops = Vec::new();
+        let mut current = self.row;
+        let mut rows = self.rows_on_screen;
+
+        for (i, new_row) in frame.rows.iter().enumerate() {
+            let old_row = self.previous.rows.get(i).map(Vec::as_slice).unwrap_or(&[]);
+            let common = old_row
+                .iter()
+                .zip(new_row.iter())
+                .take_while(|(old, new)| old == new)
+                .count();
+            if common == old_row.len() && common == new_row.len() {
+                continue;
+            }
+
+            self.move_to_row(&mut ops, &mut current, i, &mut rows);
+            let col: usize = new_row[..common].iter().map(|cell| cell.width).sum();
+            ops.push(Op::MoveToColumn(col));
+            // Print runs of cells with the same style together.
+            for run in new_row[common..].chunk_by(|a, b| a.style == b.style) {
+                let text: String = run.iter().map(|cell| cell.text.as_str()).collect();
+                if run[0].style == ContentStyle::new() {
+                    ops.push(Op::Print(text));
+                } else {
+                    ops.push(Op::PrintStyled(text, run[0].style));
+                }
+            }
+            let old_width: usize = old_row.iter().map(|cell| cell.width).sum();
+            let new_width: usize = new_row.iter().map(|cell| cell.width).sum();
+            if new_width < old_width {
+                ops.push(Op::ClearUntilNewLine);
+            }
+        }
+
+        if self.previous.rows.len() > frame.rows.len() {
+            self.move_to_row(&mut ops, &mut current, frame.rows.len(), &mut rows);
+            ops.push(Op::MoveToColumn(0));
+            ops.push(Op::ClearFromCursorDown);
+        }
+
+        let (cursor_row, cursor_col) = frame.cursor;
+        self.move_to_row(&mut ops, &mut current, cursor_row, &mut rows);
+        ops.push(Op::MoveToColumn(cursor_col));
+
+        self.previous = frame.clone();
+        self.row = current;
+        self.rows_on_screen = rows;
+        ops
+    }
+
+    /// Draws `frame` on the terminal.
+    pub fn render(&mut self, frame: &Frame) {
+        let ops = self.diff(frame);
+        let mut stdout = std::io::stdout();
+        queue!(stdout, cursor::Hide).ok();
+        write_ops(&mut stdout, ops);
+        queue!(stdout, cursor::Show).ok();
+        stdout.flush().ok();
+    }
+
+    /// Moves the cursor to the beginning of the row below the last frame, so
+    /// that output written after it doesn't overwrite it.
+    pub fn move_below(&mut self) {
+        let mut ops = Vec::new();
+        let mut current = self.row;
+        let mut rows = self.rows_on_screen;
+        let last = self.previous.height().max(1) - 1;
+        self.move_to_row(&mut ops, &mut current, last, &mut rows);
+        ops.push(Op::NewLine);
+
+        let mut stdout = std::io::stdout();
+        write_ops(&mut stdout, ops);
+        stdout.flush().ok();
+        self.reset();
+    }
+}
+
+fn write_ops(stdout: &mut impl Write, ops: Vec<Op>) {
+    for op in ops {
+        match op {
+            Op::MoveUp(n) => queue!(stdout, cursor::MoveUp(n as u16)),
+            Op::MoveDown(n) => queue!(stdout, cursor::MoveDown(n as u16)),
+            Op::MoveToColumn(col) => queue!(stdout, cursor::MoveToColumn(col as u16)),
+            Op::NewLine => queue!(stdout, Print("\r\n")),
+            Op::Print(s) => queue!(stdout, Print(s)),
+            Op::PrintStyled(s, style) => {
+                queue!(stdout, PrintStyledContent(StyledContent::new(style, s)))
+            }
+            Op::ClearUntilNewLine => queue!(stdout, Clear(ClearType::UntilNewLine)),
+            Op::ClearFromCursorDown => queue!(stdout, Clear(ClearType::FromCursorDown)),
+        }
+        .ok();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crossterm::style::Stylize;
+
+    fn frame(columns: usize, before_cursor: &str, after_cursor: &str) -> Frame {
+        let mut builder = FrameBuilder::new(columns);
+        builder.push_str(before_cursor);
+        builder.set_cursor();
+        builder.push_str(after_cursor);
+        builder.build()
+    }
+
+    #[test]
+    fn first_render_prints_everything() {
+        let mut renderer = Renderer::new();
+        let ops = renderer.diff(&frame(80, "$ ls", ""));
+        assert_eq!(
+            ops,
+            vec![
+                Op::MoveToColumn(0),
+                Op::Print("$ ls".to_owned()),
+                Op::MoveToColumn(4),
+            ]
+        );
+    }
+
+    #[test]
+    fn styled_text_is_printed_separately() {
+        let mut renderer = Renderer::new();
+        let dim = ContentStyle::new().dim();
+        let mut builder = FrameBuilder::new(80);
+        builder.push_str("$ l");
+        builder.set_cursor();
+        builder.push_styled("s -l", dim);
+        let ops = renderer.diff(&builder.build());
+        assert_eq!(
+            ops,
+            vec![
+                Op::MoveToColumn(0),
+                Op::Print("$ l".to_owned()),
+                Op::PrintStyled("s -l".to_owned(), dim),
+                Op::MoveToColumn(3),
+            ]
+        );
+
+        // Typing the suggested character replaces the styled one.
+        let ops = renderer.diff(&frame(80, "$ ls", ""));
+        assert_eq!(
+            ops,
+            vec![
+                Op::MoveToColumn(3),
+                Op::Print("s".to_owned()),
+                Op::ClearUntilNewLine,
+                Op::MoveToColumn(4),
+            ]
+        );
+    }
+
+    #[test]
+    fn appending_prints_only_the_new_character() {
+        let mut renderer = Renderer::new();
+        renderer.diff(&frame(80, "$ l", ""));
+        let ops = renderer.diff(&frame(80, "$ ls", ""));
+        assert_eq!(
+            ops,
+            vec![
+                Op::MoveToColumn(3),
+                Op::Print("s".to_owned()),
+                Op::MoveToColumn(4),
+            ]
+        );
+    }
+
+    #[test]
+    fn moving_the_cursor_prints_nothing() {
+        let mut renderer = Renderer::new();
+        renderer.diff(&frame(80, "$ ls", ""));
+        let ops = renderer.diff(&frame(80, "$ l", "s"));
+        assert_eq!(ops, vec![Op::MoveToColumn(3)]);
+    }
+
+    #[test]
+    fn deleting_in_the_middle_reprints_the_rest_and_clears() {
+        let mut renderer = Renderer::new();
+        renderer.diff(&frame(80, "$ abc", ""));
+        let ops = renderer.diff(&frame(80, "$ a", "c"));
+        assert_eq!(
+            ops,
+            vec![
+                Op::MoveToColumn(3),
+                Op::Print("c".to_owned()),
+                Op::ClearUntilNewLine,
+                Op::MoveToColumn(3),
+            ]
+        );
+    }
+
+    #[test]
+    fn deleting_at_the_end_clears() {
+        let mut renderer = Renderer::new();
+        renderer.diff(&frame(80, "$ abc", ""));
+        let ops = renderer.diff(&frame(80, "$ ab", ""));
+        assert_eq!(
+            ops,
+            vec![
+                Op::MoveToColumn(4),
+                Op::ClearUntilNewLine,
+                Op::MoveToColumn(4),
+            ]
+        );
+    }
+
+    #[test]
+    fn input_wraps_to_a_new_row() {
+        let mut renderer = Renderer::new();
+        renderer.diff(&frame(4, "$ a", ""));
+        let ops = renderer.diff(&frame(4, "$ abc", ""));
+        assert_eq!(
+            ops,
+            vec![
+                Op::MoveToColumn(3),
+                Op::Print("b".to_owned()),
+                Op::NewLine,
+                Op::MoveToColumn(0),
+                Op::Print("c".to_owned()),
+                Op::MoveToColumn(1),
+            ]
+        );
+    }
+
+    #[test]
+    fn cursor_at_the_end_of_a_full_row_goes_to_the_next_row() {
+        let mut renderer = Renderer::new();
+        let f = frame(4, "$ ab", "");
+        assert_eq!(f.cursor, (1, 0));
+        assert_eq!(f.height(), 2);
+        let ops = renderer.diff(&f);
+        assert_eq!(
+            ops,
+            vec![
+                Op::MoveToColumn(0),
+                Op::Print("$ ab".to_owned()),
+                Op::NewLine,
+                Op::MoveToColumn(0),
+            ]
+        );
+    }
+
+    #[test]
+    fn deleting_back_across_a_wrap_clears_the_rows_below() {
+        let mut renderer = Renderer::new();
+        renderer.diff(&frame(4, "$ abc", ""));
+        let ops = renderer.diff(&frame(4, "$ a", ""));
+        assert_eq!(
+            ops,
+            vec![
+                Op::MoveUp(1),
+                Op::MoveToColumn(3),
+                Op::ClearUntilNewLine,
+                Op::MoveDown(1),
+                Op::MoveToColumn(0),
+                Op::ClearFromCursorDown,
+                Op::MoveUp(1),
+                Op::MoveToColumn(3),
+            ]
+        );
+    }
+
+    #[test]
+    fn wide_character_that_does_not_fit_moves_to_the_next_row() {
+        let f = frame(4, "$ a日", "");
+        assert_eq!(f.height(), 2);
+        assert_eq!(f.cursor, (1, 2));
+    }
+
+    #[test]
+    fn moving_below_a_previously_drawn_row_does_not_scroll() {
+        let mut renderer = Renderer::new();
+        renderer.diff(&frame(4, "$ a", "bcd"));
+        let ops = renderer.diff(&frame(4, "$ abcd", ""));
+        assert_eq!(ops, vec![Op::MoveDown(1), Op::MoveToColumn(2)]);
+    }
+
+    #[test]
+    fn rows_below_the_cursor_are_cleared_when_they_go_away() {
+        let mut renderer = Renderer::new();
+        renderer.diff(&frame(80, "$ a", "\napple  apricot"));
+        let ops = renderer.diff(&frame(80, "$ a", ""));
+        assert_eq!(
+            ops,
+            vec![
+                Op::MoveDown(1),
+                Op::MoveToColumn(0),
+                Op::ClearFromCursorDown,
+                Op::MoveUp(1),
+                Op::MoveToColumn(3),
+            ]
+        );
+    }
+}
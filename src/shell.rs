@@ -1,23 +1,1330 @@
+use std::{
+    collections::{BTreeMap, BTreeSet, HashMap},
+    os::unix::io::RawFd,
+    rc::Rc,
+};
+
 use tracing::debug;
 
-use crate::{process::ExitStatus, parser};
+use crate::{
+    arith, builtins, command_index,
+    command_paths::CommandPaths,
+    completion::{Completer, ShellNames},
+    expand, glob,
+    history::History,
+    job::{JobState, Jobs},
+    keymap::Keymaps,
+    parser::{
+        self, AndOr, Assignment, CaseItem, CaseTerminator, Command, CondExpr, Pipeline, Redirect,
+        RedirectKind, RedirectTarget, Span, Term, Word,
+    },
+    process::{self, ExitStatus, Redirection},
+    regex, signal,
+    variables::Variables,
+};
+
+const CONFIG_FILE_NAME: &str = ".toyshrc";
+
+pub struct Shell {
+    keymaps: Keymaps,
+    history: History,
+    completer: Completer,
+    jobs: Jobs,
+    variables: Variables,
+    options: Options,
+    functions: HashMap<String, Rc<Command>>,
+    command_paths: CommandPaths,
+    /// What the aliases stand for, sorted by their names to be listed.
+    aliases: BTreeMap<String, String>,
+    /// The aliases being expanded, which are not expanded again in what they
+    /// stand for, e.g. `ls` in `alias ls='ls -F'`.
+    expanding: Vec<String>,
+    /// `$1`, `$2`, ... of the function being run.
+    positional: Vec<String>,
+    /// The status of the last pipeline which has finished.
+    last_status: ExitStatus,
+    /// Whether `exec` has been run without a command, whose redirections are
+    /// kept instead of undone.
+    keeping_redirects: bool,
+    /// The pid of the last job run in the background, which is `$!`.
+    last_background: Option<libc::pid_t>,
+    /// The code of the outermost term being run, which names the job it
+    /// makes if it is stopped.
+    job_code: Option<String>,
+    /// The modes of the terminal for the shell itself, which are restored
+    /// when a job is stopped.
+    terminal_modes: Option<libc::termios>,
+    /// The modes of the terminal the stopped jobs have set, which are
+    /// restored when they continue in the foreground.
+    job_modes: HashMap<usize, libc::termios>,
+    /// The statuses of the commands of the last pipeline, which is
+    /// `$PIPESTATUS`.
+    pipe_status: Vec<ExitStatus>,
+    /// How many loops the command being run is in.
+    loops: usize,
+    /// How many functions are being run.
+    calls: usize,
+    /// The `break`, `continue` or `return` being run, which stops the
+    /// commands up to the loop or the function.
+    control: Option<Control>,
+    /// The descriptors and pids of the process substitutions of the
+    /// commands being run.
+    substitutions: Vec<(RawFd, libc::pid_t)>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Control {
+    Break(usize),
+    Continue(usize),
+    Return,
+}
 
-pub struct Shell;
+/// The options set with `set -o` which belong to the shell itself.
+#[derive(Debug, Default)]
+pub struct Options {
+    /// Whether a pattern which matches no files expands to nothing.
+    pub nullglob: bool,
+    /// Whether `**` in a pattern matches any number of directories.
+    pub globstar: bool,
+    /// Whether a pipeline fails if any of its commands fails rather than
+    /// only the last one.
+    pub pipefail: bool,
+}
 
 impl Shell {
+    pub fn new() -> Self {
+        Self {
+            keymaps: Keymaps::new(),
+            history: History::new(),
+            completer: Completer::new(),
+            jobs: Jobs::new(),
+            variables: Variables::new(),
+            options: Options::default(),
+            functions: HashMap::new(),
+            command_paths: CommandPaths::new(),
+            aliases: BTreeMap::new(),
+            expanding: Vec::new(),
+            positional: Vec::new(),
+            last_status: ExitStatus::ExitedWith(0),
+            pipe_status: vec![ExitStatus::ExitedWith(0)],
+            keeping_redirects: false,
+            last_background: None,
+            job_code: None,
+            terminal_modes: process::terminal_modes(),
+            job_modes: HashMap::new(),
+            loops: 0,
+            calls: 0,
+            control: None,
+            substitutions: Vec::new(),
+        }
+    }
+
+    pub fn keymaps(&self) -> &Keymaps {
+        &self.keymaps
+    }
+
+    pub fn keymaps_mut(&mut self) -> &mut Keymaps {
+        &mut self.keymaps
+    }
+
+    pub fn history(&self) -> &History {
+        &self.history
+    }
+
+    pub fn history_mut(&mut self) -> &mut History {
+        &mut self.history
+    }
+
+    pub fn completer(&self) -> &Completer {
+        &self.completer
+    }
+
+    pub fn completer_mut(&mut self) -> &mut Completer {
+        &mut self.completer
+    }
+
+    /// The names defined in the shell for the completer to complete.
+    pub fn completion_names(&self) -> ShellNames {
+        ShellNames {
+            commands: self
+                .aliases
+                .keys()
+                .chain(self.functions.keys())
+                .cloned()
+                .collect(),
+            variables: self.variables.iter().collect(),
+        }
+    }
+
+    pub fn jobs(&self) -> &Jobs {
+        &self.jobs
+    }
+
+    pub fn jobs_mut(&mut self) -> &mut Jobs {
+        &mut self.jobs
+    }
+
+    /// Records what has become of the processes of the jobs since they were
+    /// last checked, without waiting for them.
+    pub fn update_jobs(&mut self) {
+        for pid in self.jobs.unfinished_pids() {
+            if let Some(state) = process::poll(pid) {
+                self.jobs.update(pid, state);
+            }
+        }
+    }
+
+    /// Reports the jobs which have finished since they were last checked,
+    /// e.g. `[1]+  Done                    sleep 10`, and forgets them. This
+    /// is done before showing a prompt, as other shells do.
+    pub fn notify_jobs(&mut self) {
+        self.update_jobs();
+        let done: Vec<usize> = self
+            .jobs
+            .iter()
+            .filter(|job| job.state().status().is_some())
+            .map(|job| job.id)
+            .collect();
+        for id in done {
+            if let Some(description) = self.jobs.describe(id) {
+                eprintln!("{}", description);
+            }
+            self.jobs.retire(id);
+        }
+    }
+
+    pub fn variables(&self) -> &Variables {
+        &self.variables
+    }
+
+    pub fn variables_mut(&mut self) -> &mut Variables {
+        &mut self.variables
+    }
+
+    pub fn has_function(&self, name: &str) -> bool {
+        self.functions.contains_key(name)
+    }
+
+    pub fn command_paths_mut(&mut self) -> &mut CommandPaths {
+        &mut self.command_paths
+    }
+
+    pub fn aliases(&self) -> &BTreeMap<String, String> {
+        &self.aliases
+    }
+
+    pub fn aliases_mut(&mut self) -> &mut BTreeMap<String, String> {
+        &mut self.aliases
+    }
+
+    /// The status of the last pipeline which has finished, which is `$?`.
+    pub fn last_status(&self) -> ExitStatus {
+        self.last_status
+    }
+
+    pub fn last_background(&self) -> Option<libc::pid_t> {
+        self.last_background
+    }
+
+    pub fn pipe_status(&self) -> &[ExitStatus] {
+        &self.pipe_status
+    }
+
+    pub fn positional(&self) -> &[String] {
+        &self.positional
+    }
+
+    pub fn options(&self) -> &Options {
+        &self.options
+    }
+
+    pub fn options_mut(&mut self) -> &mut Options {
+        &mut self.options
+    }
+
+    /// Reads the history saved by previous sessions.
+    pub fn load_history(&mut self) {
+        let path = match History::default_path() {
+            Some(path) => path,
+            None => return,
+        };
+
+        if let Err(err) = self.history.load(path.clone()) {
+            eprintln!("toysh: {}: {}", path.display(), err);
+        }
+    }
+
+    /// Runs `~/.toyshrc` if it exists. This is where settings such as key
+    /// bindings are made persistent.
+    pub fn load_config(&mut self) {
+        let path = match std::env::var_os("HOME") {
+            Some(home) => std::path::Path::new(&home).join(CONFIG_FILE_NAME),
+            None => return,
+        };
+
+        match std::fs::read_to_string(&path) {
+            Ok(script) => {
+                self.run_script(&script);
+            }
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => (),
+            Err(err) => eprintln!("toysh: {}: {}", path.display(), err),
+        }
+    }
+
     pub fn run_script(&mut self, script: &str) -> ExitStatus {
         match parser::parse(script) {
             Ok(ast) => {
                 debug!(?ast);
+                self.run_terms(&ast.terms)
+            }
+            Err(parser::ParseError::Empty) => ExitStatus::ExitedWith(0),
+            Err(
+                parser::ParseError::Fatal(diagnostic) | parser::ParseError::Incomplete(diagnostic),
+            ) => {
+                debug!(?diagnostic);
+                eprint!("toysh: syntax error: {}", diagnostic.render(script));
+                // As for `parse` and `--dump-ast`.
+                self.last_status = ExitStatus::ExitedWith(2);
+                self.last_status
+            }
+        }
+    }
+
+    fn run_terms(&mut self, terms: &[Term]) -> ExitStatus {
+        let mut status = ExitStatus::ExitedWith(0);
+        for term in terms {
+            if self.control.is_some() {
+                break;
+            }
+            status = if term.background {
+                // `$?` is whether the job has been started, as it hasn't
+                // finished yet.
+                self.last_status = self.run_in_background(term);
+                self.last_status
+            } else {
+                self.run_term(term)
+            };
+        }
+        status
+    }
+
+    /// Starts running `term` in a child process and records it as a job
+    /// without waiting for it.
+    fn run_in_background(&mut self, term: &Term) -> ExitStatus {
+        match process::spawn_job(None, false, || self.run_term(term)) {
+            Ok(pid) => {
+                self.last_background = Some(pid);
+                let job = self.jobs.add(pid, &[pid], &term.code, JobState::Running);
+                debug!(id = job.id, pgid = job.pgid, command = %job.command, "started a job");
+                eprintln!("[{}] {}", job.id, job.pgid);
+                ExitStatus::ExitedWith(0)
+            }
+            Err(err) => {
+                eprintln!("toysh: fork: {}", err);
+                ExitStatus::ExitedWith(1)
+            }
+        }
+    }
+
+    /// Runs the pipelines joined by `&&` and `||` from left to right. Each
+    /// one after `&&` only runs if the status so far is zero, and after `||`
+    /// only if it is not.
+    fn run_term(&mut self, term: &Term) -> ExitStatus {
+        // Terms run by functions and aliases don't rename the job.
+        let outermost = self.job_code.is_none();
+        if outermost {
+            self.job_code = Some(term.code.clone());
+        }
+        let status = self.run_and_or(term);
+        if outermost {
+            self.job_code = None;
+        }
+        status
+    }
+
+    fn run_and_or(&mut self, term: &Term) -> ExitStatus {
+        let mut status = self.run_pipeline(&term.first);
+        for (operator, pipeline) in &term.rest {
+            if self.control.is_some() {
+                break;
+            }
+            let succeeded = status == ExitStatus::ExitedWith(0);
+            if succeeded == (*operator == AndOr::And) {
+                status = self.run_pipeline(pipeline);
+            }
+        }
+        status
+    }
+
+    /// Runs the function `body` with `argv` as `$0`, `$1`, ...
+    fn call_function(&mut self, body: &Command, argv: &[String]) -> ExitStatus {
+        let positional = std::mem::replace(&mut self.positional, argv[1..].to_vec());
+        self.calls += 1;
+        let status = self.run_command(body);
+        if self.control == Some(Control::Return) {
+            self.control = None;
+        }
+        self.calls -= 1;
+        self.positional = positional;
+        status
+    }
+
+    /// Runs `script` in a child process for `<(script)`, or `>(script)` if
+    /// `output` is set, and returns the path through which it is read or
+    /// written.
+    pub fn substitute_process(&mut self, script: &str, output: bool) -> Result<String, String> {
+        let (fd, pid) = process::substitute(|| self.run_script(script), output)?;
+        self.substitutions.push((fd, pid));
+        Ok(format!("/dev/fd/{}", fd))
+    }
+
+    /// Calls the function `name` with `args` in a child process and returns
+    /// what it prints, for `complete -F`.
+    pub fn capture_function(&mut self, name: &str, args: &[String]) -> String {
+        let body = match self.functions.get(name) {
+            Some(body) => Rc::clone(body),
+            None => return String::new(),
+        };
+        let argv: Vec<String> = std::iter::once(name.to_owned())
+            .chain(args.iter().cloned())
+            .collect();
+        match process::capture(|| self.call_function(&body, &argv)) {
+            Ok(output) => String::from_utf8_lossy(&output).into_owned(),
+            Err(_) => String::new(),
+        }
+    }
+
+    /// Runs `run` in a child process and waits for it.
+    fn run_in_child(&mut self, run: impl FnOnce(&mut Self) -> ExitStatus) -> ExitStatus {
+        match process::spawn_job(None, true, || run(self)) {
+            Ok(pid) => self.wait_foreground(&[pid])[0],
+            Err(err) => {
+                eprintln!("toysh: fork: {}", err);
+                ExitStatus::ExitedWith(1)
+            }
+        }
+    }
+
+    /// Waits for the processes `pids` of a command run in the foreground and
+    /// returns their statuses. If they are stopped, e.g. by Ctrl-Z, they are
+    /// recorded as a job instead, whose status is that of being stopped by
+    /// SIGTSTP.
+    fn wait_foreground(&mut self, pids: &[libc::pid_t]) -> Vec<ExitStatus> {
+        let states = wait_processes(pids);
+        process::reclaim_terminal();
+        if !states.contains(&JobState::Stopped) {
+            return states
+                .into_iter()
+                .map(|state| state.status().unwrap_or(ExitStatus::ExitedWith(1)))
+                .collect();
+        }
+
+        let code = self.job_code.clone().unwrap_or_default();
+        let id = self.jobs.add(pids[0], pids, &code, JobState::Running).id;
+        for (pid, state) in pids.iter().zip(states) {
+            self.jobs.update(*pid, state);
+        }
+        debug!(id, command = %code, "stopped a job");
+        self.job_stopped(id);
+        vec![ExitStatus::ExitedWith(128 + libc::SIGTSTP); pids.len()]
+    }
+
+    /// Reports that the job `id` run in the foreground has been stopped, and
+    /// takes the terminal back from it, whose modes are restored when it
+    /// continues in the foreground.
+    fn job_stopped(&mut self, id: usize) {
+        if let Some(modes) = process::terminal_modes() {
+            self.job_modes.insert(id, modes);
+        }
+        if let Some(modes) = &self.terminal_modes {
+            process::set_terminal_modes(modes);
+        }
+        self.jobs.make_current(id);
+        eprintln!();
+        if let Some(description) = self.jobs.describe(id) {
+            eprintln!("{}", description);
+        }
+    }
+
+    /// Lets the job `id` continue if it is stopped, and waits for it if
+    /// `foreground` is set, for `fg` and `bg`.
+    pub fn resume_job(&mut self, id: usize, foreground: bool) -> ExitStatus {
+        let (pgid, pids) = match self.jobs.get(id) {
+            Some(job) => (job.pgid, job.unfinished_pids()),
+            None => return ExitStatus::ExitedWith(1),
+        };
+        if foreground {
+            if let Some(modes) = self.job_modes.remove(&id) {
+                process::set_terminal_modes(&modes);
+            }
+            process::give_terminal(pgid);
+        }
+        if let Err(err) = process::signal_job(pgid, &pids, libc::SIGCONT) {
+            process::reclaim_terminal();
+            eprintln!("toysh: kill: {}", process::error_message(&err));
+            return ExitStatus::ExitedWith(1);
+        }
+        for pid in &pids {
+            self.jobs.update(*pid, JobState::Running);
+        }
+        if !foreground {
+            return ExitStatus::ExitedWith(0);
+        }
+
+        for (pid, state) in pids.iter().zip(wait_processes(&pids)) {
+            self.jobs.update(*pid, state);
+        }
+        process::reclaim_terminal();
+        match self.jobs.get(id).and_then(|job| job.state().status()) {
+            Some(status) => {
+                self.jobs.remove(id);
+                status
+            }
+            None => {
+                self.job_stopped(id);
+                ExitStatus::ExitedWith(128 + libc::SIGTSTP)
+            }
+        }
+    }
+
+    /// Runs `pipeline`, whose status is that of the last command, or with
+    /// `set -o pipefail` that of the last one which has failed.
+    fn run_pipeline(&mut self, pipeline: &Pipeline) -> ExitStatus {
+        let statuses = match pipeline.commands.as_slice() {
+            [command] => {
+                let substitutions = self.substitutions.len();
+                let status = self.run_command(command);
+                for (fd, pid) in self.substitutions.split_off(substitutions) {
+                    process::finish_substitution(fd, pid);
+                }
+                vec![status]
+            }
+            commands => {
+                match process::start_pipeline(commands.len(), |i| self.run_stage(&commands[i])) {
+                    Ok(pids) => self.wait_foreground(&pids),
+                    Err(err) => {
+                        eprintln!("toysh: {}", err);
+                        vec![ExitStatus::ExitedWith(1)]
+                    }
+                }
+            }
+        };
+        let last = statuses.last().copied();
+        let status = if self.options.pipefail {
+            statuses
+                .iter()
+                .rev()
+                .find(|status| **status != ExitStatus::ExitedWith(0))
+                .copied()
+                .or(last)
+        } else {
+            last
+        };
+        let status = status.unwrap_or(ExitStatus::ExitedWith(0));
+        self.last_status = status;
+        self.pipe_status = statuses;
+        status
+    }
+
+    /// Reports that there is no command `name` to run, with the closest name
+    /// of a command there is if any.
+    pub fn command_not_found(&mut self, name: &str) -> ExitStatus {
+        if process::is_on_path_but_not_executable(name) {
+            eprintln!("toysh: {}: Permission denied", name);
+            return ExitStatus::ExitedWith(126);
+        }
+        eprintln!("toysh: {}: command not found", name);
+        if let Some(suggestion) = self.suggest_command(name) {
+            eprintln!("toysh: did you mean `{}`?", suggestion);
+        }
+        ExitStatus::ExitedWith(127)
+    }
+
+    /// Returns the name of a builtin, function, alias or executable on
+    /// `$PATH` which is a typo of `name` away, e.g. `git` for `gti`.
+    fn suggest_command(&mut self, name: &str) -> Option<String> {
+        if name.contains('/') {
+            return None;
+        }
+        // Short names are too close to too many others.
+        let max_distance = match name.chars().count() {
+            0..=2 => return None,
+            3..=5 => 1,
+            _ => 2,
+        };
+        // The index of the completer is not used as it is built in threads,
+        // which don't exist in the child process of a pipeline.
+        let path = std::env::var("PATH").unwrap_or_default();
+        let mut names: BTreeSet<String> = path
+            .split(':')
+            .flat_map(command_index::find_executables)
+            .collect();
+        names.extend(builtins::builtin_names().map(str::to_owned));
+        names.extend(self.functions.keys().cloned());
+        names.extend(self.aliases.keys().cloned());
+        names
+            .into_iter()
+            .map(|candidate| (edit_distance(name, &candidate), candidate))
+            .filter(|(distance, _)| (1..=max_distance).contains(distance))
+            .min_by_key(|(distance, _)| *distance)
+            .map(|(_, candidate)| candidate)
+    }
+
+    /// Runs `command` as what the alias of its name stands for, with the rest
+    /// of its words and its redirections after that as if the alias were
+    /// replaced in the script. Returns `None` if it is not an alias.
+    fn run_alias(&mut self, command: &Command) -> Option<ExitStatus> {
+        let Command::Simple {
+            assignments,
+            argv,
+            redirects,
+        } = command
+        else {
+            return None;
+        };
+        let name = self.alias_name(argv.first()?)?.to_owned();
+        let value = self.aliases[&name].clone();
+        let mut terms = match self.parse_alias(&name, &value) {
+            Ok(terms) => terms,
+            Err(status) => return Some(status),
+        };
+
+        let mut rest = argv[1..].to_vec();
+        // An alias ending with a blank, e.g. `alias sudo='sudo '`, lets the
+        // next word be an alias as well.
+        let mut trailing = value.ends_with([' ', '\t']);
+        let mut i = 0;
+        let mut expanded = vec![name.clone()];
+        while trailing && i < rest.len() {
+            let Some(next) = self.alias_name(&rest[i]).map(str::to_owned) else {
+                break;
+            };
+            if expanded.contains(&next) {
+                break;
+            }
+            let next_value = self.aliases[&next].clone();
+            let words = match self.parse_alias(&next, &next_value) {
+                Ok(terms) => match terms.as_slice() {
+                    [] => Vec::new(),
+                    [Term {
+                        first: Pipeline { commands },
+                        rest,
+                        background: false,
+                        ..
+                    }] if rest.is_empty() => match commands.as_slice() {
+                        [Command::Simple {
+                            assignments,
+                            argv,
+                            redirects,
+                        }] if assignments.is_empty() && redirects.is_empty() => argv.clone(),
+                        _ => break,
+                    },
+                    _ => break,
+                },
+                Err(status) => return Some(status),
+            };
+            let len = words.len();
+            rest.splice(i..i + 1, words);
+            i += len;
+            trailing = next_value.ends_with([' ', '\t']);
+            expanded.push(next);
+        }
+
+        // The words after an alias ending with a separator, e.g.
+        // `alias x='cd ..;'`, are a command of their own.
+        if terms.is_empty() || value.trim_end().ends_with([';', '&', '\n']) {
+            terms.push(Term {
+                code: String::new(),
+                first: Pipeline {
+                    commands: vec![Command::Simple {
+                        assignments: Vec::new(),
+                        argv: Vec::new(),
+                        redirects: Vec::new(),
+                    }],
+                },
+                rest: Vec::new(),
+                background: false,
+            });
+        }
+        if let Some(Command::Simple {
+            assignments: first, ..
+        }) = terms[0].first.commands.first_mut()
+        {
+            first.splice(0..0, assignments.iter().cloned());
+        }
+        let last = terms.last_mut().and_then(|term| {
+            match term.rest.last_mut() {
+                Some((_, pipeline)) => pipeline,
+                None => &mut term.first,
+            }
+            .commands
+            .last_mut()
+        });
+        match last {
+            Some(Command::Simple {
+                argv, redirects: r, ..
+            }) => {
+                argv.extend(rest);
+                r.extend(redirects.iter().cloned());
+            }
+            _ if rest.is_empty() && redirects.is_empty() => (),
+            _ => {
+                eprintln!(
+                    "toysh: {}: the alias doesn't end with a simple command",
+                    name
+                );
+                return Some(ExitStatus::ExitedWith(2));
+            }
+        }
+
+        self.expanding.push(name);
+        let status = self.run_terms(&terms);
+        self.expanding.pop();
+        Some(status)
+    }
+
+    /// Returns the name of the alias `word` is, which it is only if it is
+    /// not quoted nor expanded and the alias is not being expanded.
+    fn alias_name<'a>(&self, word: &'a Word) -> Option<&'a str> {
+        match word.0.as_slice() {
+            [Span::Literal(name)]
+                if self.aliases.contains_key(name) && !self.expanding.contains(name) =>
+            {
+                Some(name)
+            }
+            _ => None,
+        }
+    }
+
+    fn parse_alias(&self, name: &str, value: &str) -> Result<Vec<Term>, ExitStatus> {
+        match parser::parse(value) {
+            Ok(ast) => Ok(ast.terms),
+            Err(parser::ParseError::Empty) => Ok(Vec::new()),
+            Err(
+                parser::ParseError::Fatal(diagnostic) | parser::ParseError::Incomplete(diagnostic),
+            ) => {
+                eprint!(
+                    "toysh: {}: syntax error in the alias: {}",
+                    name,
+                    diagnostic.render(value)
+                );
+                Err(ExitStatus::ExitedWith(2))
+            }
+        }
+    }
+
+    /// Runs a command of a pipeline in the child process forked for it.
+    fn run_stage(&mut self, command: &Command) -> ExitStatus {
+        if let Some(status) = self.run_alias(command) {
+            return status;
+        }
+        match command {
+            Command::Simple {
+                assignments,
+                argv,
+                redirects,
+            } => {
+                let argv = match expand::expand_words(self, argv) {
+                    Ok(argv) if argv.is_empty() => return self.assign(assignments),
+                    Ok(argv) => argv,
+                    Err(err) => {
+                        eprintln!("toysh: {}", err);
+                        return ExitStatus::ExitedWith(1);
+                    }
+                };
+                // The child exits afterwards, so the descriptors don't have
+                // to be restored.
+                let redirected = self
+                    .expand_redirects(redirects)
+                    .and_then(|redirections| process::redirect(&redirections));
+                if let Err(err) = redirected {
+                    eprintln!("toysh: {}", err);
+                    return ExitStatus::ExitedWith(1);
+                }
+                self.with_assignments(assignments, |shell| {
+                    if let Some(body) = shell.functions.get(&argv[0]) {
+                        let body = Rc::clone(body);
+                        return shell.call_function(&body, &argv);
+                    }
+                    if let Some(builtin) = builtins::builtin_command(&argv[0]) {
+                        return builtin(shell, &argv);
+                    }
+                    match shell.command_paths.find(&argv[0]) {
+                        Some(path) => process::exec(&path, &argv),
+                        None => shell.command_not_found(&argv[0]),
+                    }
+                })
+            }
+            // This is already a child process.
+            Command::Subshell { terms, redirects } => {
+                self.with_redirects(redirects, |shell| shell.run_terms(terms))
+            }
+            command => self.run_command(command),
+        }
+    }
+
+    fn run_command(&mut self, command: &Command) -> ExitStatus {
+        if let Some(status) = self.run_alias(command) {
+            return status;
+        }
+        match command {
+            Command::Simple {
+                assignments,
+                argv,
+                redirects,
+            } => {
+                let argv = match expand::expand_words(self, argv) {
+                    Ok(argv) => argv,
+                    Err(err) => {
+                        eprintln!("toysh: {}", err);
+                        return ExitStatus::ExitedWith(1);
+                    }
+                };
+                let name = match argv.first() {
+                    Some(name) => name,
+                    None => return self.assign(assignments),
+                };
+
+                if let Some(body) = self.functions.get(name) {
+                    let body = Rc::clone(body);
+                    return self.with_assignments(assignments, |shell| {
+                        shell.with_redirects(redirects, |shell| shell.call_function(&body, &argv))
+                    });
+                }
+                match builtins::builtin_command(name) {
+                    Some(builtin) => self.with_assignments(assignments, |shell| {
+                        shell.with_redirects(redirects, |shell| builtin(shell, &argv))
+                    }),
+                    None => self.with_assignments(assignments, |shell| {
+                        shell.with_redirects(redirects, |shell| {
+                            match shell.command_paths.find(&argv[0]) {
+                                Some(path) => shell.run_in_child(|_| process::exec(&path, &argv)),
+                                None => shell.command_not_found(&argv[0]),
+                            }
+                        })
+                    }),
+                }
+            }
+            Command::Subshell { terms, redirects } => self.run_in_child(|shell| {
+                shell.with_redirects(redirects, |shell| shell.run_terms(terms))
+            }),
+            Command::Group { terms, redirects } => {
+                self.with_redirects(redirects, |shell| shell.run_terms(terms))
+            }
+            Command::If {
+                branches,
+                otherwise,
+                redirects,
+            } => self.with_redirects(redirects, |shell| {
+                for (condition, body) in branches {
+                    if shell.run_terms(condition) == ExitStatus::ExitedWith(0) {
+                        return shell.run_terms(body);
+                    }
+                }
+                match otherwise {
+                    Some(body) => shell.run_terms(body),
+                    None => ExitStatus::ExitedWith(0),
+                }
+            }),
+            Command::While {
+                condition,
+                body,
+                until,
+                redirects,
+            } => self.with_redirects(redirects, |shell| {
+                shell.run_loop(|shell| {
+                    let status = shell.run_terms(condition);
+                    if shell.control.is_some() {
+                        return Some(status);
+                    }
+                    if (status == ExitStatus::ExitedWith(0)) == *until {
+                        return None;
+                    }
+                    Some(shell.run_terms(body))
+                })
+            }),
+            Command::For {
+                name,
+                words,
+                body,
+                redirects,
+            } => self.with_redirects(redirects, |shell| {
+                let words = match words {
+                    Some(words) => match expand::expand_words(shell, words) {
+                        Ok(words) => words,
+                        Err(err) => {
+                            eprintln!("toysh: {}", err);
+                            return ExitStatus::ExitedWith(1);
+                        }
+                    },
+                    None => shell.positional.clone(),
+                };
+                let mut words = words.into_iter();
+                shell.run_loop(|shell| {
+                    shell.variables.set(name, words.next()?);
+                    Some(shell.run_terms(body))
+                })
+            }),
+            Command::ArithFor {
+                init,
+                condition,
+                step,
+                body,
+                redirects,
+            } => self.with_redirects(redirects, |shell| {
+                if let Err(err) = arith::evaluate(init, &mut shell.variables) {
+                    eprintln!("toysh: {}", err);
+                    return ExitStatus::ExitedWith(1);
+                }
+                let mut first = true;
+                shell.run_loop(|shell| {
+                    // The step is also evaluated after `continue`.
+                    let evaluated = if first {
+                        first = false;
+                        Ok(0)
+                    } else {
+                        arith::evaluate(step, &mut shell.variables)
+                    };
+                    let evaluated = evaluated.and_then(|_| match condition.trim() {
+                        "" => Ok(1),
+                        condition => arith::evaluate(condition, &mut shell.variables),
+                    });
+                    match evaluated {
+                        Ok(0) => None,
+                        Ok(_) => Some(shell.run_terms(body)),
+                        // Stop the loop as if it were broken out of.
+                        Err(err) => {
+                            eprintln!("toysh: {}", err);
+                            shell.control = Some(Control::Break(1));
+                            Some(ExitStatus::ExitedWith(1))
+                        }
+                    }
+                })
+            }),
+            Command::Case {
+                word,
+                items,
+                redirects,
+            } => self.with_redirects(redirects, |shell| {
+                shell.run_case(word, items).unwrap_or_else(|err| {
+                    eprintln!("toysh: {}", err);
+                    ExitStatus::ExitedWith(1)
+                })
+            }),
+            Command::Cond { expr, redirects } => {
+                self.with_redirects(redirects, |shell| match shell.test(expr) {
+                    Ok(true) => ExitStatus::ExitedWith(0),
+                    Ok(false) => ExitStatus::ExitedWith(1),
+                    Err(err) => {
+                        eprintln!("toysh: {}", err);
+                        ExitStatus::ExitedWith(2)
+                    }
+                })
+            }
+            Command::Function { name, body } => {
+                self.functions.insert(name.clone(), Rc::clone(body));
                 ExitStatus::ExitedWith(0)
             }
-            Err(parser::ParseError::Empty) => {
+            Command::Return(status) => {
+                if self.calls == 0 {
+                    eprintln!("toysh: return: can only return from a function");
+                    return ExitStatus::ExitedWith(1);
+                }
+                self.control = Some(Control::Return);
+                match self.numeric_argument("return", status.as_ref()) {
+                    // Only the low 8 bits are kept as in exit statuses.
+                    Ok(Some(status)) => ExitStatus::ExitedWith((status & 0xff) as i32),
+                    Ok(None) => self.last_status,
+                    Err(err) => {
+                        eprintln!("toysh: {}", err);
+                        ExitStatus::ExitedWith(2)
+                    }
+                }
+            }
+            Command::Break(count) | Command::Continue(count) => {
+                let name = match command {
+                    Command::Break(_) => "break",
+                    _ => "continue",
+                };
+                if self.loops == 0 {
+                    eprintln!("toysh: {}: only meaningful in a loop", name);
+                    return ExitStatus::ExitedWith(0);
+                }
+                let count = match self.numeric_argument(name, count.as_ref()) {
+                    Ok(Some(count)) if count > 0 => count as usize,
+                    Ok(Some(count)) => {
+                        eprintln!("toysh: {}: {}: loop count out of range", name, count);
+                        return ExitStatus::ExitedWith(1);
+                    }
+                    Ok(None) => 1,
+                    Err(err) => {
+                        eprintln!("toysh: {}", err);
+                        return ExitStatus::ExitedWith(1);
+                    }
+                };
+                let count = count.min(self.loops);
+                self.control = Some(match command {
+                    Command::Break(_) => Control::Break(count),
+                    _ => Control::Continue(count),
+                });
                 ExitStatus::ExitedWith(0)
             }
-            Err(parser::ParseError::Fatal(err)) => {
-                debug!("Parse error: {}", err);
-                ExitStatus::ExitedWith(-1)
+        }
+    }
+
+    /// Expands the argument of `return`, `break` or `continue` `name` into
+    /// an integer, or `None` if there is none, e.g. when it is an unset `$1`.
+    fn numeric_argument(&mut self, name: &str, word: Option<&Word>) -> Result<Option<i64>, String> {
+        let args = match word {
+            Some(word) => expand::expand_words(self, std::slice::from_ref(word))?,
+            None => return Ok(None),
+        };
+        match args.as_slice() {
+            [] => Ok(None),
+            [arg] => arg
+                .parse()
+                .map(Some)
+                .map_err(|_| format!("{}: {}: numeric argument required", name, arg)),
+            _ => Err(format!("{}: too many arguments", name)),
+        }
+    }
+
+    fn run_case(&mut self, word: &Word, items: &[CaseItem]) -> Result<ExitStatus, String> {
+        let word = expand::expand_word(self, word)?;
+        let mut status = ExitStatus::ExitedWith(0);
+        let mut fall_through = false;
+        for item in items {
+            if !fall_through {
+                let mut matched = false;
+                for pattern in &item.patterns {
+                    if glob::matches(&expand::expand_pattern(self, pattern)?, &word) {
+                        matched = true;
+                        break;
+                    }
+                }
+                if !matched {
+                    continue;
+                }
+            }
+            status = self.run_terms(&item.body);
+            match item.terminator {
+                CaseTerminator::Break => break,
+                CaseTerminator::FallThrough => fall_through = true,
+                CaseTerminator::Continue => fall_through = false,
+            }
+        }
+        Ok(status)
+    }
+
+    /// Evaluates the expression of `[[ ]]`. The right of `==` and `!=` is a
+    /// pattern and that of `=~` a regex, while `-eq` and the like compare
+    /// arithmetic expressions.
+    fn test(&mut self, expr: &CondExpr) -> Result<bool, String> {
+        let value = match expr {
+            CondExpr::And(left, right) => self.test(left)? && self.test(right)?,
+            CondExpr::Or(left, right) => self.test(left)? || self.test(right)?,
+            CondExpr::Not(expr) => !self.test(expr)?,
+            CondExpr::Word(word) => !expand::expand_word(self, word)?.is_empty(),
+            CondExpr::Unary { op, operand } => {
+                let operand = expand::expand_word(self, operand)?;
+                match op.as_str() {
+                    "-z" => operand.is_empty(),
+                    "-n" => !operand.is_empty(),
+                    op => test_file(op, &operand),
+                }
+            }
+            CondExpr::Binary { op, left, right } => {
+                let left = expand::expand_word(self, left)?;
+                match op.as_str() {
+                    "==" | "=" => glob::matches(&expand::expand_pattern(self, right)?, &left),
+                    "!=" => !glob::matches(&expand::expand_pattern(self, right)?, &left),
+                    "=~" => regex::is_match(&expand::expand_regex(self, right)?, &left)?,
+                    "<" => left < expand::expand_word(self, right)?,
+                    ">" => left > expand::expand_word(self, right)?,
+                    op => {
+                        let right = expand::expand_word(self, right)?;
+                        let left = arith::evaluate(&left, &mut self.variables)?;
+                        let right = arith::evaluate(&right, &mut self.variables)?;
+                        match op {
+                            "-eq" => left == right,
+                            "-ne" => left != right,
+                            "-lt" => left < right,
+                            "-le" => left <= right,
+                            "-gt" => left > right,
+                            _ => left >= right,
+                        }
+                    }
+                }
+            }
+        };
+        Ok(value)
+    }
+
+    /// Runs `iterate` until it returns `None`, which it does when the loop
+    /// is over, or a `break` in it stops the loop. Ctrl-C stops it as well
+    /// so that a loop which doesn't end can be interrupted although each
+    /// command in it is.
+    fn run_loop(&mut self, mut iterate: impl FnMut(&mut Self) -> Option<ExitStatus>) -> ExitStatus {
+        self.loops += 1;
+        let mut status = ExitStatus::ExitedWith(0);
+        loop {
+            if signal::interrupted() {
+                status = ExitStatus::ExitedWith(130);
+                break;
+            }
+            match iterate(self) {
+                Some(iterated) => status = iterated,
+                None => break,
+            }
+            match self.control {
+                Some(Control::Break(count)) => {
+                    self.control = (count > 1).then(|| Control::Break(count - 1));
+                    break;
+                }
+                // `continue 2` goes on with the loop around this one.
+                Some(Control::Continue(count)) if count > 1 => {
+                    self.control = Some(Control::Continue(count - 1));
+                    break;
+                }
+                Some(Control::Continue(_)) => self.control = None,
+                Some(Control::Return) => break,
+                None => (),
+            }
+        }
+        self.loops -= 1;
+        status
+    }
+
+    /// Sets the variables of a command which only has assignments.
+    fn assign(&mut self, assignments: &[Assignment]) -> ExitStatus {
+        for assignment in assignments {
+            match expand::expand_word(self, &assignment.value) {
+                Ok(value) => self.variables.set(&assignment.name, value),
+                Err(err) => {
+                    eprintln!("toysh: {}", err);
+                    return ExitStatus::ExitedWith(1);
+                }
+            }
+        }
+        ExitStatus::ExitedWith(0)
+    }
+
+    /// Runs `run` with `assignments` exported, which are undone afterwards,
+    /// as the environment of a command, e.g. `LC_ALL=C sort`.
+    fn with_assignments(
+        &mut self,
+        assignments: &[Assignment],
+        run: impl FnOnce(&mut Self) -> ExitStatus,
+    ) -> ExitStatus {
+        let mut saved = Vec::new();
+        let mut status = None;
+        for assignment in assignments {
+            match expand::expand_word(self, &assignment.value) {
+                Ok(value) => saved.push(self.variables.export_temporarily(&assignment.name, value)),
+                Err(err) => {
+                    eprintln!("toysh: {}", err);
+                    status = Some(ExitStatus::ExitedWith(1));
+                    break;
+                }
+            }
+        }
+        let status = status.unwrap_or_else(|| run(self));
+        // In reverse so that `x=1 x=2 command` restores `x` as it was first.
+        for saved in saved.into_iter().rev() {
+            self.variables.restore(saved);
+        }
+        status
+    }
+
+    /// Makes the redirections of the command being run stay in effect once it
+    /// is done, for `exec` without a command.
+    pub fn keep_redirects(&mut self) {
+        self.keeping_redirects = true;
+    }
+
+    /// Runs `run` with `redirects` applied, which are undone afterwards
+    /// unless `run` calls [`Shell::keep_redirects`].
+    fn with_redirects(
+        &mut self,
+        redirects: &[Redirect],
+        run: impl FnOnce(&mut Self) -> ExitStatus,
+    ) -> ExitStatus {
+        let saved = self
+            .expand_redirects(redirects)
+            .and_then(|redirections| process::redirect(&redirections));
+        let saved = match saved {
+            Ok(saved) => saved,
+            Err(err) => {
+                eprintln!("toysh: {}", err);
+                return ExitStatus::ExitedWith(1);
+            }
+        };
+        let status = run(self);
+        if std::mem::take(&mut self.keeping_redirects) {
+            saved.keep();
+        } else {
+            saved.restore();
+        }
+        status
+    }
+
+    fn expand_redirects(&mut self, redirects: &[Redirect]) -> Result<Vec<Redirection>, String> {
+        let mut redirections = Vec::with_capacity(redirects.len());
+        for redirect in redirects {
+            let fd = redirect.fd;
+            let path = match &redirect.target {
+                RedirectTarget::File(word) => expand::expand_path(self, word)?,
+                RedirectTarget::Fd(from) => {
+                    redirections.push(Redirection::Duplicate { fd, from: *from });
+                    continue;
+                }
+                RedirectTarget::Text(text) => {
+                    let text = expand::expand_word(self, text)?;
+                    redirections.push(Redirection::Text { fd, text });
+                    continue;
+                }
+            };
+            redirections.push(match redirect.kind {
+                RedirectKind::Read => Redirection::Read { fd, path },
+                RedirectKind::Write | RedirectKind::Append => Redirection::Write {
+                    fd,
+                    path,
+                    append: redirect.kind == RedirectKind::Append,
+                },
+            });
+        }
+        Ok(redirections)
+    }
+}
+
+/// Waits for each of the processes `pids` of a command run in the foreground
+/// to exit or to be stopped. Ctrl-Z stops all of them.
+fn wait_processes(pids: &[libc::pid_t]) -> Vec<JobState> {
+    let mut states = Vec::with_capacity(pids.len());
+    for (i, pid) in pids.iter().enumerate() {
+        let state = match process::wait_for_change(*pid) {
+            Some(state) => state,
+            None => {
+                process::stop(&pids[i..]);
+                process::wait_for_change(*pid).unwrap_or(JobState::Stopped)
+            }
+        };
+        states.push(state);
+    }
+    states
+}
+
+/// Tests the file at `path` with `op` such as `-f` in `[[ ]]`.
+/// Returns the number of characters to insert, delete, replace or swap with
+/// the next one to turn `a` into `b`.
+fn edit_distance(a: &str, b: &str) -> usize {
+    let a: Vec<char> = a.chars().collect();
+    let b: Vec<char> = b.chars().collect();
+    // `distances[i][j]` is the distance between `a[..i]` and `b[..j]`.
+    let mut distances = vec![vec![0; b.len() + 1]; a.len() + 1];
+    for (i, row) in distances.iter_mut().enumerate() {
+        row[0] = i;
+    }
+    for (j, distance) in distances[0].iter_mut().enumerate() {
+        *distance = j;
+    }
+    for i in 1..=a.len() {
+        for j in 1..=b.len() {
+            let cost = usize::from(a[i - 1] != b[j - 1]);
+            let mut distance = (distances[i - 1][j] + 1)
+                .min(distances[i][j - 1] + 1)
+                .min(distances[i - 1][j - 1] + cost);
+            if i > 1 && j > 1 && a[i - 1] == b[j - 2] && a[i - 2] == b[j - 1] {
+                distance = distance.min(distances[i - 2][j - 2] + 1);
             }
+            distances[i][j] = distance;
         }
     }
-}
\ No newline at end of file
+    distances[a.len()][b.len()]
+}
+
+/// Tests the file at `path` with `op` such as `-f` in `[[ ]]`.
+fn test_file(op: &str, path: &str) -> bool {
+    let metadata = match op {
+        "-L" | "-h" => std::fs::symlink_metadata(path),
+        _ => std::fs::metadata(path),
+    };
+    let metadata = match metadata {
+        Ok(metadata) => metadata,
+        Err(_) => return false,
+    };
+    let access = match op {
+        "-e" => return true,
+        "-f" => return metadata.is_file(),
+        "-d" => return metadata.is_dir(),
+        "-s" => return metadata.len() > 0,
+        "-L" | "-h" => return metadata.file_type().is_symlink(),
+        "-r" => libc::R_OK,
+        "-w" => libc::W_OK,
+        _ => libc::X_OK,
+    };
+    // The mode alone doesn't tell whether the shell may access it, e.g. as
+    // root.
+    std::ffi::CString::new(path).is_ok_and(|path| {
+        // SAFETY: `path` is a valid C string.
+        unsafe { libc::access(path.as_ptr(), access) == 0 }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Runs `script` in a new shell and returns the value of `$name` after.
+    fn run(script: &str, name: &str) -> Option<String> {
+        let mut shell = Shell::new();
+        shell.run_script(script);
+        shell.variables().get(name)
+    }
+
+    /// Runs `script` in a new shell and returns its exit status.
+    fn status(script: &str) -> i32 {
+        let ExitStatus::ExitedWith(status) = Shell::new().run_script(script);
+        status
+    }
+
+    #[test]
+    fn return_statuses() {
+        assert_eq!(status("f() { return $1; }; f 3"), 3);
+        // Without a status, the one of the last command is kept.
+        assert_eq!(status("g() { return 5; }; f() { g; return $1; }; f"), 5);
+        assert_eq!(status("f() { return 257; }; f"), 1);
+        assert_eq!(status("f() { return -1; }; f"), 255);
+        assert_eq!(status("f() { n=4; return $n; }; f"), 4);
+        assert_eq!(status("f() { return x; }; f"), 2);
+        assert_eq!(status("return 3"), 1);
+        // Nothing runs after the `return`.
+        assert_eq!(run("f() { return 0; x=1; }; f", "x"), None);
+    }
+
+    #[test]
+    fn loop_counts() {
+        let nested = |control: &str, count: &str| {
+            let script = format!(
+                "f() {{ for i in 1 2; do for j in a b; do x=$x$i$j; {} $1; done; done; }}; f {}",
+                control, count
+            );
+            run(&script, "x")
+        };
+        assert_eq!(nested("break", "").as_deref(), Some("1a2a"));
+        assert_eq!(nested("break", "2").as_deref(), Some("1a"));
+        assert_eq!(nested("break", "9").as_deref(), Some("1a"));
+        assert_eq!(nested("continue", "").as_deref(), Some("1a1b2a2b"));
+        assert_eq!(nested("continue", "2").as_deref(), Some("1a2a"));
+
+        assert_eq!(status("for i in 1; do break 0; done"), 1);
+        assert_eq!(status("for i in 1; do continue x; done"), 1);
+    }
+
+    #[test]
+    fn braces_in_parameter_defaults() {
+        assert_eq!(run("x=${data:-{}}", "x").as_deref(), Some("{}"));
+        assert_eq!(run("x=${y:-{a,b}}", "x").as_deref(), Some("{a,b}"));
+        assert_eq!(run("y=1; x=${y:+{$y}}", "x").as_deref(), Some("{1}"));
+    }
+}
@@ -0,0 +1,197 @@
+//! Signals which the shell handles itself.
+
+use std::sync::atomic::{AtomicBool, Ordering};
+
+/// The signals which can be given by name, e.g. to `kill`, from the lowest
+/// number.
+const SIGNALS: &[(&str, libc::c_int)] = &[
+    ("HUP", libc::SIGHUP),
+    ("INT", libc::SIGINT),
+    ("QUIT", libc::SIGQUIT),
+    ("ILL", libc::SIGILL),
+    ("TRAP", libc::SIGTRAP),
+    ("ABRT", libc::SIGABRT),
+    ("BUS", libc::SIGBUS),
+    ("FPE", libc::SIGFPE),
+    ("KILL", libc::SIGKILL),
+    ("USR1", libc::SIGUSR1),
+    ("SEGV", libc::SIGSEGV),
+    ("USR2", libc::SIGUSR2),
+    ("PIPE", libc::SIGPIPE),
+    ("ALRM", libc::SIGALRM),
+    ("TERM", libc::SIGTERM),
+    ("CHLD", libc::SIGCHLD),
+    ("CONT", libc::SIGCONT),
+    ("STOP", libc::SIGSTOP),
+    ("TSTP", libc::SIGTSTP),
+    ("TTIN", libc::SIGTTIN),
+    ("TTOU", libc::SIGTTOU),
+    ("URG", libc::SIGURG),
+    ("XCPU", libc::SIGXCPU),
+    ("XFSZ", libc::SIGXFSZ),
+    ("VTALRM", libc::SIGVTALRM),
+    ("PROF", libc::SIGPROF),
+    ("WINCH", libc::SIGWINCH),
+    ("IO", libc::SIGIO),
+    ("SYS", libc::SIGSYS),
+];
+
+/// Whether SIGINT has been received while running a command line.
+static INTERRUPTED: AtomicBool = AtomicBool::new(false);
+
+/// Whether SIGTSTP has been received while waiting for a command.
+static STOP_REQUESTED: AtomicBool = AtomicBool::new(false);
+
+extern "C" fn on_interrupt(_: libc::c_int) {
+    INTERRUPTED.store(true, Ordering::Relaxed);
+}
+
+extern "C" fn on_stop(_: libc::c_int) {
+    STOP_REQUESTED.store(true, Ordering::Relaxed);
+}
+
+/// Sets up the signals which the shell handles itself, e.g. again after
+/// `exec` has failed to run a command.
+pub fn install() {
+    catch_interrupts();
+    catch_stops();
+    ignore_terminal_stops();
+}
+
+/// Makes SIGINT set a flag instead of killing the shell, so that e.g. a loop
+/// stops when Ctrl-C interrupts the command in it. Child processes get the
+/// default action back.
+fn catch_interrupts() {
+    // SAFETY: the handler only stores to an atomic, which is
+    // async-signal-safe.
+    unsafe {
+        let mut action: libc::sigaction = std::mem::zeroed();
+        action.sa_sigaction = on_interrupt as extern "C" fn(libc::c_int) as libc::sighandler_t;
+        action.sa_flags = libc::SA_RESTART;
+        libc::sigemptyset(&mut action.sa_mask);
+        libc::sigaction(libc::SIGINT, &action, std::ptr::null_mut());
+    }
+}
+
+/// Makes SIGTSTP interrupt waiting for the commands run in the foreground
+/// instead of stopping the shell, so that the shell stops them itself.
+fn catch_stops() {
+    // SAFETY: the handler only stores to an atomic, which is
+    // async-signal-safe.
+    unsafe {
+        let mut action: libc::sigaction = std::mem::zeroed();
+        action.sa_sigaction = on_stop as extern "C" fn(libc::c_int) as libc::sighandler_t;
+        libc::sigemptyset(&mut action.sa_mask);
+        libc::sigaction(libc::SIGTSTP, &action, std::ptr::null_mut());
+    }
+}
+
+/// Returns whether SIGTSTP has been received and not taken yet.
+pub fn stop_requested() -> bool {
+    STOP_REQUESTED.load(Ordering::Relaxed)
+}
+
+/// Returns whether SIGTSTP has been received, and forgets about it.
+pub fn take_stop_request() -> bool {
+    STOP_REQUESTED.swap(false, Ordering::Relaxed)
+}
+
+/// Keeps the shell from being stopped when it takes the terminal back from
+/// a job or, after that, reads from or writes to it.
+fn ignore_terminal_stops() {
+    // SAFETY: ignoring a signal has no preconditions.
+    unsafe {
+        libc::signal(libc::SIGTTOU, libc::SIG_IGN);
+        libc::signal(libc::SIGTTIN, libc::SIG_IGN);
+    }
+}
+
+/// Gives a child process, or a command which `exec` replaces the shell with,
+/// the default action of the signals which the shell handles, so that the
+/// commands it runs can be interrupted and stopped.
+pub fn restore_defaults() {
+    // SAFETY: restoring the default action has no preconditions.
+    unsafe {
+        libc::signal(libc::SIGINT, libc::SIG_DFL);
+        // The Rust runtime ignores SIGPIPE, but e.g. `yes | head` relies on
+        // it to end `yes`.
+        libc::signal(libc::SIGPIPE, libc::SIG_DFL);
+        libc::signal(libc::SIGTSTP, libc::SIG_DFL);
+        libc::signal(libc::SIGTTOU, libc::SIG_DFL);
+        libc::signal(libc::SIGTTIN, libc::SIG_DFL);
+    }
+}
+
+/// Describes the signal `number` as `strsignal` does, e.g. `Terminated` for
+/// SIGTERM.
+pub fn description(number: libc::c_int) -> String {
+    // SAFETY: `strsignal` returns a string which stays valid until it is
+    // called again, and it is copied right away.
+    unsafe {
+        let description = libc::strsignal(number);
+        if description.is_null() {
+            return format!("Signal {}", number);
+        }
+        std::ffi::CStr::from_ptr(description)
+            .to_string_lossy()
+            .into_owned()
+    }
+}
+
+/// Records SIGINT as received, e.g. when it has killed a command run in the
+/// foreground.
+pub fn interrupt() {
+    INTERRUPTED.store(true, Ordering::Relaxed);
+}
+
+/// The names of the signals without `SIG` and their numbers.
+pub fn signals() -> impl Iterator<Item = (&'static str, libc::c_int)> {
+    SIGNALS.iter().copied()
+}
+
+/// Returns the number of the signal `spec`, which is a number or a name
+/// with or without `SIG` in any case, e.g. `15`, `TERM` or `sigterm`. 0 only
+/// checks whether a process can be sent a signal.
+pub fn number(spec: &str) -> Option<libc::c_int> {
+    if let Ok(number) = spec.parse() {
+        return (number == 0 || name(number).is_some()).then_some(number);
+    }
+    let name = spec.to_ascii_uppercase();
+    let name = name.strip_prefix("SIG").unwrap_or(&name);
+    signals()
+        .find(|(n, _)| *n == name)
+        .map(|(_, number)| number)
+}
+
+/// Returns the name of the signal `number` without `SIG`.
+pub fn name(number: libc::c_int) -> Option<&'static str> {
+    signals().find(|(_, n)| *n == number).map(|(name, _)| name)
+}
+
+/// Returns whether SIGINT has been received since the last `clear_interrupt`.
+pub fn interrupted() -> bool {
+    INTERRUPTED.load(Ordering::Relaxed)
+}
+
+/// Forgets the signals received while running the last command line.
+pub fn clear_interrupt() {
+    INTERRUPTED.store(false, Ordering::Relaxed);
+    STOP_REQUESTED.store(false, Ordering::Relaxed);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn number_of_spec() {
+        assert_eq!(number("TERM"), Some(libc::SIGTERM));
+        assert_eq!(number("SIGHUP"), Some(libc::SIGHUP));
+        assert_eq!(number("kill"), Some(libc::SIGKILL));
+        assert_eq!(number("9"), Some(libc::SIGKILL));
+        assert_eq!(number("0"), Some(0));
+        assert_eq!(number("999"), None);
+        assert_eq!(number("SIG"), None);
+        assert_eq!(number("FOO"), None);
+    }
+}
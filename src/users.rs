@@ -0,0 +1,71 @@
+//! Lookups in the user database, for `~user`.
+
+use std::{
+    ffi::{CStr, CString, OsStr},
+    os::unix::ffi::OsStrExt,
+    path::PathBuf,
+};
+
+/// Returns the names of all the users, sorted.
+pub fn names() -> Vec<String> {
+    let mut names = Vec::new();
+    // SAFETY: the entries returned by `getpwent` are only read before the
+    // next call, and nothing else in the shell enumerates the database.
+    unsafe {
+        libc::setpwent();
+        loop {
+            let passwd = libc::getpwent();
+            if passwd.is_null() {
+                break;
+            }
+            if let Ok(name) = CStr::from_ptr((*passwd).pw_name).to_str() {
+                names.push(name.to_owned());
+            }
+        }
+        libc::endpwent();
+    }
+    names.sort();
+    names.dedup();
+    names
+}
+
+/// Returns the home directory `~user` stands for, or `~` if `user` is
+/// empty: `$HOME`, or the current user's home directory if it is unset.
+pub fn home_dir(user: &str) -> Option<PathBuf> {
+    if user.is_empty() {
+        if let Some(home) = std::env::var_os("HOME") {
+            return Some(PathBuf::from(home));
+        }
+    }
+
+    let mut passwd: libc::passwd = unsafe { std::mem::zeroed() };
+    let mut result = std::ptr::null_mut();
+    let mut buf = vec![0 as libc::c_char; 4096];
+    // SAFETY: `passwd` and `buf` outlive the call, and the strings in
+    // `passwd` point into `buf`, which is still alive when they are read.
+    unsafe {
+        if user.is_empty() {
+            libc::getpwuid_r(
+                libc::getuid(),
+                &mut passwd,
+                buf.as_mut_ptr(),
+                buf.len(),
+                &mut result,
+            );
+        } else {
+            let name = CString::new(user).ok()?;
+            libc::getpwnam_r(
+                name.as_ptr(),
+                &mut passwd,
+                buf.as_mut_ptr(),
+                buf.len(),
+                &mut result,
+            );
+        }
+        if result.is_null() {
+            return None;
+        }
+        let dir = CStr::from_ptr(passwd.pw_dir);
+        Some(PathBuf::from(OsStr::from_bytes(dir.to_bytes())))
+    }
+}
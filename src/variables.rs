@@ -0,0 +1,83 @@
+//! Shell variables. Exported ones are kept in the environment of the shell
+//! itself so that commands inherit them.
+
+use std::collections::HashMap;
+
+#[derive(Debug, Default)]
+pub struct Variables {
+    /// The variables which are not exported.
+    values: HashMap<String, String>,
+}
+
+impl Variables {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn get(&self, name: &str) -> Option<String> {
+        match self.values.get(name) {
+            Some(value) => Some(value.clone()),
+            None => std::env::var(name).ok(),
+        }
+    }
+
+    /// The names and values of the variables, exported or not. Their order
+    /// is unspecified.
+    pub fn iter(&self) -> impl Iterator<Item = (String, String)> + '_ {
+        let exported = std::env::vars_os().filter_map(|(name, value)| {
+            Some((name.into_string().ok()?, value.into_string().ok()?))
+        });
+        self.values
+            .iter()
+            .map(|(name, value)| (name.clone(), value.clone()))
+            .chain(exported)
+    }
+
+    /// Sets the variable `name`, which stays exported if it is.
+    pub fn set(&mut self, name: &str, value: String) {
+        if std::env::var_os(name).is_some() {
+            std::env::set_var(name, value);
+        } else {
+            self.values.insert(name.to_owned(), value);
+        }
+    }
+
+    /// Exports `name` with `value` until [`Variables::restore`] is called
+    /// with what this returns, as for `NAME=value command`.
+    pub fn export_temporarily(&mut self, name: &str, value: String) -> Saved {
+        let saved = match self.values.remove(name) {
+            Some(value) => Saved {
+                name: name.to_owned(),
+                value: Some(value),
+                exported: false,
+            },
+            None => Saved {
+                name: name.to_owned(),
+                value: std::env::var(name).ok(),
+                exported: true,
+            },
+        };
+        std::env::set_var(name, value);
+        saved
+    }
+
+    /// Puts back a variable as it was before it was exported temporarily.
+    pub fn restore(&mut self, saved: Saved) {
+        match saved.value {
+            Some(value) if saved.exported => std::env::set_var(&saved.name, value),
+            Some(value) => {
+                std::env::remove_var(&saved.name);
+                self.values.insert(saved.name, value);
+            }
+            None => std::env::remove_var(&saved.name),
+        }
+    }
+}
+
+/// A variable as it was before [`Variables::export_temporarily`].
+#[derive(Debug)]
+pub struct Saved {
+    name: String,
+    value: Option<String>,
+    exported: bool,
+}